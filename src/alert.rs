@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use crate::{
+    base::{Duration, Interval, TimeStamp},
+    ops::element,
+    registry::ShardedRegistry,
+    sample::Sample,
+    AlignedSeries,
+};
+
+/// Comparison applied between a sample and a rule's threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl CmpOp {
+    /// Returns true if `value <op> threshold` holds.
+    pub fn matches(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Gt => value > threshold,
+            Self::Ge => value >= threshold,
+            Self::Lt => value < threshold,
+            Self::Le => value <= threshold,
+        }
+    }
+
+    /// Returns the more extreme of `a` and `b` in the direction of the
+    /// comparison, i.e. the larger for `Gt`/`Ge` and the smaller otherwise.
+    fn peak(&self, a: f64, b: f64) -> f64 {
+        match self {
+            Self::Gt | Self::Ge => a.max(b),
+            Self::Lt | Self::Le => a.min(b),
+        }
+    }
+}
+
+/// A threshold rule, e.g. "value > 90 for at least 5 minutes".
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub op: CmpOp,
+    pub threshold: f64,
+    pub for_duration: Duration,
+
+    /// If set, `Err` slots inside a run are skipped instead of ending it.
+    pub tolerate_gaps: bool,
+}
+
+/// A maximal run of slots satisfying a rule. `end_ts` is exclusive: it's the
+/// end of the last matching slot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertInterval {
+    pub start_ts: TimeStamp,
+    pub end_ts: TimeStamp,
+    pub peak: f64,
+}
+
+/// Find the maximal runs of consecutive slots in `series` that satisfy `rule`
+/// and span at least `rule.for_duration`. The span of a run is measured from
+/// the start of its first matching slot to the end of its last matching slot.
+pub fn evaluate(rule: &Rule, series: &AlignedSeries<f64>) -> Vec<AlertInterval> {
+    let mut alerts = vec![];
+    let mut run: Option<(usize, usize, f64)> = None;
+
+    let slot_ts =
+        |i: usize| TimeStamp(series.start_ts.millis() + (i as i64 * series.interval.millis()));
+
+    let mut close = |run: Option<(usize, usize, f64)>| {
+        if let Some((first, last, peak)) = run {
+            let start_ts = slot_ts(first);
+            let end_ts = slot_ts(last + 1);
+            if (end_ts - start_ts).millis() >= rule.for_duration.millis() {
+                alerts.push(AlertInterval {
+                    start_ts,
+                    end_ts,
+                    peak,
+                });
+            }
+        }
+    };
+
+    for (i, sample) in series.values.iter().enumerate() {
+        if let Sample::Err = sample {
+            if !rule.tolerate_gaps {
+                close(run.take());
+            }
+            continue;
+        }
+
+        let v = sample.val();
+        if rule.op.matches(v, rule.threshold) {
+            run = Some(match run {
+                Some((first, _, peak)) => (first, i, rule.op.peak(peak, v)),
+                None => (i, i, v),
+            });
+        } else {
+            close(run.take());
+        }
+    }
+    close(run);
+
+    alerts
+}
+
+/// Evaluate rules against metrics in a registry. Each metric's latest raw
+/// series is aligned with `op` before its rules are evaluated.
+pub fn evaluate_all(
+    registry: &ShardedRegistry<f64>,
+    rules_by_metric: &HashMap<String, Vec<Rule>>,
+    interval: Interval,
+    start_ts: TimeStamp,
+    end_ts: Option<TimeStamp>,
+    op: element::Op<f64>,
+) -> anyhow::Result<HashMap<String, Vec<AlertInterval>>> {
+    let mut alerts = HashMap::new();
+
+    for (name, rules) in rules_by_metric.iter() {
+        let series = registry.aggregate(name, interval, start_ts, end_ts, op)?;
+        let fired = rules
+            .iter()
+            .flat_map(|rule| evaluate(rule, &series))
+            .collect::<Vec<_>>();
+        alerts.insert(name.clone(), fired);
+    }
+
+    Ok(alerts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::element::youngest;
+
+    fn series(samples: &[Sample<f64>]) -> AlignedSeries<f64> {
+        let mut series = AlignedSeries::new(Interval::from_secs(60), TimeStamp(0));
+        for s in samples {
+            series.push_sample(*s);
+        }
+        series
+    }
+
+    fn rule(tolerate_gaps: bool) -> Rule {
+        Rule {
+            op: CmpOp::Gt,
+            threshold: 90.0,
+            for_duration: Interval::from_minutes(5),
+            tolerate_gaps,
+        }
+    }
+
+    #[test]
+    fn duration_boundary() {
+        use Sample::*;
+
+        // Exactly five one-minute slots over the threshold.
+        let s = series(&[
+            Point(50.0),
+            Point(91.0),
+            Point(95.0),
+            Point(92.0),
+            Point(99.0),
+            Point(91.0),
+            Point(10.0),
+        ]);
+        let alerts = evaluate(&rule(false), &s);
+        assert_eq!(
+            alerts,
+            vec![AlertInterval {
+                start_ts: TimeStamp::from(Interval::from_minutes(1).millis()),
+                end_ts: TimeStamp::from(Interval::from_minutes(6).millis()),
+                peak: 99.0,
+            }]
+        );
+
+        // Four slots is one short.
+        let s = series(&[
+            Point(91.0),
+            Point(95.0),
+            Point(92.0),
+            Point(99.0),
+            Point(10.0),
+        ]);
+        assert!(evaluate(&rule(false), &s).is_empty());
+    }
+
+    #[test]
+    fn gaps() {
+        use Sample::*;
+
+        let s = series(&[
+            Point(91.0),
+            Point(95.0),
+            Err,
+            Point(92.0),
+            Fake(99.0),
+            Point(91.0),
+        ]);
+
+        assert!(evaluate(&rule(false), &s).is_empty());
+
+        let alerts = evaluate(&rule(true), &s);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].start_ts, TimeStamp(0));
+        assert_eq!(
+            alerts[0].end_ts,
+            TimeStamp::from(Interval::from_minutes(6).millis())
+        );
+        assert_eq!(alerts[0].peak, 99.0);
+
+        // A non-matching sample still ends a run when gaps are tolerated.
+        let s = series(&[Point(91.0), Point(95.0), Err, Point(10.0), Point(92.0)]);
+        assert!(evaluate(&rule(true), &s).is_empty());
+    }
+
+    #[test]
+    fn no_alerts_and_registry() {
+        let rule = Rule {
+            op: CmpOp::Lt,
+            threshold: 1.0,
+            for_duration: Interval::from_secs(30),
+            tolerate_gaps: false,
+        };
+
+        let registry = ShardedRegistry::<f64>::new(2);
+        for i in 0..10 {
+            registry.push("healthy", TimeStamp(i * 10_000), 5.0);
+            registry.push("idle", TimeStamp(i * 10_000), if i < 5 { 5.0 } else { 0.5 });
+        }
+
+        let mut rules = HashMap::new();
+        rules.insert("healthy".to_string(), vec![rule.clone()]);
+        rules.insert("idle".to_string(), vec![rule]);
+
+        let alerts = evaluate_all(
+            &registry,
+            &rules,
+            Interval::from_secs(10),
+            TimeStamp(0),
+            None,
+            youngest,
+        )
+        .unwrap();
+
+        assert!(alerts["healthy"].is_empty());
+        assert_eq!(
+            alerts["idle"],
+            vec![AlertInterval {
+                start_ts: TimeStamp(50_000),
+                end_ts: TimeStamp(100_000),
+                peak: 0.5,
+            }]
+        );
+    }
+}
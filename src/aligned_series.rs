@@ -1,21 +1,276 @@
 use anyhow::Result;
-use std::fmt;
+use std::{
+    fmt,
+    ops::{Add, Div, Mul, Range, Sub},
+};
 
 use crate::{
-    base::{Interval, TimeStamp},
-    element::Element,
+    base::{Duration, Interval, TimeStamp},
+    element::{ChangeEvent, Element},
     ops::{element, sample},
     raw_series::RawSeries,
-    sample::{Sample, SampleValue},
+    sample::{Sample, SampleValue, SampleValueOp},
+    unit::{Unit, UnitError},
+    window::Window,
 };
 
+/// `MissingPolicy` decides how windowed operations treat `Err` samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingPolicy {
+    /// Run the op over the valid samples only. Windows with fewer than
+    /// `min_valid` valid samples yield `Err`.
+    Skip { min_valid: usize },
+
+    /// Any `Err` sample in a window makes the result `Err`.
+    Propagate,
+
+    /// Pass `Err` samples through to the op, which sees them as zero.
+    Zero,
+}
+
+impl MissingPolicy {
+    /// Run `op` over `window` according to the policy.
+    pub fn apply<T: SampleValue>(
+        &self,
+        window: &[Sample<T>],
+        mut op: impl FnMut(&[Sample<T>]) -> Sample<T>,
+    ) -> Sample<T> {
+        match self {
+            Self::Zero => op(window),
+            Self::Propagate => {
+                if window.iter().any(|s| s.is_err()) {
+                    Sample::Err
+                } else {
+                    op(window)
+                }
+            }
+            Self::Skip { min_valid } => {
+                let valid = window
+                    .iter()
+                    .filter(|s| !s.is_err())
+                    .copied()
+                    .collect::<Vec<_>>();
+
+                if valid.is_empty() || valid.len() < *min_valid {
+                    Sample::Err
+                } else {
+                    op(&valid)
+                }
+            }
+        }
+    }
+}
+
+/// `FillPolicy` decides how missing slots are filled: slots whose window
+/// holds no raw samples in `AlignedSeries::from_raw_series_with_fill`, and
+/// `Err` samples in `AlignedSeries::fill_missing`. Filled values are `Fake`,
+/// so they can be told apart from real data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillPolicy<T: SampleValue> {
+    /// Leave the slot `Err`.
+    Err,
+
+    /// Fill with `Fake(zero)`.
+    Zero,
+
+    /// Fill with the given value.
+    Constant(T),
+
+    /// Carry the last valid value forward. Slots before the first valid value
+    /// stay `Err`.
+    PreviousValue,
+
+    /// Carry the next valid value backward. Slots after the last valid value
+    /// stay `Err`.
+    NextValue,
+
+    /// Interpolate linearly between the valid values on either side of the
+    /// gap, truncating for integer types. Gaps at either end stay `Err`.
+    LinearInterpolate,
+}
+
+impl<T: SampleValue> FillPolicy<T> {
+    /// Resolve `slots`, where `None` marks an empty window.
+    fn fill(&self, slots: &[Option<Sample<T>>]) -> Vec<Sample<T>> {
+        let valid = |slot: &Option<Sample<T>>| slot.filter(|s| !s.is_err()).map(|s| s.val());
+
+        // The next valid value at or after each slot.
+        let mut next: Vec<Option<(usize, T)>> = vec![None; slots.len()];
+        if matches!(self, Self::NextValue | Self::LinearInterpolate) {
+            let mut following = None;
+            for (i, slot) in slots.iter().enumerate().rev() {
+                if let Some(v) = valid(slot) {
+                    following = Some((i, v));
+                }
+                next[i] = following;
+            }
+        }
+
+        let mut prev: Option<(usize, T)> = None;
+        let mut filled = Vec::with_capacity(slots.len());
+        for (i, slot) in slots.iter().enumerate() {
+            if let Some(sample) = slot {
+                if let Some(v) = valid(slot) {
+                    prev = Some((i, v));
+                }
+                filled.push(*sample);
+                continue;
+            }
+
+            let sample = match (self, prev, next[i]) {
+                (Self::Zero, _, _) => Sample::Fake(T::zero()),
+                (Self::Constant(v), _, _) => Sample::Fake(*v),
+                (Self::PreviousValue, Some((_, v)), _) => Sample::Fake(v),
+                (Self::NextValue, _, Some((_, v))) => Sample::Fake(v),
+                (Self::LinearInterpolate, Some((i0, v0)), Some((i1, v1))) => {
+                    let interpolated = match (v0.to_f64(), v1.to_f64()) {
+                        (Some(v0), Some(v1)) => {
+                            T::from(v0 + (v1 - v0) * (i - i0) as f64 / (i1 - i0) as f64)
+                        }
+                        _ => None,
+                    };
+                    interpolated.map_or(Sample::Err, Sample::Fake)
+                }
+                _ => Sample::Err,
+            };
+            filled.push(sample);
+        }
+
+        filled
+    }
+}
+
+/// `PartialChunk` decides what `AlignedSeries::resample` does with new slots
+/// only partly covered by the series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartialChunk {
+    /// Leave the slot out.
+    Drop,
+
+    /// Aggregate the samples that are there.
+    Aggregate,
+}
+
+/// `UpsampleMethod` decides how `AlignedSeries::upsample` fills the new
+/// slots between the original samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsampleMethod {
+    /// Repeat each sample until the next one.
+    Repeat,
+
+    /// Interpolate linearly towards the next sample, truncating for integer
+    /// types. Slots next to an `Err` are `Err`.
+    Linear,
+
+    /// Divide each value evenly over its slots, so sums are preserved, for
+    /// counters. Integer remainders stay in the original slot.
+    Spread,
+}
+
+/// Errors returned by `AlignedSeries::resample` and `upsample`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResampleError {
+    /// The new interval isn't a positive multiple of the series interval.
+    NotMultiple {
+        interval: Interval,
+        new_interval: Interval,
+    },
+
+    /// The series interval isn't a multiple of the new, positive interval.
+    NotDivisor {
+        interval: Interval,
+        new_interval: Interval,
+    },
+
+    /// The series' slots straddle the slot boundaries of the new interval:
+    /// `start_ts` isn't a whole number of `interval`s past a multiple of
+    /// `new_interval`.
+    Misaligned {
+        start_ts: TimeStamp,
+        interval: Interval,
+        new_interval: Interval,
+    },
+}
+
+impl fmt::Display for ResampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotMultiple {
+                interval,
+                new_interval,
+            } => write!(
+                f,
+                "new interval {} is not a positive multiple of {}",
+                new_interval, interval
+            ),
+            Self::NotDivisor {
+                interval,
+                new_interval,
+            } => write!(
+                f,
+                "interval {} is not a multiple of new interval {}",
+                interval, new_interval
+            ),
+            Self::Misaligned {
+                start_ts,
+                interval,
+                new_interval,
+            } => write!(
+                f,
+                "start {} is not a whole number of {} slots past a multiple of {}",
+                start_ts.0, interval, new_interval
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResampleError {}
+
+/// A `Sink` consumes aligned elements as they are produced, see
+/// `AlignedSeries::from_raw_series_into`.
+pub trait Sink<T: SampleValue> {
+    fn send(&mut self, element: Element<T>);
+
+    /// Called with the number of elements about to be sent.
+    fn reserve(&mut self, _additional: usize) {}
+}
+
+impl<T: SampleValue> Sink<T> for Vec<Element<T>> {
+    fn send(&mut self, element: Element<T>) {
+        self.push(element);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional);
+    }
+}
+
+impl<T: SampleValue> Sink<T> for AlignedSeries<T> {
+    fn send(&mut self, element: Element<T>) {
+        self.push_sample(element.1);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        AlignedSeries::reserve(self, additional);
+    }
+}
+
 /// `AlignedSeries` represents Time Series with a fixed interval between
 /// samples.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
 pub struct AlignedSeries<T: SampleValue> {
     pub start_ts: TimeStamp,
     pub interval: Interval,
     pub values: Vec<Sample<T>>,
+
+    /// Unit of the values, if known. Slicing, shifting, scaling and
+    /// offsetting keep it, and the `zip_*` arithmetic checks and combines it.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub unit: Option<Unit>,
 }
 
 impl<T: SampleValue> AlignedSeries<T> {
@@ -25,11 +280,35 @@ impl<T: SampleValue> AlignedSeries<T> {
             interval,
             start_ts,
             values: vec![],
+            unit: None,
+        }
+    }
+
+    /// Create a new, empty series with room for `capacity` samples.
+    pub fn with_capacity(interval: Interval, start_ts: TimeStamp, capacity: usize) -> Self {
+        Self {
+            interval,
+            start_ts,
+            values: Vec::with_capacity(capacity),
+            unit: None,
         }
     }
 
+    /// Set the unit of the values.
+    pub fn with_unit(mut self, unit: Unit) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    /// Reserve room for at least `additional` more samples.
+    pub fn reserve(&mut self, additional: usize) {
+        self.values.reserve(additional);
+    }
+
     /// Create a new aligned series from a raw series. The raw series is
-    /// aggregated into windows of the given interval.
+    /// aggregated into windows of the given interval. Windows without samples
+    /// are passed to `op` as an empty slice. If `end_ts` is given, there is a slot for every
+    /// window starting before it, even past the last raw sample.
     pub fn from_raw_series(
         series: &RawSeries<T>,
         interval: Interval,
@@ -38,6 +317,22 @@ impl<T: SampleValue> AlignedSeries<T> {
         op: element::Op<T>,
     ) -> anyhow::Result<Self> {
         let mut aligned_series = Self::new(interval, start_ts);
+        Self::from_raw_series_into(series, interval, start_ts, end_ts, op, &mut aligned_series)?;
+
+        Ok(aligned_series)
+    }
+
+    /// Aggregate a raw series into windows of the given interval, feeding each
+    /// aligned element to `sink` as it is produced instead of materializing
+    /// the whole series. Returns the number of elements sent.
+    pub fn from_raw_series_into(
+        series: &RawSeries<T>,
+        interval: Interval,
+        start_ts: TimeStamp,
+        end_ts: Option<TimeStamp>,
+        op: element::Op<T>,
+        sink: &mut impl Sink<T>,
+    ) -> anyhow::Result<usize> {
         let mut window_iter = series.windows(interval, start_ts);
 
         if let Some(end_ts) = end_ts {
@@ -47,11 +342,163 @@ impl<T: SampleValue> AlignedSeries<T> {
 
             window_iter.set_end_ts(end_ts);
         }
+        sink.reserve(window_iter.num_windows());
 
-        aligned_series
-            .values
-            .extend(window_iter.samples().aggregate(op));
+        let mut count = 0;
+        for sample in window_iter.samples().aggregate(op) {
+            let ts = TimeStamp(start_ts.millis() + (count as i64 * interval.millis()));
+            sink.send((ts, sample).into());
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Like `from_raw_series`, but windows any chronological stream of
+    /// elements, such as `merged_iter` over several raw series, without
+    /// collecting it first. Elements before `start_ts`, or from `end_ts` on,
+    /// are skipped.
+    pub fn from_elements<'a>(
+        elements: impl IntoIterator<Item = &'a Element<T>>,
+        interval: Interval,
+        start_ts: TimeStamp,
+        end_ts: Option<TimeStamp>,
+        op: element::Op<T>,
+    ) -> anyhow::Result<Self>
+    where
+        T: 'a,
+    {
+        if interval.millis() <= 0 {
+            anyhow::bail!("interval must be positive");
+        }
+        if end_ts.is_some_and(|end_ts| end_ts < start_ts) {
+            anyhow::bail!("end_ts must be greater than or equal to start_ts");
+        }
+
+        let mut aligned_series = Self::new(interval, start_ts);
+        aligned_series.window_elements(elements, end_ts, true, op);
+        Ok(aligned_series)
+    }
+
+    /// Extend the series with the windows of `raw` past its last slot,
+    /// aggregated by `op` as in `from_raw_series`. Only closed windows, those
+    /// followed by a later raw sample, are appended; the window holding the
+    /// last raw sample is left until it closes. Returns the number of slots
+    /// appended.
+    pub fn extend_from_raw(
+        &mut self,
+        raw: &RawSeries<T>,
+        op: element::Op<T>,
+    ) -> anyhow::Result<usize> {
+        let next_start = self.slot_ts(self.len());
+        let first = raw.values.partition_point(|e| e.0 < next_start);
+        self.extend_from_elements(&raw.values[first..], op)
+    }
+
+    /// Like `extend_from_raw`, over any chronological stream of elements.
+    pub fn extend_from_elements<'a>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'a Element<T>>,
+        op: element::Op<T>,
+    ) -> anyhow::Result<usize>
+    where
+        T: 'a,
+    {
+        if self.interval.millis() <= 0 {
+            anyhow::bail!("interval must be positive");
+        }
+
+        Ok(self.window_elements(elements, None, false, op))
+    }
+
+    /// Append the windows of `elements` from the next slot on, up to
+    /// `end_ts` if given. Without it, the window holding the last element is
+    /// appended only if `close_last` is set. Empty windows are `Err`.
+    /// Returns the number of slots appended.
+    fn window_elements<'a>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'a Element<T>>,
+        end_ts: Option<TimeStamp>,
+        close_last: bool,
+        op: element::Op<T>,
+    ) -> usize
+    where
+        T: 'a,
+    {
+        let step = self.interval.millis();
+        let start = self.slot_ts(self.len()).millis();
+        let limit = end_ts.map(|end_ts| ((end_ts.millis() - start).max(0) + step - 1) / step);
+
+        let mut window: Vec<Element<T>> = vec![];
+        let mut appended = 0;
+        let mut seen = false;
+        let mut close = |series: &mut Self, window: &mut Vec<Element<T>>| {
+            series.push_sample(op(window));
+            window.clear();
+            appended += 1;
+        };
+
+        let mut current = 0;
+        for element in elements {
+            let offset = element.0.millis() - start;
+            if offset < 0 {
+                continue;
+            }
+
+            let index = offset / step;
+            if limit.is_some_and(|limit| index >= limit) {
+                break;
+            }
+            for _ in current..index {
+                close(self, &mut window);
+            }
+            current = current.max(index);
+            window.push(element.clone());
+            seen = true;
+        }
+
+        let last = match limit {
+            Some(limit) => limit,
+            None if close_last && seen => current + 1,
+            None => current,
+        };
+        for _ in current..last {
+            close(self, &mut window);
+        }
+
+        appended
+    }
+
+    /// Like `from_raw_series`, but slots whose window holds no raw samples
+    /// are filled according to `fill` instead of by `op`. If `end_ts` is
+    /// given, the series extends up to it even past the last raw sample, so
+    /// trailing gaps are filled too.
+    pub fn from_raw_series_with_fill(
+        series: &RawSeries<T>,
+        interval: Interval,
+        start_ts: TimeStamp,
+        end_ts: Option<TimeStamp>,
+        op: element::Op<T>,
+        fill: FillPolicy<T>,
+    ) -> anyhow::Result<Self> {
+        let mut window_iter = series.windows(interval, start_ts);
+        if let Some(end_ts) = end_ts {
+            if end_ts < start_ts {
+                anyhow::bail!("end_ts must be greater than or equal to start_ts");
+            }
 
+            window_iter.set_end_ts(end_ts);
+        }
+
+        let slots = window_iter
+            .map(|window| match window {
+                Window::Empty => None,
+                Window::Range(start, end) => Some(op(&series.values[start..=end])),
+            })
+            .collect::<Vec<_>>();
+
+        let mut aligned_series = Self::with_capacity(interval, start_ts, slots.len());
+        aligned_series.values.extend(fill.fill(&slots));
         Ok(aligned_series)
     }
 
@@ -76,12 +523,34 @@ impl<T: SampleValue> AlignedSeries<T> {
     }
 
     /// Returns a new AlignedSeries constructed from running the given `op` over
-    /// a sliding window of length `len`.
-    pub fn sliding_aggregate(&self, len: usize, op: sample::Op<T>) -> Result<Self> {
-        let mut new_series = Self::new(self.interval, self.start_ts);
+    /// a sliding window of length `len`. The result has the same `start_ts`
+    /// and length as `self`: slot `i` holds `op` over the trailing window
+    /// ending at slot `i`, and the first `len - 1` slots, which have no full
+    /// window, are `Err`. `len` must be positive.
+    pub fn sliding_aggregate(
+        &self,
+        len: usize,
+        op: impl FnMut(&[Sample<T>]) -> Sample<T>,
+    ) -> Result<Self> {
+        self.sliding_aggregate_with(len, op, MissingPolicy::Zero)
+    }
+
+    /// Like `sliding_aggregate`, but `policy` decides how `Err` samples inside
+    /// a window are handled.
+    pub fn sliding_aggregate_with(
+        &self,
+        len: usize,
+        mut op: impl FnMut(&[Sample<T>]) -> Sample<T>,
+        policy: MissingPolicy,
+    ) -> Result<Self> {
+        if len == 0 {
+            anyhow::bail!("window length must be positive");
+        }
+
+        let mut new_series = Self::with_capacity(self.interval, self.start_ts, self.values.len());
 
-        for _ in 0..len - 1 {
-            new_series.push_sample(Sample::point(T::zero()));
+        for _ in 0..(len - 1).min(self.values.len()) {
+            new_series.push_sample(Sample::Err);
         }
 
         if len > self.values.len() {
@@ -90,117 +559,2370 @@ impl<T: SampleValue> AlignedSeries<T> {
 
         self.values
             .windows(len)
-            .map(op)
+            .map(|window| policy.apply(window, &mut op))
             .for_each(|s| new_series.push_sample(s));
 
         Ok(new_series)
     }
 
-    /// Get the nearest sample after or equal to the given timestamp.
-    pub fn at_or_after(&self, ts: TimeStamp) -> Option<Element<T>> {
-        if ts <= self.start_ts {
-            if self.is_empty() {
-                return None;
-            } else {
-                return Some((self.start_ts, self.values[0]).into());
+    /// Like `sliding_aggregate`, but leaves out the first `len - 1` slots,
+    /// which have no full window, instead of padding them. The result starts
+    /// `len - 1` intervals after `start_ts`, so each sample keeps the
+    /// timestamp of the last slot in its window.
+    pub fn sliding_aggregate_valid(
+        &self,
+        len: usize,
+        op: impl FnMut(&[Sample<T>]) -> Sample<T>,
+    ) -> Result<Self> {
+        let mut new_series = self.sliding_aggregate(len, op)?;
+        let skip = (len - 1).min(new_series.len());
+        new_series.values.drain(..skip);
+        new_series.start_ts = self.slot_ts(len - 1);
+
+        Ok(new_series)
+    }
+
+    /// Deprecated name of `sliding_aggregate`.
+    #[deprecated(note = "use sliding_aggregate")]
+    pub fn sliding_window(
+        &self,
+        len: usize,
+        op: impl FnMut(&[Sample<T>]) -> Sample<T>,
+    ) -> Result<Self> {
+        self.sliding_aggregate(len, op)
+    }
+
+    /// Rolling sum over a trailing window of `len` samples.
+    pub fn rolling_sum(&self, len: usize, policy: MissingPolicy) -> Result<Self> {
+        self.sliding_aggregate_with(len, sample::sum, policy)
+    }
+
+    /// Rolling minimum over a trailing window of `len` samples.
+    pub fn rolling_min(&self, len: usize, policy: MissingPolicy) -> Result<Self> {
+        self.sliding_aggregate_with(len, sample::min, policy)
+    }
+
+    /// Rolling maximum over a trailing window of `len` samples.
+    pub fn rolling_max(&self, len: usize, policy: MissingPolicy) -> Result<Self> {
+        self.sliding_aggregate_with(len, sample::max, policy)
+    }
+
+    /// Returns a new series at the coarser `new_interval`, aggregating the
+    /// samples in each slot of the new interval with `op`. The new slots are
+    /// aligned to multiples of `new_interval`, so the first slot covers
+    /// fewer samples if `start_ts` isn't aligned, as does the last if the
+    /// series ends mid-slot; `partial` decides whether those are kept.
+    /// `new_interval` must be a positive multiple of `interval`, and
+    /// `start_ts` a whole number of slots past a multiple of `new_interval`,
+    /// so that no slot straddles two new slots.
+    pub fn resample(
+        &self,
+        new_interval: Interval,
+        op: sample::Op<T>,
+        partial: PartialChunk,
+    ) -> Result<Self, ResampleError> {
+        if new_interval.millis() <= 0 || new_interval.millis() % self.interval.millis() != 0 {
+            return Err(ResampleError::NotMultiple {
+                interval: self.interval,
+                new_interval,
+            });
+        }
+
+        let n = (new_interval.millis() / self.interval.millis()) as usize;
+        let offset = self.start_ts.millis().rem_euclid(new_interval.millis());
+        if offset % self.interval.millis() != 0 {
+            return Err(ResampleError::Misaligned {
+                start_ts: self.start_ts,
+                interval: self.interval,
+                new_interval,
+            });
+        }
+        let mut start_ts = TimeStamp(self.start_ts.millis() - offset);
+        // Slots of the first chunk before `self.start_ts`.
+        let lead = (offset / self.interval.millis()) as usize;
+
+        let (first, rest) = self.values.split_at((n - lead).min(self.len()));
+        let mut chunks = std::iter::once(first)
+            .filter(|chunk| !chunk.is_empty())
+            .chain(rest.chunks(n));
+
+        if partial == PartialChunk::Drop && lead > 0 && chunks.next().is_some() {
+            start_ts = TimeStamp(start_ts.millis() + new_interval.millis());
+        }
+
+        let mut new_series = Self::with_capacity(new_interval, start_ts, self.len().div_ceil(n));
+        for chunk in chunks {
+            if chunk.len() < n && partial == PartialChunk::Drop {
+                break;
             }
+            new_series.push_sample(op(chunk));
         }
 
-        if (ts - self.start_ts).millis() % self.interval.millis() == 0 {
-            let index = ((ts - self.start_ts).millis() / self.interval.millis()) as usize;
-            if index < self.values.len() {
-                return Some((ts, self.values[index]).into());
+        Ok(new_series)
+    }
+
+    /// Replace `Err` samples, and `Zero` samples if `fill_zero` is set, with
+    /// `Fake` copies of the closest preceding valid sample. At most
+    /// `max_slots` consecutive holes are filled after a valid sample
+    /// (unbounded if `None`); the rest of a longer run, and any leading holes,
+    /// are left as they were. Returns the number of samples filled.
+    pub fn fill_forward(&mut self, max_slots: Option<usize>, fill_zero: bool) -> usize {
+        Self::fill_from_neighbor(self.values.iter_mut(), max_slots, fill_zero)
+    }
+
+    /// Like `fill_forward`, but fills holes from the closest following valid
+    /// sample. Trailing holes are left as they were.
+    pub fn fill_backward(&mut self, max_slots: Option<usize>, fill_zero: bool) -> usize {
+        Self::fill_from_neighbor(self.values.iter_mut().rev(), max_slots, fill_zero)
+    }
+
+    fn is_hole(sample: &Sample<T>, fill_zero: bool) -> bool {
+        sample.is_err() || (fill_zero && sample.is_zero())
+    }
+
+    fn fill_from_neighbor<'a>(
+        samples: impl Iterator<Item = &'a mut Sample<T>>,
+        max_slots: Option<usize>,
+        fill_zero: bool,
+    ) -> usize
+    where
+        T: 'a,
+    {
+        let mut last: Option<T> = None;
+        let mut run = 0;
+        let mut filled = 0;
+
+        for sample in samples {
+            if !Self::is_hole(sample, fill_zero) {
+                last = Some(sample.val());
+                run = 0;
+                continue;
             }
-        } else {
-            let index = ((ts - self.start_ts).millis() / self.interval.millis()) as usize + 1;
-            if index < self.values.len() {
-                return Some(
-                    (
-                        self.start_ts.millis() + (index as i64 * self.interval.millis()),
-                        self.values[index],
-                    )
-                        .into(),
-                );
+
+            run += 1;
+            if let Some(v) = last {
+                if max_slots.is_none_or(|max| run <= max) {
+                    *sample = Sample::Fake(v);
+                    filled += 1;
+                }
             }
         }
 
-        None
+        filled
     }
-}
 
-impl<T> fmt::Display for AlignedSeries<T>
-where
-    T: SampleValue + fmt::Display,
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (i, sample) in self.values.iter().enumerate() {
-            write!(
-                f,
-                "\n {} {}",
-                TimeStamp(self.start_ts.millis() + (i as i64 * self.interval.millis())),
-                sample
-            )?;
+    /// Replace every `Err` sample, and every `Zero` sample if `fill_zero` is
+    /// set, with `Fake(value)`. Returns the number of samples filled.
+    pub fn fill_value(&mut self, value: T, fill_zero: bool) -> usize {
+        let mut filled = 0;
+        for sample in self
+            .values
+            .iter_mut()
+            .filter(|s| Self::is_hole(s, fill_zero))
+        {
+            *sample = Sample::Fake(value);
+            filled += 1;
         }
-        Ok(())
+        filled
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{ops::element::sum, sample::SampleEquals};
+    /// Replace `Err` samples with `Fake` values according to `policy`.
+    /// `LinearInterpolate` only bridges runs between two `Point`s, see
+    /// `fill_linear`. Returns the number of samples filled.
+    pub fn fill_missing(&mut self, policy: FillPolicy<T>) -> usize {
+        match policy {
+            FillPolicy::Err => 0,
+            FillPolicy::Zero => self.fill_value(T::zero(), false),
+            FillPolicy::Constant(value) => self.fill_value(value, false),
+            FillPolicy::PreviousValue => self.fill_forward(None, false),
+            FillPolicy::NextValue => self.fill_backward(None, false),
+            FillPolicy::LinearInterpolate => self.fill_linear(),
+        }
+    }
 
-    #[test]
-    fn aligned_series() {
-        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
-        series.push(0);
-        series.push(1);
-        series.push(2);
-        series.push(3);
-        series.push(4);
-        series.push(5);
-        series.push(6);
-        series.push(7);
-        series.push(8);
-        series.push(9);
+    /// Replace interior runs of `Err` samples with `Fake` values linearly
+    /// interpolated between the `Point`s on either side, truncating for
+    /// integer types. Runs at either end, or next to a `Zero` or `Fake`,
+    /// stay `Err`. Returns the number of samples filled.
+    pub fn fill_linear(&mut self) -> usize {
+        let mut prev: Option<(usize, f64)> = None;
+        let mut filled = 0;
 
-        assert_eq!(series.at_or_after(TimeStamp(0)).unwrap().0, 1000.into());
-        assert!(series
-            .at_or_after(TimeStamp(0))
-            .unwrap()
-            .1
-            .equals(&Sample::point(0)));
+        for i in 0..self.values.len() {
+            let Sample::Point(v) = self.values[i] else {
+                if !self.values[i].is_err() {
+                    prev = None;
+                }
+                continue;
+            };
+            let Some(v1) = v.to_f64() else {
+                prev = None;
+                continue;
+            };
 
-        assert_eq!(series.at_or_after(TimeStamp(999)).unwrap().0, 1000.into());
-        assert!(series
-            .at_or_after(TimeStamp(999))
-            .unwrap()
-            .1
-            .equals(&Sample::point(0)));
+            if let Some((i0, v0)) = prev {
+                for j in i0 + 1..i {
+                    let value = T::from(v0 + (v1 - v0) * (j - i0) as f64 / (i - i0) as f64);
+                    if let Some(value) = value {
+                        self.values[j] = Sample::Fake(value);
+                        filled += 1;
+                    }
+                }
+            }
+            prev = Some((i, v1));
+        }
 
-        assert_eq!(series.at_or_after(TimeStamp(1000)).unwrap().0, 1000.into());
-        assert!(series
-            .at_or_after(TimeStamp(1000))
-            .unwrap()
-            .1
-            .equals(&Sample::point(0)));
+        filled
+    }
 
-        assert_eq!(series.at_or_after(TimeStamp(1010)).unwrap().0, 1100.into());
-        assert!(series
-            .at_or_after(TimeStamp(1010))
-            .unwrap()
-            .1
-            .equals(&Sample::point(1)));
+    /// Returns a copy of the series with interior `Err` runs filled as in
+    /// `fill_linear`.
+    pub fn filled_linear(&self) -> Self {
+        let mut series = self.clone();
+        series.fill_linear();
+        series
+    }
 
-        assert_eq!(series.at_or_after(TimeStamp(1100)).unwrap().0, 1100.into());
-        assert!(series
-            .at_or_after(TimeStamp(1100))
-            .unwrap()
-            .1
-            .equals(&Sample::point(1)));
+    /// Returns the value transitions in the series: an event for every slot
+    /// whose value differs by more than `eps` from the last value reported
+    /// (or the first valid value). `Err` slots are skipped, and so are `Fake`
+    /// slots if `skip_fake` is set. With an `eps` of zero, values are compared
+    /// exactly in `T`; a positive `eps` is checked against the difference in
+    /// `f64`.
+    pub fn changes(&self, eps: f64, skip_fake: bool) -> Vec<ChangeEvent<T>> {
+        crate::element::changes(self.iter(), eps, skip_fake)
+    }
 
-        assert_eq!(series.at_or_after(TimeStamp(1900)).unwrap().0, 1900.into());
-        assert!(series.at_or_after(TimeStamp(1910)).is_none());
+    /// Returns true if `other` has the same start, interval and length, and
+    /// every sample is within `eps` of its counterpart (see
+    /// `Sample::approx_eq`).
+    pub fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        self.start_ts == other.start_ts
+            && self.interval == other.interval
+            && self.len() == other.len()
+            && self
+                .values
+                .iter()
+                .zip(other.values.iter())
+                .all(|(a, b)| a.approx_eq(b, eps))
+    }
+
+    /// Returns a new series with the ratio of each sample to the sample `lag`
+    /// slots before it, i.e. `v[i] / v[i - lag]`. The first `lag` samples, and
+    /// any slot where either side is `Err` or the denominator is zero, are
+    /// `Err`. If either side is `Fake`, the result is `Fake`.
+    pub fn ratio_to(&self, lag: usize) -> AlignedSeries<f64> {
+        self.lagged_map(lag, |cur, prev| cur / prev)
+    }
+
+    /// Returns a new series with the percent change of each sample relative
+    /// to the sample `lag` slots before it, i.e. `(v[i] - v[i - lag]) /
+    /// v[i - lag] * 100`. Missing and zero denominators are handled as in
+    /// `ratio_to`.
+    pub fn pct_change(&self, lag: usize) -> AlignedSeries<f64> {
+        self.lagged_map(lag, |cur, prev| (cur - prev) / prev * 100.0)
+    }
+
+    /// Returns the exponential moving average of the series with smoothing
+    /// factor `alpha`, which must be in `(0, 1]`. The first `Point` seeds the
+    /// average, and each later `Point` or `Fake` value `v` moves it to
+    /// `alpha * v + (1 - alpha) * ema`, keeping the variant. `Err` slots emit
+    /// the previous average as `Fake`, and `Zero` resets the seed. Slots
+    /// before the seed are `Err`.
+    pub fn ema(&self, alpha: f64) -> Result<AlignedSeries<f64>> {
+        if !(alpha > 0.0 && alpha <= 1.0) {
+            anyhow::bail!("alpha must be in (0, 1], got {}", alpha);
+        }
+
+        let mut new_series = AlignedSeries::with_capacity(self.interval, self.start_ts, self.len());
+        let mut ema: Option<f64> = None;
+
+        for sample in self.values.iter() {
+            let next = match (sample, ema) {
+                (Sample::Zero, _) => {
+                    ema = None;
+                    Sample::Zero
+                }
+                (Sample::Err, Some(prev)) => Sample::Fake(prev),
+                (Sample::Err | Sample::Fake(_), None) => Sample::Err,
+                (Sample::Point(v) | Sample::Fake(v), prev) => match v.to_f64() {
+                    Some(v) => {
+                        let value = prev.map_or(v, |prev| alpha * v + (1.0 - alpha) * prev);
+                        ema = Some(value);
+                        sample.map(|_| value)
+                    }
+                    None => Sample::Err,
+                },
+            };
+            new_series.push_sample(next);
+        }
+
+        Ok(new_series)
+    }
+
+    fn lagged_map(&self, lag: usize, f: impl Fn(f64, f64) -> f64) -> AlignedSeries<f64> {
+        let mut new_series = AlignedSeries::new(self.interval, self.start_ts);
+
+        for (i, cur) in self.values.iter().enumerate() {
+            if i < lag {
+                new_series.push_sample(Sample::Err);
+                continue;
+            }
+
+            let prev = &self.values[i - lag];
+            if cur.is_err() || prev.is_err() {
+                new_series.push_sample(Sample::Err);
+                continue;
+            }
+
+            let (Some(cur_val), Some(prev_val)) = (cur.val().to_f64(), prev.val().to_f64()) else {
+                new_series.push_sample(Sample::Err);
+                continue;
+            };
+
+            if prev_val == 0.0 {
+                new_series.push_sample(Sample::Err);
+            } else if matches!(cur, Sample::Fake(_)) || matches!(prev, Sample::Fake(_)) {
+                new_series.push_sample(Sample::Fake(f(cur_val, prev_val)));
+            } else {
+                new_series.push_sample(Sample::Point(f(cur_val, prev_val)));
+            }
+        }
+
+        new_series
+    }
+
+    /// Returns a copy of the series moved in time by `offset`: `start_ts` is
+    /// shifted and the samples are kept.
+    pub fn shift(&self, offset: Duration) -> Self {
+        Self {
+            start_ts: TimeStamp(self.start_ts.millis() + offset.millis()),
+            interval: self.interval,
+            values: self.values.clone(),
+            unit: self.unit.clone(),
+        }
+    }
+
+    /// Returns a copy of the series with the samples moved `n` slots later
+    /// (earlier if `n` is negative), keeping `start_ts` and the length.
+    /// Samples moved past either end are dropped, and the vacated slots are
+    /// `Err`.
+    pub fn shift_samples(&self, n: i64) -> Self {
+        let len = self.len();
+        let moved = (n.unsigned_abs() as usize).min(len);
+        let mut values = Vec::with_capacity(len);
+
+        if n >= 0 {
+            values.extend(std::iter::repeat_n(Sample::Err, moved));
+            values.extend_from_slice(&self.values[..len - moved]);
+        } else {
+            values.extend_from_slice(&self.values[moved..]);
+            values.extend(std::iter::repeat_n(Sample::Err, moved));
+        }
+
+        Self {
+            start_ts: self.start_ts,
+            interval: self.interval,
+            values,
+            unit: self.unit.clone(),
+        }
+    }
+
+    /// Returns a new series with `f` applied to each pair of samples from
+    /// `self` and `other` in lockstep. Both series must have the same
+    /// `start_ts` and `interval`; the result is as long as the shorter one.
+    /// It keeps the unit only if both series have the same one.
+    pub fn zip_with(
+        &self,
+        other: &Self,
+        f: impl Fn(Sample<T>, Sample<T>) -> Sample<T>,
+    ) -> Result<Self> {
+        if self.start_ts != other.start_ts || self.interval != other.interval {
+            anyhow::bail!(
+                "series are not aligned: start {} interval {} vs start {} interval {}",
+                self.start_ts,
+                self.interval,
+                other.start_ts,
+                other.interval
+            );
+        }
+
+        let mut new_series =
+            Self::with_capacity(self.interval, self.start_ts, self.len().min(other.len()));
+        if self.unit == other.unit {
+            new_series.unit = self.unit.clone();
+        }
+        for (a, b) in self.values.iter().zip(other.values.iter()) {
+            new_series.push_sample(f(*a, *b));
+        }
+
+        Ok(new_series)
+    }
+
+    /// Returns the running total of the series. Each `Point` or `Fake`
+    /// sample becomes the sum of all values since the start or the last
+    /// `Zero`, keeping its variant. `Zero` samples reset the total and `Err`
+    /// samples add nothing; both stay as they are.
+    pub fn cumsum(&self) -> Self {
+        let mut total = T::zero();
+        let mut new_series = self.clone();
+
+        for sample in new_series.values.iter_mut() {
+            if sample.is_zero() {
+                total = T::zero();
+            }
+            *sample = sample.map(|v| {
+                total = total + v;
+                total
+            });
+        }
+
+        new_series
+    }
+
+    /// Multiply every `Point` and `Fake` value by `factor` in place. `Err`
+    /// and `Zero` samples are kept as they are. See also `&series * factor`.
+    pub fn scale(&mut self, factor: T) {
+        self.map_in_place(|v| v * factor);
+    }
+
+    /// Add `delta` to every `Point` and `Fake` value in place. `Err` and
+    /// `Zero` samples are kept as they are. See also `&series + delta`.
+    pub fn offset(&mut self, delta: T) {
+        self.map_in_place(|v| v + delta);
+    }
+
+    fn map_in_place(&mut self, f: impl Fn(T) -> T) {
+        for sample in self.values.iter_mut() {
+            *sample = sample.map(&f);
+        }
+    }
+
+    fn map_samples(&self, f: impl Fn(T) -> T) -> Self {
+        let mut new_series = self.clone();
+        new_series.map_in_place(f);
+        new_series
+    }
+
+    /// Returns an iterator over the samples paired with their slot
+    /// timestamps, `start_ts + i * interval`.
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (TimeStamp, Sample<T>)> + ExactSizeIterator + '_ {
+        self.iter_elements().map(|e| (e.0, e.1))
+    }
+
+    /// Returns an iterator over the slots as elements, as `iter` does.
+    pub fn iter_elements(&self) -> Elements<'_, T> {
+        Elements {
+            series: self,
+            inner: self.values.iter().enumerate(),
+        }
+    }
+
+    /// Returns a raw series with an element at each slot timestamp, keeping
+    /// the sample variants.
+    pub fn to_raw_series(&self) -> RawSeries<T> {
+        let mut series = RawSeries::with_capacity(self.len());
+        series.values.extend(self.iter_elements());
+        series
+    }
+
+    /// Returns an iterator over the samples, without timestamps.
+    pub fn iter_values(&self) -> std::slice::Iter<'_, Sample<T>> {
+        self.values.iter()
+    }
+
+    /// Returns the timestamp of the slot at `index`, or `None` past the end.
+    pub fn timestamp_at(&self, index: usize) -> Option<TimeStamp> {
+        (index < self.len()).then(|| self.slot_ts(index))
+    }
+
+    fn slot_ts(&self, index: usize) -> TimeStamp {
+        TimeStamp(self.start_ts.millis() + index as i64 * self.interval.millis())
+    }
+
+    /// Returns the timestamp of the last slot.
+    pub fn end_ts(&self) -> Option<TimeStamp> {
+        self.timestamp_at(self.len().checked_sub(1)?)
+    }
+
+    /// Returns the index of the slot containing `ts`. Slot `i` covers
+    /// `[start_ts + i * interval, start_ts + (i + 1) * interval)`, so
+    /// timestamps between slot boundaries round down. Returns `None` if `ts`
+    /// is before the first slot or after the end of the last.
+    pub fn index_for(&self, ts: TimeStamp) -> Option<usize> {
+        usize::try_from(self.slot_offset(ts, false))
+            .ok()
+            .filter(|&index| index < self.len())
+    }
+
+    /// Returns the offset from the first slot of the slot starting at or
+    /// before `ts`, or at or after it if `round_up` is set. The result may be
+    /// out of range, and is negative for timestamps before `start_ts`.
+    fn slot_offset(&self, ts: TimeStamp, round_up: bool) -> i64 {
+        let offset = (ts - self.start_ts).millis();
+        let interval = self.interval.millis();
+        if round_up {
+            -(-offset).div_euclid(interval)
+        } else {
+            offset.div_euclid(interval)
+        }
+    }
+
+    /// Returns a new series with the slots starting in `[start, end)`, or
+    /// `None` if there are none. The range is clamped to the series.
+    pub fn slice(&self, start: TimeStamp, end: TimeStamp) -> Option<Self> {
+        let range = self.slot_range(start, end);
+        if range.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            start_ts: self.slot_ts(range.start),
+            interval: self.interval,
+            values: self.values[range].to_vec(),
+            unit: self.unit.clone(),
+        })
+    }
+
+    /// Like `slice`, but borrows the samples.
+    pub fn slice_values(&self, start: TimeStamp, end: TimeStamp) -> &[Sample<T>] {
+        &self.values[self.slot_range(start, end)]
+    }
+
+    fn slot_range(&self, start: TimeStamp, end: TimeStamp) -> Range<usize> {
+        let index = |ts| self.slot_offset(ts, true).clamp(0, self.len() as i64) as usize;
+        let start = index(start);
+        start..index(end).max(start)
+    }
+
+    /// Returns the sample whose slot starts exactly at `ts`.
+    pub fn get_at(&self, ts: TimeStamp) -> Option<&Sample<T>> {
+        let index = self.index_for(ts)?;
+        (self.slot_ts(index) == ts).then(|| &self.values[index])
+    }
+
+    /// Get the sample of the last slot starting at or before `ts`, i.e. the
+    /// slot containing `ts`, or the last slot if `ts` is past the end.
+    pub fn at_or_before(&self, ts: TimeStamp) -> Option<Element<T>> {
+        let index = usize::try_from(self.slot_offset(ts, false)).ok()?;
+        let index = index.min(self.len().checked_sub(1)?);
+
+        Some((self.slot_ts(index), self.values[index]).into())
+    }
+
+    /// Get the sample of the first slot starting at or after `ts`. A `ts`
+    /// inside a slot, but after its start, gives the next slot, so
+    /// timestamps past the start of the last slot give `None`.
+    pub fn at_or_after(&self, ts: TimeStamp) -> Option<Element<T>> {
+        let index = self.slot_offset(ts, true).max(0) as usize;
+
+        Some((self.timestamp_at(index)?, self.values[index]).into())
+    }
+}
+
+impl<T: SampleValueOp<T>> AlignedSeries<T> {
+    /// Rolling mean over a trailing window of `len` samples, so slot `i`
+    /// averages slots `i + 1 - len..=i`. The first `len - 1` slots are `Err`,
+    /// see `sliding_aggregate`.
+    pub fn rolling_mean(&self, len: usize, policy: MissingPolicy) -> Result<Self> {
+        self.sliding_aggregate_with(len, sample::mean, policy)
+    }
+
+    /// Returns a new series at the finer `new_interval`, splitting each slot
+    /// into `interval / new_interval` slots filled according to `method`. The
+    /// original samples keep their variant (and, except for `Spread`, their
+    /// value) in the first of their slots; the generated ones are `Fake`,
+    /// or `Err` where the original is. `Linear` has nothing to interpolate
+    /// towards after the last sample, so the series ends there.
+    pub fn upsample(
+        &self,
+        new_interval: Interval,
+        method: UpsampleMethod,
+    ) -> Result<Self, ResampleError> {
+        if new_interval.millis() <= 0 || self.interval.millis() % new_interval.millis() != 0 {
+            return Err(ResampleError::NotDivisor {
+                interval: self.interval,
+                new_interval,
+            });
+        }
+
+        let n = (self.interval.millis() / new_interval.millis()) as usize;
+        let mut new_series = Self::with_capacity(new_interval, self.start_ts, self.len() * n);
+
+        for (i, sample) in self.values.iter().enumerate() {
+            let fill = |v: T| {
+                if sample.is_err() {
+                    Sample::Err
+                } else {
+                    Sample::Fake(v)
+                }
+            };
+
+            match method {
+                UpsampleMethod::Repeat => {
+                    new_series.push_sample(*sample);
+                    for _ in 1..n {
+                        new_series.push_sample(fill(sample.val()));
+                    }
+                }
+                UpsampleMethod::Spread => {
+                    let Some(parts) = T::from(n) else {
+                        new_series
+                            .values
+                            .extend(std::iter::repeat_n(Sample::Err, n));
+                        continue;
+                    };
+                    let part = sample.val() / parts;
+                    new_series.push_sample(sample.map(|v| v - part * (parts - T::one())));
+                    for _ in 1..n {
+                        new_series.push_sample(fill(part));
+                    }
+                }
+                UpsampleMethod::Linear => {
+                    new_series.push_sample(*sample);
+                    let Some(next) = self.values.get(i + 1) else {
+                        break;
+                    };
+                    let ends = match (
+                        sample.is_err() || next.is_err(),
+                        sample.val().to_f64(),
+                        next.val().to_f64(),
+                    ) {
+                        (false, Some(v0), Some(v1)) => Some((v0, v1)),
+                        _ => None,
+                    };
+                    for j in 1..n {
+                        let value =
+                            ends.and_then(|(v0, v1)| T::from(v0 + (v1 - v0) * j as f64 / n as f64));
+                        new_series.push_sample(value.map_or(Sample::Err, Sample::Fake));
+                    }
+                }
+            }
+        }
+
+        Ok(new_series)
+    }
+
+    /// Returns the per-second rate of a counter between consecutive slots,
+    /// i.e. their delta divided by the interval in seconds. A drop in value
+    /// or a `Zero` marker is a counter reset, handled as in
+    /// `ops::sample::delta`. Slots next to an `Err` are `Err`, and the rate
+    /// is `Fake` if either side is. The result has one sample less, and
+    /// starts one interval later, at the end of the first pair.
+    pub fn rate(&self) -> AlignedSeries<f64> {
+        let secs = self.interval.millis() as f64 / 1000.0;
+        let mut new_series = AlignedSeries::with_capacity(
+            self.interval,
+            self.slot_ts(1),
+            self.len().saturating_sub(1),
+        );
+
+        for pair in self.values.windows(2) {
+            let rate = match sample::counter_delta(&pair[0], &pair[1]) {
+                Sample::Point(delta) if secs > 0.0 => delta.to_f64().map(|delta| delta / secs),
+                _ => None,
+            };
+            let fake = pair.iter().any(|s| matches!(s, Sample::Fake(_)));
+            new_series.push_sample(match rate {
+                None => Sample::Err,
+                Some(rate) if fake => Sample::Fake(rate),
+                Some(rate) => Sample::Point(rate),
+            });
+        }
+
+        new_series
+    }
+
+    /// Elementwise `self + other`, see `zip_values`. If both units are
+    /// known they must be equal, or this fails with `UnitError::UnitMismatch`;
+    /// an unknown unit takes the other one.
+    pub fn zip_add(&self, other: &Self) -> Result<Self> {
+        let unit = self.sum_unit(other)?;
+        Ok(self
+            .zip_values(other, |a, b| Some(a + b))?
+            .with_unit_opt(unit))
+    }
+
+    /// Elementwise `self - other`, with units as for `zip_add`.
+    pub fn zip_sub(&self, other: &Self) -> Result<Self> {
+        let unit = self.sum_unit(other)?;
+        Ok(self
+            .zip_values(other, |a, b| Some(a - b))?
+            .with_unit_opt(unit))
+    }
+
+    /// Elementwise `self * other`, see `zip_values`. The unit is the product
+    /// of both units, if known.
+    pub fn zip_mul(&self, other: &Self) -> Result<Self> {
+        let unit = self
+            .unit
+            .as_ref()
+            .zip(other.unit.as_ref())
+            .map(|(a, b)| a.mul(b));
+        Ok(self
+            .zip_values(other, |a, b| Some(a * b))?
+            .with_unit_opt(unit))
+    }
+
+    /// Elementwise `self / other`, see `zip_values`. Slots where `other` is
+    /// zero are `Err`. The unit is the quotient of both units, if known.
+    pub fn zip_div(&self, other: &Self) -> Result<Self> {
+        let unit = self
+            .unit
+            .as_ref()
+            .zip(other.unit.as_ref())
+            .map(|(a, b)| a.div(b));
+        Ok(self
+            .zip_values(other, |a, b| (!b.is_zero()).then(|| a / b))?
+            .with_unit_opt(unit))
+    }
+
+    /// The unit of a sum or difference of `self` and `other`.
+    fn sum_unit(&self, other: &Self) -> Result<Option<Unit>, UnitError> {
+        match (&self.unit, &other.unit) {
+            (Some(a), Some(b)) => a.add(b).map(Some),
+            (a, b) => Ok(a.clone().or_else(|| b.clone())),
+        }
+    }
+
+    fn with_unit_opt(mut self, unit: Option<Unit>) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// `zip_with` over the sample values: `Err` on either side, or `f`
+    /// returning `None`, gives `Err`. The result is `Fake` if either side is
+    /// `Fake`, and `Zero` samples count as zero.
+    fn zip_values(&self, other: &Self, f: impl Fn(T, T) -> Option<T>) -> Result<Self> {
+        self.zip_with(other, |a, b| {
+            if a.is_err() || b.is_err() {
+                return Sample::Err;
+            }
+            match f(a.val(), b.val()) {
+                None => Sample::Err,
+                Some(v) if matches!(a, Sample::Fake(_)) || matches!(b, Sample::Fake(_)) => {
+                    Sample::Fake(v)
+                }
+                Some(v) => Sample::Point(v),
+            }
+        })
+    }
+}
+
+/// Iterator over the slots of an aligned series as elements, see
+/// `AlignedSeries::iter_elements`.
+pub struct Elements<'a, T: SampleValue> {
+    series: &'a AlignedSeries<T>,
+    inner: std::iter::Enumerate<std::slice::Iter<'a, Sample<T>>>,
+}
+
+impl<T: SampleValue> Iterator for Elements<'_, T> {
+    type Item = Element<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, sample) = self.inner.next()?;
+        Some(Element(self.series.slot_ts(i), *sample))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T: SampleValue> DoubleEndedIterator for Elements<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (i, sample) = self.inner.next_back()?;
+        Some(Element(self.series.slot_ts(i), *sample))
+    }
+}
+
+impl<T: SampleValue> ExactSizeIterator for Elements<'_, T> {}
+
+impl<'a, T: SampleValue> IntoIterator for &'a AlignedSeries<T> {
+    type Item = Element<T>;
+    type IntoIter = Elements<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_elements()
+    }
+}
+
+/// Scalar arithmetic on `Point` and `Fake` values, returning a new series.
+/// `Err` and `Zero` samples are kept as they are.
+impl<T: SampleValue> Add<T> for &AlignedSeries<T> {
+    type Output = AlignedSeries<T>;
+
+    fn add(self, delta: T) -> Self::Output {
+        self.map_samples(|v| v + delta)
+    }
+}
+
+impl<T: SampleValueOp<T>> Sub<T> for &AlignedSeries<T> {
+    type Output = AlignedSeries<T>;
+
+    fn sub(self, delta: T) -> Self::Output {
+        self.map_samples(|v| v - delta)
+    }
+}
+
+impl<T: SampleValue> Mul<T> for &AlignedSeries<T> {
+    type Output = AlignedSeries<T>;
+
+    fn mul(self, factor: T) -> Self::Output {
+        self.map_samples(|v| v * factor)
+    }
+}
+
+/// Dividing by a zero scalar is an error rather than a panic (for integers)
+/// or a series of infinities (for floats).
+impl<T: SampleValueOp<T>> Div<T> for &AlignedSeries<T> {
+    type Output = Result<AlignedSeries<T>>;
+
+    fn div(self, divisor: T) -> Self::Output {
+        if divisor.is_zero() {
+            anyhow::bail!("division by zero");
+        }
+        Ok(self.map_samples(|v| v / divisor))
+    }
+}
+
+impl<T> fmt::Display for AlignedSeries<T>
+where
+    T: SampleValue + fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for element in self {
+            write!(f, "\n {}", element)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ops::element::sum, sample::SampleEquals};
+
+    #[test]
+    fn iter() {
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        series.push(1);
+        series.push_sample(Sample::Err);
+        series.push_sample(Sample::Fake(3));
+
+        let elements = series.iter().collect::<Vec<_>>();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(
+            elements[2].0,
+            TimeStamp(series.start_ts.millis() + 2 * series.interval.millis())
+        );
+        assert!(elements[2].1.approx_eq(&Sample::Fake(3), 0.0));
+        assert!(elements[1].1.is_err());
+
+        assert_eq!(series.iter().next_back().unwrap().0, TimeStamp(1200));
+
+        let mut elements = series.iter_elements();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements.next_back().unwrap().0, TimeStamp(1200));
+        assert_eq!(elements.next().unwrap().0, TimeStamp(1000));
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements.next().unwrap().0, TimeStamp(1100));
+        assert!(elements.next().is_none());
+
+        let timestamps = (&series).into_iter().map(|e| e.0 .0).collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![1000, 1100, 1200]);
+        let mut count = 0;
+        for element in &series {
+            assert!(element.1.approx_eq(&series.values[count], 0.0));
+            count += 1;
+        }
+        assert_eq!(count, 3);
+        assert_eq!(
+            series.iter_values().map(|s| s.val()).collect::<Vec<_>>(),
+            vec![1, 0, 3]
+        );
+    }
+
+    #[test]
+    fn to_raw_series() {
+        let mut raw = RawSeries::new();
+        for i in 0..50 {
+            // Irregular samples with a gap between 200 and 300.
+            if !(20..30).contains(&i) {
+                raw.push(TimeStamp(i * 10 + i % 4), i);
+            }
+        }
+
+        let aligned =
+            AlignedSeries::from_raw_series(&raw, Interval(50), TimeStamp(0), None, sum).unwrap();
+        let back = aligned.to_raw_series();
+        assert_eq!(back.len(), aligned.len());
+        for (e, (ts, sample)) in back.iter().zip(aligned.iter()) {
+            assert_eq!(e.0, ts);
+            assert!(e.1.approx_eq(&sample, 0.0));
+        }
+
+        // The gap's slots are Err, and come back as empty windows once the
+        // Err elements are dropped.
+        assert!(aligned.values[4].is_err());
+        let mut back = back;
+        assert_eq!(back.drop_errors(), 2);
+        let again =
+            AlignedSeries::from_raw_series(&back, Interval(50), TimeStamp(0), None, sum).unwrap();
+        assert!(again.approx_eq(&aligned, 0.0));
+    }
+
+    #[test]
+    fn from_raw_series_with_fill() {
+        // Windows of 10: empty 0..20, values in 20..30 and 60..70, empty in
+        // between and up to the end at 100.
+        let mut raw = RawSeries::new();
+        raw.push(TimeStamp(22), 2.0);
+        raw.push(TimeStamp(25), 4.0);
+        raw.push(TimeStamp(61), 12.0);
+
+        let align = |fill| {
+            let series = AlignedSeries::from_raw_series_with_fill(
+                &raw,
+                Interval(10),
+                TimeStamp(0),
+                Some(TimeStamp(100)),
+                crate::ops::element::mean,
+                fill,
+            )
+            .unwrap();
+            series.values
+        };
+        let assert_samples = |samples: Vec<Sample<f64>>, expected: &[Sample<f64>]| {
+            assert_eq!(samples.len(), expected.len());
+            for (i, (a, b)) in samples.iter().zip(expected.iter()).enumerate() {
+                assert!(a.approx_eq(b, 1e-9), "slot {}: {} != {}", i, a, b);
+            }
+        };
+        use Sample::{Err, Fake, Point};
+
+        assert_samples(
+            align(FillPolicy::Err),
+            &[
+                Err,
+                Err,
+                Point(3.0),
+                Err,
+                Err,
+                Err,
+                Point(12.0),
+                Err,
+                Err,
+                Err,
+            ],
+        );
+        assert_samples(
+            align(FillPolicy::Zero),
+            &[
+                Fake(0.0),
+                Fake(0.0),
+                Point(3.0),
+                Fake(0.0),
+                Fake(0.0),
+                Fake(0.0),
+                Point(12.0),
+                Fake(0.0),
+                Fake(0.0),
+                Fake(0.0),
+            ],
+        );
+        assert_samples(
+            align(FillPolicy::PreviousValue),
+            &[
+                Err,
+                Err,
+                Point(3.0),
+                Fake(3.0),
+                Fake(3.0),
+                Fake(3.0),
+                Point(12.0),
+                Fake(12.0),
+                Fake(12.0),
+                Fake(12.0),
+            ],
+        );
+        assert_samples(
+            align(FillPolicy::NextValue),
+            &[
+                Fake(3.0),
+                Fake(3.0),
+                Point(3.0),
+                Fake(12.0),
+                Fake(12.0),
+                Fake(12.0),
+                Point(12.0),
+                Err,
+                Err,
+                Err,
+            ],
+        );
+        assert_samples(
+            align(FillPolicy::Constant(-1.0)),
+            &[
+                Fake(-1.0),
+                Fake(-1.0),
+                Point(3.0),
+                Fake(-1.0),
+                Fake(-1.0),
+                Fake(-1.0),
+                Point(12.0),
+                Fake(-1.0),
+                Fake(-1.0),
+                Fake(-1.0),
+            ],
+        );
+        assert_samples(
+            align(FillPolicy::LinearInterpolate),
+            &[
+                Err,
+                Err,
+                Point(3.0),
+                Fake(5.25),
+                Fake(7.5),
+                Fake(9.75),
+                Point(12.0),
+                Err,
+                Err,
+                Err,
+            ],
+        );
+
+        assert!(AlignedSeries::from_raw_series_with_fill(
+            &raw,
+            Interval(10),
+            TimeStamp(50),
+            Some(TimeStamp(0)),
+            crate::ops::element::mean,
+            FillPolicy::Err
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn timestamps_and_indices() {
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        assert_eq!(series.end_ts(), None);
+        assert_eq!(series.timestamp_at(0), None);
+        assert_eq!(series.index_for(TimeStamp(1000)), None);
+
+        for i in 0..3 {
+            series.push(i);
+        }
+        assert_eq!(series.timestamp_at(0), Some(TimeStamp(1000)));
+        assert_eq!(series.timestamp_at(2), Some(TimeStamp(1200)));
+        assert_eq!(series.timestamp_at(3), None);
+        assert_eq!(series.end_ts(), Some(TimeStamp(1200)));
+
+        let index_for = |ts| series.index_for(TimeStamp(ts));
+        assert_eq!(index_for(999), None);
+        assert_eq!(index_for(1000), Some(0));
+        assert_eq!(index_for(1001), Some(0));
+        assert_eq!(index_for(1099), Some(0));
+        assert_eq!(index_for(1100), Some(1));
+        assert_eq!(index_for(1101), Some(1));
+        assert_eq!(index_for(1299), Some(2));
+        assert_eq!(index_for(1300), None);
+
+        let at_or_after = |ts| series.at_or_after(TimeStamp(ts)).map(|e| e.0 .0);
+        assert_eq!(at_or_after(999), Some(1000));
+        assert_eq!(at_or_after(1000), Some(1000));
+        assert_eq!(at_or_after(1001), Some(1100));
+        assert_eq!(at_or_after(1199), Some(1200));
+        assert_eq!(at_or_after(1200), Some(1200));
+        assert_eq!(at_or_after(1201), None);
+
+        let at_or_before = |ts| series.at_or_before(TimeStamp(ts)).map(|e| e.0 .0);
+        assert_eq!(at_or_before(999), None);
+        assert_eq!(at_or_before(1000), Some(1000));
+        assert_eq!(at_or_before(1099), Some(1000));
+        assert_eq!(at_or_before(1100), Some(1100));
+        assert_eq!(at_or_before(1150), Some(1100));
+        assert_eq!(at_or_before(1200), Some(1200));
+        assert_eq!(at_or_before(5000), Some(1200));
+        assert_eq!(series.at_or_before(TimeStamp(1150)).unwrap().1.val(), 1);
+        assert!(AlignedSeries::<i64>::new(Interval(100), TimeStamp(1000))
+            .at_or_before(TimeStamp(1000))
+            .is_none());
+
+        let get_at = |ts| series.get_at(TimeStamp(ts)).map(|s| s.val());
+        assert_eq!(get_at(999), None);
+        assert_eq!(get_at(1000), Some(0));
+        assert_eq!(get_at(1099), None);
+        assert_eq!(get_at(1100), Some(1));
+        assert_eq!(get_at(1200), Some(2));
+        assert_eq!(get_at(1300), None);
+    }
+
+    #[test]
+    fn lookups_every_millisecond() {
+        let mut series = AlignedSeries::new(Interval(7), TimeStamp(-10));
+        for i in 0..5 {
+            series.push(i);
+        }
+        let slots: Vec<i64> = series.iter().map(|(ts, _)| ts.millis()).collect();
+
+        for ts in -40..40 {
+            let after = slots.iter().position(|&slot| slot >= ts);
+            let before = slots.iter().rposition(|&slot| slot <= ts);
+            let containing = before.filter(|&i| ts < slots[i] + 7);
+            let exact = slots.iter().position(|&slot| slot == ts);
+
+            let ts = TimeStamp(ts);
+            assert_eq!(series.index_for(ts), containing, "index_for({})", ts);
+            assert_eq!(
+                series.get_at(ts).map(|s| s.val() as usize),
+                exact,
+                "get_at({})",
+                ts
+            );
+            for (got, want) in [
+                (series.at_or_after(ts), after),
+                (series.at_or_before(ts), before),
+            ] {
+                let got = got.map(|e| (e.0, e.1.val()));
+                let want = want.map(|i| (TimeStamp(slots[i]), i as i64));
+                assert_eq!(got, want, "lookup of {}", ts);
+            }
+        }
+
+        let empty = AlignedSeries::<i64>::new(Interval(7), TimeStamp(-10));
+        for ts in [-11, -10, 0] {
+            assert!(empty.at_or_after(TimeStamp(ts)).is_none());
+            assert!(empty.at_or_before(TimeStamp(ts)).is_none());
+            assert!(empty.index_for(TimeStamp(ts)).is_none());
+        }
+    }
+
+    #[test]
+    fn slice() {
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        for i in 0..5 {
+            series.push(i);
+        }
+
+        let slice = series.slice(TimeStamp(1150), TimeStamp(1400)).unwrap();
+        assert_eq!(slice.start_ts, TimeStamp(1200));
+        assert_eq!(slice.interval, series.interval);
+        assert_eq!(
+            slice.values.iter().map(|s| s.val()).collect::<Vec<_>>(),
+            [2, 3]
+        );
+
+        let values = |start, end| {
+            series
+                .slice_values(TimeStamp(start), TimeStamp(end))
+                .iter()
+                .map(|s| s.val())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values(1000, 1100), [0]);
+        assert_eq!(values(1000, 1101), [0, 1]);
+        assert_eq!(values(0, 1250), [0, 1, 2]);
+        assert_eq!(values(1350, 9000), [4]);
+        assert_eq!(values(0, 9000), [0, 1, 2, 3, 4]);
+        assert!(values(1201, 1300).is_empty());
+        assert!(values(1300, 1200).is_empty());
+
+        assert!(series.slice(TimeStamp(0), TimeStamp(1000)).is_none());
+        assert!(series.slice(TimeStamp(1401), TimeStamp(9000)).is_none());
+        assert!(series.slice(TimeStamp(1300), TimeStamp(1300)).is_none());
+        let whole = series.slice(TimeStamp(0), TimeStamp(9000)).unwrap();
+        assert!(whole.approx_eq(&series, 0.0));
+    }
+
+    #[test]
+    fn aligned_series() {
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        series.push(0);
+        series.push(1);
+        series.push(2);
+        series.push(3);
+        series.push(4);
+        series.push(5);
+        series.push(6);
+        series.push(7);
+        series.push(8);
+        series.push(9);
+
+        assert_eq!(series.at_or_after(TimeStamp(0)).unwrap().0, 1000.into());
+        assert!(series
+            .at_or_after(TimeStamp(0))
+            .unwrap()
+            .1
+            .equals(&Sample::point(0)));
+
+        assert_eq!(series.at_or_after(TimeStamp(999)).unwrap().0, 1000.into());
+        assert!(series
+            .at_or_after(TimeStamp(999))
+            .unwrap()
+            .1
+            .equals(&Sample::point(0)));
+
+        assert_eq!(series.at_or_after(TimeStamp(1000)).unwrap().0, 1000.into());
+        assert!(series
+            .at_or_after(TimeStamp(1000))
+            .unwrap()
+            .1
+            .equals(&Sample::point(0)));
+
+        assert_eq!(series.at_or_after(TimeStamp(1010)).unwrap().0, 1100.into());
+        assert!(series
+            .at_or_after(TimeStamp(1010))
+            .unwrap()
+            .1
+            .equals(&Sample::point(1)));
+
+        assert_eq!(series.at_or_after(TimeStamp(1100)).unwrap().0, 1100.into());
+        assert!(series
+            .at_or_after(TimeStamp(1100))
+            .unwrap()
+            .1
+            .equals(&Sample::point(1)));
+
+        assert_eq!(series.at_or_after(TimeStamp(1900)).unwrap().0, 1900.into());
+        assert!(series.at_or_after(TimeStamp(1910)).is_none());
+    }
+
+    #[test]
+    fn approx_eq() {
+        let mut a = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        a.push(1.0);
+        a.push(2.0);
+        a.push_sample(Sample::Err);
+        a.push_sample(Sample::Fake(3.0));
+
+        let mut b = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        b.push(1.0 + 1e-9);
+        b.push(2.0 - 1e-9);
+        b.push_sample(Sample::Err);
+        b.push_sample(Sample::Fake(3.0 + 1e-9));
+
+        assert!(a.approx_eq(&b, 1e-6));
+        assert!(b.approx_eq(&a, 1e-6));
+        assert!(!a.approx_eq(&b, 1e-12));
+
+        // Variants must match, even if the values are close.
+        let mut c = b.clone();
+        c.values[3] = Sample::Point(3.0);
+        assert!(!a.approx_eq(&c, 1e-6));
+
+        // Alignment and length must match.
+        let mut d = b.clone();
+        d.start_ts = TimeStamp(1100);
+        assert!(!a.approx_eq(&d, 1e-6));
+
+        let mut e = b.clone();
+        e.push(4.0);
+        assert!(!a.approx_eq(&e, 1e-6));
+    }
+
+    #[test]
+    fn zip_with() {
+        use Sample::*;
+
+        let mut used = AlignedSeries::new(Interval(60), TimeStamp(600));
+        used.values = vec![
+            Point(10.0),
+            Point(30.0),
+            Err,
+            Fake(5.0),
+            Point(1.0),
+            Point(2.0),
+        ];
+        let mut total = AlignedSeries::new(Interval(60), TimeStamp(600));
+        total.values = vec![Point(100.0), Point(60.0), Point(10.0), Point(10.0), Zero];
+
+        let ratio = used.zip_div(&total).unwrap();
+        assert_eq!(ratio.start_ts, TimeStamp(600));
+        assert_eq!(ratio.interval, Interval(60));
+        let expected = [Point(0.1), Point(0.5), Err, Fake(0.5), Err];
+        assert_eq!(ratio.len(), expected.len());
+        for (got, want) in ratio.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 1e-9), "{} != {}", got, want);
+        }
+
+        let sum = used.zip_add(&total).unwrap();
+        assert!(sum.values[0].approx_eq(&Point(110.0), 0.0));
+        assert!(sum.values[4].approx_eq(&Point(1.0), 0.0));
+        assert!(used.zip_sub(&total).unwrap().values[1].approx_eq(&Point(-30.0), 0.0));
+        assert!(used.zip_mul(&total).unwrap().values[3].approx_eq(&Fake(50.0), 0.0));
+
+        // Integer division by zero is an error rather than a panic.
+        let mut ints = AlignedSeries::new(Interval(60), TimeStamp(600));
+        ints.values = vec![Point(7), Point(7)];
+        let mut divisors = ints.clone();
+        divisors.values = vec![Point(2), Zero];
+        let quotient = ints.zip_div(&divisors).unwrap();
+        assert!(quotient.values[0].approx_eq(&Point(3), 0.0));
+        assert!(quotient.values[1].is_err());
+
+        let custom = used
+            .zip_with(&total, |a, b| if a.is_err() { b } else { a })
+            .unwrap();
+        assert!(custom.values[2].approx_eq(&Point(10.0), 0.0));
+
+        let shifted = AlignedSeries::<f64>::new(Interval(60), TimeStamp(660));
+        assert!(used.zip_div(&shifted).is_err());
+        let coarser = AlignedSeries::<f64>::new(Interval(120), TimeStamp(600));
+        assert!(used.zip_div(&coarser).is_err());
+    }
+
+    #[test]
+    fn units() {
+        let bytes = Unit::parse("bytes").unwrap();
+        let seconds = Unit::parse("seconds").unwrap();
+        let series = |unit: Option<&Unit>| {
+            let mut series = AlignedSeries::new(Interval(60), TimeStamp(0));
+            series.values = vec![Sample::Point(2.0), Sample::Point(4.0)];
+            series.unit = unit.cloned();
+            series
+        };
+        let used = series(Some(&bytes));
+        let total = series(Some(&bytes));
+        let elapsed = series(Some(&seconds));
+
+        // Sums and differences need equal units.
+        assert_eq!(used.zip_add(&total).unwrap().unit, Some(bytes.clone()));
+        assert_eq!(used.zip_sub(&total).unwrap().unit, Some(bytes.clone()));
+        let err = used.zip_add(&elapsed).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<UnitError>(),
+            Some(&UnitError::UnitMismatch {
+                left: bytes.clone(),
+                right: seconds.clone()
+            })
+        );
+        assert!(used.zip_sub(&elapsed).is_err());
+
+        // Products and quotients combine them.
+        assert_eq!(
+            used.zip_div(&elapsed).unwrap().unit,
+            Some(Unit::parse("bytes/seconds").unwrap())
+        );
+        assert_eq!(
+            used.zip_div(&total).unwrap().unit,
+            Some(Unit::dimensionless())
+        );
+        assert_eq!(
+            used.zip_mul(&elapsed).unwrap().unit,
+            Some(Unit::parse("bytes*seconds").unwrap())
+        );
+
+        // Unknown units aren't checked.
+        let unknown = series(None);
+        assert_eq!(used.zip_add(&unknown).unwrap().unit, Some(bytes.clone()));
+        assert_eq!(unknown.zip_div(&used).unwrap().unit, None);
+
+        // Scaling, offsetting and shifting keep the unit.
+        assert_eq!((&used * 8.0).unit, Some(bytes.clone()));
+        assert_eq!((&used - 1.0).unit, Some(bytes.clone()));
+        assert_eq!((&used / 2.0).unwrap().unit, Some(bytes.clone()));
+        assert_eq!(used.shift_samples(1).unit, Some(bytes.clone()));
+        let mut scaled = used.clone();
+        scaled.scale(2.0);
+        assert_eq!(scaled.unit, Some(bytes));
+    }
+
+    #[test]
+    fn scale_and_offset() {
+        use Sample::*;
+
+        let mut series = AlignedSeries::new(Interval(60), TimeStamp(600));
+        series.values = vec![Point(0.25), Err, Fake(0.5), Zero, Point(1.0)];
+
+        let percent = &series * 100.0;
+        assert_eq!(percent.start_ts, series.start_ts);
+        assert_eq!(percent.interval, series.interval);
+        let expected = [Point(25.0), Err, Fake(50.0), Zero, Point(100.0)];
+        assert_eq!(percent.len(), expected.len());
+        for (got, want) in percent.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 1e-9), "{} != {}", got, want);
+        }
+
+        let fraction = (&percent / 100.0).unwrap();
+        assert!(fraction.approx_eq(&series, 1e-9));
+        assert!((&series / 0.0).is_err());
+
+        let shifted = &series - 1.0;
+        let expected = [Point(-0.75), Err, Fake(-0.5), Zero, Point(0.0)];
+        for (got, want) in shifted.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 1e-9), "{} != {}", got, want);
+        }
+        assert!((&shifted + 1.0).approx_eq(&series, 1e-9));
+
+        // In place.
+        let mut bytes = AlignedSeries::new(Interval(60), TimeStamp(600));
+        bytes.values = vec![Point(1_000_000), Fake(250_000), Err, Zero];
+        bytes.scale(8);
+        bytes.offset(-1_000);
+        let expected = [Point(7_999_000), Fake(1_999_000), Err, Zero];
+        for (got, want) in bytes.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 0.0), "{} != {}", got, want);
+        }
+
+        // Integer division truncates, and dividing by zero doesn't panic.
+        let megabits = (&bytes / 1_000_000).unwrap();
+        assert!(megabits.values[0].approx_eq(&Point(7), 0.0));
+        assert!(megabits.values[1].approx_eq(&Fake(1), 0.0));
+        assert!((&bytes / 0).is_err());
+    }
+
+    #[test]
+    fn cumsum() {
+        use Sample::*;
+
+        let mut series = AlignedSeries::new(Interval(60), TimeStamp(600));
+        series.values = vec![Point(1), Fake(2), Err, Zero, Point(5), Point(3)];
+
+        let total = series.cumsum();
+        assert_eq!(total.start_ts, series.start_ts);
+        assert_eq!(total.interval, series.interval);
+        let expected = [Point(1), Fake(3), Err, Zero, Point(5), Point(8)];
+        assert_eq!(total.len(), expected.len());
+        for (got, want) in total.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 0.0), "{} != {}", got, want);
+        }
+    }
+
+    #[test]
+    fn ema() {
+        use Sample::*;
+
+        let mut series = AlignedSeries::new(Interval(60), TimeStamp(600));
+        series.values = vec![
+            Err,
+            Fake(3),
+            Point(10),
+            Point(20),
+            Err,
+            Fake(0),
+            Zero,
+            Err,
+            Point(4),
+            Point(8),
+        ];
+
+        let ema = series.ema(0.5).unwrap();
+        assert_eq!(ema.start_ts, series.start_ts);
+        assert_eq!(ema.interval, series.interval);
+        let expected = [
+            Err,
+            Err,
+            Point(10.0),
+            Point(15.0),
+            Fake(15.0),
+            Fake(7.5),
+            Zero,
+            Err,
+            Point(4.0),
+            Point(6.0),
+        ];
+        assert_eq!(ema.len(), expected.len());
+        for (got, want) in ema.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 1e-9), "{} != {}", got, want);
+        }
+
+        // alpha = 1 follows the values.
+        let ema = series.ema(1.0).unwrap();
+        assert!(ema.values[3].approx_eq(&Point(20.0), 0.0));
+
+        let ema = series.ema(0.25).unwrap();
+        assert!(ema.values[3].approx_eq(&Point(12.5), 1e-9));
+        assert!(ema.values[9].approx_eq(&Point(5.0), 1e-9));
+
+        for alpha in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(series.ema(alpha).is_err(), "alpha {}", alpha);
+        }
+    }
+
+    #[test]
+    fn rate() {
+        use Sample::*;
+
+        let mut series = AlignedSeries::new(Interval::from_secs(60), TimeStamp(600_000));
+        series.values = vec![
+            Point(0),
+            Point(120),
+            Point(300),
+            Point(60),
+            Err,
+            Point(90),
+            Fake(150),
+            Zero,
+            Point(30),
+        ];
+
+        let rates = series.rate();
+        assert_eq!(rates.start_ts, TimeStamp(660_000));
+        assert_eq!(rates.interval, series.interval);
+        // The drop from 300 to 60 and the Zero marker are resets.
+        let expected = [
+            Point(2.0),
+            Point(3.0),
+            Point(1.0),
+            Err,
+            Err,
+            Fake(1.0),
+            Fake(0.0),
+            Point(0.5),
+        ];
+        assert_eq!(rates.len(), expected.len());
+        for (got, want) in rates.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 1e-9), "{} != {}", got, want);
+        }
+
+        let mut single = AlignedSeries::new(Interval::from_secs(60), TimeStamp(0));
+        single.push(1.0);
+        assert!(single.rate().is_empty());
+    }
+
+    #[test]
+    fn shift() {
+        use Sample::*;
+
+        let day = Interval::from_secs(24 * 60 * 60);
+        let week = Duration::from_secs(7 * 24 * 60 * 60);
+        let this_week_start = TimeStamp(1_700_000_000_000).align_millis(day.millis());
+
+        let mut last_week =
+            AlignedSeries::new(day, TimeStamp(this_week_start.millis() - week.millis()));
+        let mut this_week = AlignedSeries::new(day, this_week_start);
+        for i in 0..7 {
+            last_week.push(100.0 + i as f64);
+            this_week.push(110.0 + 2.0 * i as f64);
+        }
+
+        // Shifted by a week, last week lines up with this one.
+        let shifted = last_week.shift(week);
+        assert_eq!(shifted.start_ts, this_week.start_ts);
+        assert!(this_week.zip_with(&last_week, |a, _| a).is_err());
+        let delta = this_week
+            .zip_with(&shifted, |now, then| match (now, then) {
+                (Point(a), Point(b)) => Point(a - b),
+                _ => Err,
+            })
+            .unwrap();
+        for (i, sample) in delta.values.iter().enumerate() {
+            assert!(sample.approx_eq(&Point(10.0 + i as f64), 1e-9));
+        }
+
+        let back = shifted.shift(Interval(-week.millis()));
+        assert!(back.approx_eq(&last_week, 0.0));
+
+        // The same comparison on one series by moving samples a week on.
+        let mut two_weeks = last_week.clone();
+        two_weeks.values.extend(this_week.values.iter().copied());
+        let delta = two_weeks.zip_sub(&two_weeks.shift_samples(7)).unwrap();
+        assert_eq!(delta.len(), 14);
+        assert!(delta.values[..7].iter().all(|s| s.is_err()));
+        assert!(delta.values[7].approx_eq(&Point(10.0), 1e-9));
+        assert!(delta.values[13].approx_eq(&Point(16.0), 1e-9));
+
+        let earlier = this_week.shift_samples(-2);
+        assert_eq!(earlier.start_ts, this_week.start_ts);
+        assert!(earlier.values[0].approx_eq(&Point(114.0), 0.0));
+        assert!(earlier.values[4].approx_eq(&Point(122.0), 0.0));
+        assert!(earlier.values[5..].iter().all(|s| s.is_err()));
+
+        assert!(this_week.shift_samples(0).approx_eq(&this_week, 0.0));
+        assert!(this_week
+            .shift_samples(100)
+            .values
+            .iter()
+            .all(|s| s.is_err()));
+        assert!(this_week
+            .shift_samples(-100)
+            .values
+            .iter()
+            .all(|s| s.is_err()));
+        assert_eq!(this_week.shift_samples(-100).len(), 7);
+    }
+
+    #[test]
+    fn pct_change() {
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        series.push(10);
+        series.push(0);
+        series.push(15);
+        series.push(5);
+        series.push_sample(Sample::Fake(20));
+        series.push_sample(Sample::Err);
+        series.push(30);
+
+        let pct = series.pct_change(1);
+        assert_eq!(pct.len(), series.len());
+        assert_eq!(pct.start_ts, series.start_ts);
+        assert_eq!(pct.interval, series.interval);
+
+        let mut expected = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        expected.push_sample(Sample::Err); // leading lag
+        expected.push(-100.0); // 10 -> 0
+        expected.push_sample(Sample::Err); // zero denominator
+        expected.push(-200.0 / 3.0); // 15 -> 5
+        expected.push_sample(Sample::Fake(300.0)); // 5 -> Fake(20)
+        expected.push_sample(Sample::Err); // Err numerator
+        expected.push_sample(Sample::Err); // Err denominator
+        assert!(pct.approx_eq(&expected, 1e-9));
+
+        let ratio = series.ratio_to(2);
+        let mut expected = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        expected.push_sample(Sample::Err);
+        expected.push_sample(Sample::Err);
+        expected.push(1.5); // 10 -> 15
+        expected.push_sample(Sample::Err); // zero denominator
+        expected.push_sample(Sample::Fake(20.0 / 15.0));
+        expected.push_sample(Sample::Err);
+        expected.push_sample(Sample::Fake(1.5)); // Fake(20) -> 30
+        assert!(ratio.approx_eq(&expected, 1e-9));
+
+        // A lag longer than the series yields all Err.
+        let pct = series.pct_change(10);
+        assert_eq!(pct.len(), series.len());
+        assert!(pct.values.iter().all(|s| s.is_err()));
+    }
+
+    #[test]
+    fn sliding_aggregate_delta() {
+        let mut series = AlignedSeries::new(Interval(20), TimeStamp(100));
+        for v in [1, 3, 6, 6, 2, 5] {
+            series.push(v);
+        }
+
+        let deltas = series.sliding_aggregate(2, sample::delta).unwrap();
+        assert_eq!(deltas.start_ts, series.start_ts);
+        assert_eq!(deltas.interval, series.interval);
+        assert_eq!(deltas.len(), series.len());
+
+        // Padding, then deltas with a counter reset from 6 to 2.
+        assert!(deltas.values[0].is_err());
+        let expected = [2, 3, 0, 2, 3];
+        for (got, want) in deltas.values[1..].iter().zip(expected) {
+            assert!(got.equals(&Sample::Point(want)));
+        }
+
+        // Windows longer than the series only produce padding.
+        let sums = series.sliding_aggregate(10, sample::sum).unwrap();
+        assert_eq!(sums.len(), series.len());
+        assert!(sums.values.iter().all(|s| s.is_err()));
+
+        // Without padding, the result starts at the end of the first window.
+        let deltas = series.sliding_aggregate_valid(2, sample::delta).unwrap();
+        assert_eq!(deltas.start_ts, TimeStamp(120));
+        assert_eq!(deltas.interval, series.interval);
+        assert_eq!(deltas.len(), 5);
+        assert!(deltas.values[0].equals(&Sample::Point(2)));
+        assert_eq!(deltas.end_ts(), series.end_ts());
+
+        let sums = series.sliding_aggregate_valid(3, sample::sum).unwrap();
+        assert_eq!(sums.start_ts, TimeStamp(140));
+        assert!(sums.values[0].equals(&Sample::Point(10)));
+        assert_eq!(sums.len(), 4);
+
+        let sums = series.sliding_aggregate_valid(10, sample::sum).unwrap();
+        assert!(sums.is_empty());
+        assert!(series.sliding_aggregate_valid(0, sample::sum).is_err());
+        assert!(series.sliding_aggregate(0, sample::sum).is_err());
+    }
+
+    #[test]
+    fn reserve_windows() {
+        let mut series = RawSeries::with_capacity(16);
+        for ts in [0, 2, 3, 4, 6, 7, 9, 15, 22, 28, 30, 31, 32, 35, 40] {
+            series.push(TimeStamp(ts), ts);
+        }
+
+        // The up-front reservation matches the number of windows produced,
+        // whether or not the end is bounded or falls on a window edge.
+        for end_ts in [None, Some(0), Some(12), Some(15), Some(100)] {
+            let mut window_iter = series.windows(Interval(5), TimeStamp(0));
+            if let Some(end_ts) = end_ts {
+                window_iter.set_end_ts(TimeStamp(end_ts));
+            }
+            let expected = window_iter.num_windows();
+
+            let aligned_series = AlignedSeries::from_raw_series(
+                &series,
+                Interval(5),
+                TimeStamp(0),
+                end_ts.map(TimeStamp),
+                sum,
+            )
+            .unwrap();
+            assert_eq!(aligned_series.len(), expected);
+            assert!(aligned_series.values.capacity() >= expected);
+
+            let mut copy = AlignedSeries::with_capacity(Interval(5), TimeStamp(0), expected);
+            copy.reserve(0);
+            for sample in aligned_series.values.iter() {
+                copy.push_sample(*sample);
+            }
+            assert!(copy.approx_eq(&aligned_series, 0.0));
+        }
+    }
+
+    #[test]
+    fn from_raw_series_into() {
+        let mut series = RawSeries::new();
+        for ts in [0, 2, 3, 4, 6, 7, 9, 15, 22, 28, 30, 31, 32, 35, 40] {
+            series.push(TimeStamp(ts), ts);
+        }
+
+        struct CountingSink {
+            elements: Vec<Element<i64>>,
+            reserved: usize,
+        }
+
+        impl Sink<i64> for CountingSink {
+            fn send(&mut self, element: Element<i64>) {
+                self.elements.push(element);
+            }
+
+            fn reserve(&mut self, additional: usize) {
+                self.reserved += additional;
+            }
+        }
+
+        let mut sink = CountingSink {
+            elements: vec![],
+            reserved: 0,
+        };
+        let count = AlignedSeries::from_raw_series_into(
+            &series,
+            Interval(5),
+            TimeStamp(0),
+            None,
+            sum,
+            &mut sink,
+        )
+        .unwrap();
+
+        let aligned_series =
+            AlignedSeries::from_raw_series(&series, Interval(5), TimeStamp(0), None, sum).unwrap();
+
+        assert_eq!(count, aligned_series.len());
+        assert_eq!(sink.reserved, count);
+        assert_eq!(sink.elements.len(), aligned_series.len());
+        for (i, element) in sink.elements.iter().enumerate() {
+            assert_eq!(element.0, TimeStamp(i as i64 * 5));
+            assert!(element.1.equals(&aligned_series.values[i]));
+        }
+
+        // Invalid bounds are rejected before anything reaches the sink.
+        let mut elements: Vec<Element<i64>> = vec![];
+        assert!(AlignedSeries::from_raw_series_into(
+            &series,
+            Interval(5),
+            TimeStamp(10),
+            Some(TimeStamp(5)),
+            sum,
+            &mut elements,
+        )
+        .is_err());
+        assert!(elements.is_empty());
+    }
+
+    #[test]
+    fn from_elements() {
+        let mut raw = RawSeries::new();
+        for ts in [0, 2, 3, 4, 6, 7, 9, 15, 22, 28, 30, 31, 32, 35, 40] {
+            raw.push(TimeStamp(ts), ts);
+        }
+
+        // Same windows as from_raw_series, for any bounds.
+        for start in [-7, 0, 3, 20, 41] {
+            for end in [None, Some(start), Some(start + 1), Some(33), Some(60)] {
+                if end.is_some_and(|end| end < start) {
+                    continue;
+                }
+                let (start, end) = (TimeStamp(start), end.map(TimeStamp));
+                let expected =
+                    AlignedSeries::from_raw_series(&raw, Interval(5), start, end, sum).unwrap();
+                let aligned =
+                    AlignedSeries::from_elements(&raw.values, Interval(5), start, end, sum)
+                        .unwrap();
+                assert!(aligned.approx_eq(&expected, 0.0), "{:?}..{:?}", start, end);
+            }
+        }
+
+        assert!(AlignedSeries::from_elements(
+            &raw.values,
+            Interval(5),
+            TimeStamp(10),
+            Some(TimeStamp(5)),
+            sum
+        )
+        .is_err());
+        assert!(
+            AlignedSeries::from_elements(&raw.values, Interval(0), TimeStamp(0), None, sum)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn extend_from_raw() {
+        let mut raw = RawSeries::new();
+        let mut aligned = AlignedSeries::new(Interval(5), TimeStamp(0));
+
+        // Nothing to do without raw data.
+        assert_eq!(aligned.extend_from_raw(&raw, sum).unwrap(), 0);
+
+        for ts in [0, 2, 3, 4, 6] {
+            raw.push(TimeStamp(ts), ts);
+        }
+
+        // The window at 5 is still open.
+        assert_eq!(aligned.extend_from_raw(&raw, sum).unwrap(), 1);
+        assert_eq!(aligned.len(), 1);
+        assert_eq!(aligned.extend_from_raw(&raw, sum).unwrap(), 0);
+
+        // A sample at 9 doesn't close it either, one at 22 closes it and the
+        // empty windows after it.
+        raw.push(TimeStamp(9), 9);
+        assert_eq!(aligned.extend_from_raw(&raw, sum).unwrap(), 0);
+        raw.push(TimeStamp(22), 22);
+        assert_eq!(aligned.extend_from_raw(&raw, sum).unwrap(), 3);
+
+        let expected = [
+            Sample::Point(9),
+            Sample::Point(15),
+            Sample::Err,
+            Sample::Err,
+        ];
+        assert_eq!(aligned.len(), expected.len());
+        for (sample, expected) in aligned.values.iter().zip(expected) {
+            assert!(sample.equals(&expected));
+        }
+
+        // Extending in steps matches aligning in one go, minus the open
+        // window.
+        for ts in [28, 30, 31, 32, 35, 40] {
+            raw.push(TimeStamp(ts), ts);
+            aligned.extend_from_raw(&raw, sum).unwrap();
+        }
+        let full =
+            AlignedSeries::from_raw_series(&raw, Interval(5), TimeStamp(0), None, sum).unwrap();
+        assert_eq!(aligned.len(), full.len() - 1);
+        for (sample, expected) in aligned.values.iter().zip(full.values.iter()) {
+            assert!(sample.equals(expected));
+        }
+
+        // Raw samples before the series start are ignored.
+        let mut late = AlignedSeries::new(Interval(5), TimeStamp(20));
+        assert_eq!(late.extend_from_raw(&raw, sum).unwrap(), 4);
+        assert!(late.values[0].equals(&Sample::Point(22)));
+        assert!(late.values[2].equals(&Sample::Point(93)));
+    }
+
+    #[test]
+    fn fill() {
+        use Sample::*;
+
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        series.values = vec![Err, Point(1), Err, Err, Err, Point(5), Err, Zero, Err, Err];
+
+        let mut forward = series.clone();
+        assert_eq!(forward.fill_forward(Some(2), false), 5);
+        let expected = [
+            Err,
+            Point(1),
+            Fake(1),
+            Fake(1),
+            Err,
+            Point(5),
+            Fake(5),
+            Zero,
+            Fake(0),
+            Fake(0),
+        ];
+        for (got, want) in forward.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 0.0), "{} != {}", got, want);
+        }
+
+        let mut forward = series.clone();
+        assert_eq!(forward.fill_forward(None, false), 6);
+        assert!(forward.values[0].is_err());
+        assert!(forward.values[4].approx_eq(&Fake(1), 0.0));
+
+        let mut backward = series.clone();
+        assert_eq!(backward.fill_backward(Some(1), false), 3);
+        let expected = [
+            Fake(1),
+            Point(1),
+            Err,
+            Err,
+            Fake(5),
+            Point(5),
+            Fake(0),
+            Zero,
+            Err,
+            Err,
+        ];
+        for (got, want) in backward.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 0.0), "{} != {}", got, want);
+        }
+
+        let mut constant = series.clone();
+        assert_eq!(constant.fill_value(7, false), 7);
+        assert!(constant.values[0].approx_eq(&Fake(7), 0.0));
+        assert!(constant.values[7].is_zero());
+        assert!(!constant.values.iter().any(|s| s.is_err()));
+
+        // With `fill_zero`, the reset is a hole too rather than a neighbor.
+        let mut forward = series.clone();
+        assert_eq!(forward.fill_forward(Some(2), true), 4);
+        assert!(forward.values[7].approx_eq(&Fake(5), 0.0));
+        assert!(forward.values[8].is_err());
+
+        let mut backward = series.clone();
+        assert_eq!(backward.fill_backward(None, true), 4);
+        assert!(backward.values[6].is_err());
+        assert!(backward.values[7].is_zero());
+
+        let mut constant = series.clone();
+        assert_eq!(constant.fill_value(7, true), 8);
+        assert!(constant.values[7].approx_eq(&Fake(7), 0.0));
+
+        let mut linear = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        linear.values = vec![Err, Point(10.0), Err, Point(20.0), Err];
+        assert_eq!(linear.fill_linear(), 1);
+        let expected = [Err, Point(10.0), Fake(15.0), Point(20.0), Err];
+        for (got, want) in linear.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 0.0), "{} != {}", got, want);
+        }
+
+        let mut holes = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        holes.values = vec![Point(10.0), Err, Point(20.0)];
+        let filled = holes.filled_linear();
+        assert!(filled.values[1].approx_eq(&Fake(15.0), 0.0));
+        assert!(holes.values[1].is_err());
+    }
+
+    #[test]
+    fn fill_missing() {
+        use Sample::*;
+
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        series.values = vec![
+            Err,
+            Point(1.0),
+            Err,
+            Err,
+            Point(4.0),
+            Err,
+            Fake(5.0),
+            Err,
+            Point(7.0),
+            Err,
+        ];
+
+        // Only the gap between the two points is bridged.
+        let mut linear = series.clone();
+        assert_eq!(linear.fill_missing(FillPolicy::LinearInterpolate), 2);
+        let expected = [
+            Err,
+            Point(1.0),
+            Fake(2.0),
+            Fake(3.0),
+            Point(4.0),
+            Err,
+            Fake(5.0),
+            Err,
+            Point(7.0),
+            Err,
+        ];
+        for (got, want) in linear.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 1e-9), "{} != {}", got, want);
+        }
+
+        let mut forward = series.clone();
+        assert_eq!(forward.fill_missing(FillPolicy::PreviousValue), 5);
+        assert!(forward.values[0].is_err());
+        assert!(forward.values[3].approx_eq(&Fake(1.0), 0.0));
+        assert!(forward.values[9].approx_eq(&Fake(7.0), 0.0));
+
+        let mut back = series.clone();
+        assert_eq!(back.fill_missing(FillPolicy::NextValue), 5);
+        assert!(back.values[0].approx_eq(&Fake(1.0), 0.0));
+        assert!(back.values[9].is_err());
+
+        let mut constant = series.clone();
+        assert_eq!(constant.fill_missing(FillPolicy::Constant(-1.0)), 6);
+        assert!(!constant.values.iter().any(|s| s.is_err()));
+
+        let mut zero = series.clone();
+        assert_eq!(zero.fill_missing(FillPolicy::Zero), 6);
+        assert!(zero.values[0].approx_eq(&Fake(0.0), 0.0));
+        assert_eq!(series.clone().fill_missing(FillPolicy::Err), 0);
+
+        // Integer values are truncated.
+        let mut ints = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        ints.values = vec![Point(0), Err, Err, Point(5)];
+        assert_eq!(ints.fill_missing(FillPolicy::LinearInterpolate), 2);
+        assert!(ints.values[1].approx_eq(&Fake(1), 0.0));
+        assert!(ints.values[2].approx_eq(&Fake(3), 0.0));
+    }
+
+    #[test]
+    fn resample() {
+        use Sample::*;
+
+        let three_minutes = Interval::from_minutes(3);
+        let mut series = AlignedSeries::new(Interval::from_minutes(1), TimeStamp(540_000));
+        series.values = vec![
+            Point(1),
+            Point(2),
+            Err,
+            Point(4),
+            Point(5),
+            Point(6),
+            Point(7),
+        ];
+
+        // The last slot only has one sample.
+        let resampled = series
+            .resample(three_minutes, sample::sum, PartialChunk::Aggregate)
+            .unwrap();
+        assert_eq!(resampled.start_ts, series.start_ts);
+        assert_eq!(resampled.interval, three_minutes);
+        let expected = [Point(3), Point(15), Point(7)];
+        assert_eq!(resampled.len(), expected.len());
+        for (got, want) in resampled.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 0.0), "{} != {}", got, want);
+        }
+
+        let resampled = series
+            .resample(three_minutes, sample::sum, PartialChunk::Drop)
+            .unwrap();
+        assert_eq!(resampled.start_ts, series.start_ts);
+        assert_eq!(resampled.len(), 2);
+
+        // Starting mid-slot, the first slot is partial as well.
+        series.start_ts = TimeStamp(600_000);
+        let resampled = series
+            .resample(three_minutes, sample::sum, PartialChunk::Aggregate)
+            .unwrap();
+        assert_eq!(resampled.start_ts, TimeStamp(540_000));
+        let expected = [Point(3), Point(9), Point(13)];
+        assert_eq!(resampled.len(), expected.len());
+        for (got, want) in resampled.values.iter().zip(expected.iter()) {
+            assert!(got.approx_eq(want, 0.0), "{} != {}", got, want);
+        }
+
+        let resampled = series
+            .resample(three_minutes, sample::sum, PartialChunk::Drop)
+            .unwrap();
+        assert_eq!(resampled.start_ts, TimeStamp(720_000));
+        assert_eq!(resampled.len(), 1);
+        assert!(resampled.values[0].approx_eq(&Point(9), 0.0));
+
+        // Cascading 1m -> 3m -> 6m matches 1m -> 6m.
+        let cascaded = series
+            .resample(three_minutes, sample::max, PartialChunk::Aggregate)
+            .and_then(|s| {
+                s.resample(
+                    Interval::from_minutes(6),
+                    sample::max,
+                    PartialChunk::Aggregate,
+                )
+            })
+            .unwrap();
+        let direct = series
+            .resample(
+                Interval::from_minutes(6),
+                sample::max,
+                PartialChunk::Aggregate,
+            )
+            .unwrap();
+        assert!(cascaded.approx_eq(&direct, 0.0));
+
+        let resampled = series
+            .resample(series.interval, sample::max, PartialChunk::Drop)
+            .unwrap();
+        assert!(resampled.approx_eq(&series, 0.0));
+
+        let empty = AlignedSeries::<i64>::new(Interval(10), TimeStamp(0));
+        assert!(empty
+            .resample(Interval(30), sample::sum, PartialChunk::Aggregate)
+            .unwrap()
+            .is_empty());
+
+        // Finer intervals and non-multiples are rejected.
+        for new_interval in [30, 90, 0, -60] {
+            assert_eq!(
+                series
+                    .resample(
+                        Interval::from_secs(new_interval),
+                        sample::sum,
+                        PartialChunk::Aggregate
+                    )
+                    .unwrap_err(),
+                ResampleError::NotMultiple {
+                    interval: series.interval,
+                    new_interval: Interval::from_secs(new_interval),
+                }
+            );
+        }
+
+        // Slots straddling the new slot boundaries are rejected.
+        series.start_ts = TimeStamp(630_000);
+        let err = series
+            .resample(three_minutes, sample::sum, PartialChunk::Aggregate)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ResampleError::Misaligned {
+                start_ts: TimeStamp(630_000),
+                interval: series.interval,
+                new_interval: three_minutes,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "start 630000 is not a whole number of 1m slots past a multiple of 3m"
+        );
+    }
+
+    #[test]
+    fn upsample() {
+        use Sample::*;
+
+        let mut series = AlignedSeries::new(Interval::from_minutes(1), TimeStamp(60_000));
+        series.values = vec![Point(7), Err, Point(12), Zero];
+        let check = |method, expected: &[Sample<i64>]| {
+            let upsampled = series.upsample(Interval::from_secs(20), method).unwrap();
+            assert_eq!(upsampled.start_ts, series.start_ts);
+            assert_eq!(upsampled.interval, Interval::from_secs(20));
+            assert_eq!(upsampled.len(), expected.len(), "{:?}", method);
+            for (got, want) in upsampled.values.iter().zip(expected.iter()) {
+                assert!(
+                    got.approx_eq(want, 0.0),
+                    "{:?}: {} != {}",
+                    method,
+                    got,
+                    want
+                );
+            }
+        };
+
+        check(
+            UpsampleMethod::Repeat,
+            &[
+                Point(7),
+                Fake(7),
+                Fake(7),
+                Err,
+                Err,
+                Err,
+                Point(12),
+                Fake(12),
+                Fake(12),
+                Zero,
+                Fake(0),
+                Fake(0),
+            ],
+        );
+        // Nothing follows the last sample.
+        check(
+            UpsampleMethod::Linear,
+            &[
+                Point(7),
+                Err,
+                Err,
+                Err,
+                Err,
+                Err,
+                Point(12),
+                Fake(8),
+                Fake(4),
+                Zero,
+            ],
+        );
+        check(
+            UpsampleMethod::Spread,
+            &[
+                Point(3),
+                Fake(2),
+                Fake(2),
+                Err,
+                Err,
+                Err,
+                Point(4),
+                Fake(4),
+                Fake(4),
+                Zero,
+                Fake(0),
+                Fake(0),
+            ],
+        );
+
+        let same = series
+            .upsample(series.interval, UpsampleMethod::Linear)
+            .unwrap();
+        assert!(same.approx_eq(&series, 0.0));
+
+        for new_interval in [40, 120, 0, -20] {
+            assert_eq!(
+                series
+                    .upsample(Interval::from_secs(new_interval), UpsampleMethod::Repeat)
+                    .unwrap_err(),
+                ResampleError::NotDivisor {
+                    interval: series.interval,
+                    new_interval: Interval::from_secs(new_interval),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn missing_policy() {
+        use Sample::*;
+
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        series.values = vec![
+            Point(1.0),
+            Err,
+            Point(3.0),
+            Point(5.0),
+            Err,
+            Err,
+            Point(7.0),
+        ];
+
+        let check = |got: AlignedSeries<f64>, want: &[Sample<f64>]| {
+            assert_eq!(got.len(), want.len());
+            for (g, w) in got.values.iter().zip(want.iter()) {
+                assert!(g.approx_eq(w, 1e-9), "{} != {}", g, w);
+            }
+        };
+
+        // Zero: Err samples count as zero.
+        check(
+            series.rolling_mean(3, MissingPolicy::Zero).unwrap(),
+            &[
+                Err,
+                Err,
+                Point(4.0 / 3.0),
+                Point(8.0 / 3.0),
+                Point(8.0 / 3.0),
+                Point(5.0 / 3.0),
+                Point(7.0 / 3.0),
+            ],
+        );
+
+        // Propagate: any Err poisons the window.
+        check(
+            series.rolling_sum(3, MissingPolicy::Propagate).unwrap(),
+            &[Err, Err, Err, Err, Err, Err, Err],
+        );
+        check(
+            series.rolling_max(2, MissingPolicy::Propagate).unwrap(),
+            &[Err, Err, Err, Point(5.0), Err, Err, Err],
+        );
+
+        // Skip: compute over valid samples only.
+        check(
+            series
+                .rolling_mean(3, MissingPolicy::Skip { min_valid: 1 })
+                .unwrap(),
+            &[
+                Err,
+                Err,
+                Point(2.0),
+                Point(4.0),
+                Point(4.0),
+                Point(5.0),
+                Point(7.0),
+            ],
+        );
+        check(
+            series
+                .rolling_min(3, MissingPolicy::Skip { min_valid: 2 })
+                .unwrap(),
+            &[Err, Err, Point(1.0), Point(3.0), Point(3.0), Err, Err],
+        );
+
+        // Trailing mean of [1, 2, 3, 4].
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        series.values = vec![Point(1.0), Point(2.0), Point(3.0), Point(4.0)];
+        let mean = series.rolling_mean(2, MissingPolicy::Propagate).unwrap();
+        assert_eq!(mean.start_ts, series.start_ts);
+        check(mean, &[Err, Point(1.5), Point(2.5), Point(3.5)]);
+    }
+
+    #[test]
+    fn changes() {
+        use Sample::*;
+
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(0));
+        series.values = vec![
+            Point(3.0),
+            Point(3.05),
+            Err,
+            Point(2.97),
+            Point(5.0),
+            Fake(7.0),
+            Point(5.02),
+            Point(3.0),
+        ];
+
+        let events = series.changes(0.1, false);
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent {
+                    ts: TimeStamp(400),
+                    from: 3.0,
+                    to: 5.0
+                },
+                ChangeEvent {
+                    ts: TimeStamp(500),
+                    from: 5.0,
+                    to: 7.0
+                },
+                ChangeEvent {
+                    ts: TimeStamp(600),
+                    from: 7.0,
+                    to: 5.02
+                },
+                ChangeEvent {
+                    ts: TimeStamp(700),
+                    from: 5.02,
+                    to: 3.0
+                },
+            ]
+        );
+
+        let events = series.changes(0.1, true);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].ts, TimeStamp(400));
+        assert_eq!(events[1].ts, TimeStamp(700));
+
+        // Exact changes on integers.
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(0));
+        series.values = vec![Point(3), Point(3), Point(5), Zero, Point(0)];
+        let events = series.changes(0.0, false);
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent {
+                    ts: TimeStamp(200),
+                    from: 3,
+                    to: 5
+                },
+                ChangeEvent {
+                    ts: TimeStamp(300),
+                    from: 5,
+                    to: 0
+                },
+            ]
+        );
     }
 
     #[test]
@@ -4,6 +4,7 @@ use core::fmt;
 use derive_more::{Add, Div, From, Into, Mul, Sub};
 
 #[repr(transparent)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(
     From, Into, Debug, PartialEq, Eq, Clone, Ord, PartialOrd, Hash, Add, Sub, Mul, Div, Copy,
 )]
@@ -15,11 +16,7 @@ impl TimeStamp {
     }
 
     pub fn to_utc(&self) -> chrono::DateTime<chrono::Utc> {
-        chrono::DateTime::from_utc(
-            chrono::NaiveDateTime::from_timestamp_millis(self.0)
-                .unwrap_or(chrono::NaiveDateTime::from_timestamp_millis(0).unwrap()),
-            chrono::Utc,
-        )
+        chrono::DateTime::from_timestamp_millis(self.0).unwrap_or_default()
     }
 
     pub fn from_utc(dt: chrono::DateTime<chrono::Utc>) -> Self {
@@ -47,6 +44,9 @@ impl fmt::Display for TimeStamp {
 )]
 pub struct Interval(pub i64);
 
+/// A span of time in milliseconds.
+pub type Duration = Interval;
+
 impl Interval {
     pub fn millis(&self) -> i64 {
         self.0
@@ -63,12 +63,171 @@ impl Interval {
     pub fn from_millis(millis: i64) -> Self {
         Self(millis)
     }
+
+    /// Returns the interval as a count of the largest unit that divides it
+    /// exactly, e.g. "500ms", "90s", "5m", "1h", "7d". A zero interval is
+    /// "0ms".
+    pub fn canonical_name(&self) -> String {
+        for (unit, millis) in INTERVAL_UNITS.iter() {
+            if self.0 != 0 && self.0 % millis == 0 {
+                return format!("{}{}", self.0 / millis, unit);
+            }
+        }
+
+        format!("{}ms", self.0)
+    }
+
+    /// Parses an interval from a name produced by `canonical_name`. Any
+    /// integer count of "ms", "s", "m", "h" or "d" is accepted.
+    pub fn from_name(name: &str) -> anyhow::Result<Self> {
+        let split = name
+            .find(|c: char| !c.is_ascii_digit() && c != '-')
+            .ok_or_else(|| anyhow::anyhow!("missing unit in interval {:?}", name))?;
+        let (count, unit) = name.split_at(split);
+
+        let count = count
+            .parse::<i64>()
+            .map_err(|e| anyhow::anyhow!("invalid count in interval {:?}: {}", name, e))?;
+        let millis = INTERVAL_UNITS
+            .iter()
+            .find(|(u, _)| *u == unit)
+            .map(|(_, millis)| *millis)
+            .ok_or_else(|| anyhow::anyhow!("unknown unit in interval {:?}", name))?;
+
+        match count.checked_mul(millis) {
+            Some(millis) => Ok(Self(millis)),
+            None => anyhow::bail!("interval {:?} is out of range", name),
+        }
+    }
+}
+
+impl std::str::FromStr for Interval {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        Self::from_name(name)
+    }
 }
 
+/// Units used by `Interval::canonical_name`, largest first.
+const INTERVAL_UNITS: [(&str, i64); 5] = [
+    ("d", 24 * 60 * 60 * 1000),
+    ("h", 60 * 60 * 1000),
+    ("m", 60 * 1000),
+    ("s", 1000),
+    ("ms", 1),
+];
+
+/// Intervals display as their canonical name, e.g. "5m".
 impl fmt::Display for Interval {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let secs = self.0 / 1000;
-        let millis = self.0 % 1000;
-        write!(f, "{}.{:03}s", secs, millis)
+        write!(f, "{}", self.canonical_name())
+    }
+}
+
+/// Human-readable formats use the canonical name, others the milliseconds.
+/// Both forms are accepted when deserializing a human-readable format.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Interval {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.canonical_name())
+        } else {
+            serializer.serialize_i64(self.0)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Interval {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Millis(i64),
+            Name(String),
+        }
+
+        if !deserializer.is_human_readable() {
+            return i64::deserialize(deserializer).map(Self);
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Millis(millis) => Ok(Self(millis)),
+            Repr::Name(name) => Self::from_name(&name).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// A calendar field that timestamps can be bucketed by, regardless of which
+/// day (or month) they fall on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CalendarUnit {
+    /// Hour of the day, 0 to 23.
+    HourOfDay,
+
+    /// Day of the week, 0 (Monday) to 6 (Sunday).
+    DayOfWeek,
+
+    /// Day of the month, 1 to 31.
+    DayOfMonth,
+}
+
+impl CalendarUnit {
+    /// Returns the bucket key for `ts` in the timezone `tz`.
+    pub fn key<Tz: chrono::TimeZone>(&self, ts: TimeStamp, tz: &Tz) -> u32 {
+        use chrono::{Datelike, Timelike};
+
+        let local = ts.to_utc().with_timezone(tz);
+        match self {
+            Self::HourOfDay => local.hour(),
+            Self::DayOfWeek => local.weekday().num_days_from_monday(),
+            Self::DayOfMonth => local.day(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_names() {
+        let cases = [
+            (Interval::from_millis(0), "0ms"),
+            (Interval::from_millis(250), "250ms"),
+            (Interval::from_millis(1500), "1500ms"),
+            (Interval::from_secs(1), "1s"),
+            (Interval::from_secs(90), "90s"),
+            (Interval::from_minutes(1), "1m"),
+            (Interval::from_minutes(5), "5m"),
+            (Interval::from_minutes(90), "90m"),
+            (Interval::from_minutes(60), "1h"),
+            (Interval::from_minutes(60 * 24), "1d"),
+            (Interval::from_minutes(60 * 24 * 7), "7d"),
+            (Interval::from_minutes(-5), "-5m"),
+        ];
+
+        for (interval, name) in cases {
+            assert_eq!(interval.canonical_name(), name);
+            assert_eq!(Interval::from_name(name).unwrap(), interval);
+        }
+
+        // Non-canonical names still parse.
+        assert_eq!(
+            Interval::from_name("60s").unwrap(),
+            Interval::from_minutes(1)
+        );
+
+        assert!(Interval::from_name("").is_err());
+        assert!(Interval::from_name("5").is_err());
+        assert!(Interval::from_name("m").is_err());
+        assert!(Interval::from_name("5w").is_err());
+        assert!(Interval::from_name("1.5h").is_err());
+        assert!(Interval::from_name("9223372036854775807d").is_err());
+        assert!(Interval::from_name("-9223372036854775808s").is_err());
+
+        // Display and FromStr go through the canonical name.
+        assert_eq!(Interval::from_minutes(5).to_string(), "5m");
+        assert_eq!("90s".parse::<Interval>().unwrap(), Interval::from_secs(90));
     }
 }
@@ -1,4 +1,4 @@
-use sup::{metric::Metric, ops, AlignedSeries, RawSeries, TimeStamp};
+use sup::prelude::*;
 use sysinfo::{CpuExt, CpuRefreshKind, RefreshKind, SystemExt};
 
 fn main() {
@@ -15,7 +15,7 @@ fn main() {
 
     let series = AlignedSeries::from_raw_series(
         &series,
-        sup::Interval(20),
+        Interval(20),
         series.get(0).unwrap().0.align_millis(100),
         None,
         ops::element::youngest,
@@ -44,6 +44,6 @@ fn main() {
         std::thread::sleep(std::time::Duration::from_millis(300));
     }
 
-    let stream = metric.stream.raw.first().unwrap();
+    let stream = metric.stream.raw().first().unwrap();
     println!("usage ({}): {}", stream.len(), stream);
 }
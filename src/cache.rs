@@ -0,0 +1,147 @@
+//! Read-through cache for `Stream::query` results.
+
+use std::{ops::Range, sync::Arc};
+
+use crate::{sample::SampleValue, AlignedSeries, Interval, TimeStamp};
+
+/// Hit and miss counters of a query cache.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Key {
+    range: Range<TimeStamp>,
+    interval: Interval,
+    op: String,
+}
+
+#[derive(Clone)]
+struct Entry<T: SampleValue> {
+    key: Key,
+    series: Arc<AlignedSeries<T>>,
+
+    /// The stream's data version the series is valid for.
+    version: u64,
+
+    /// Whether the series covers the whole range. Partial results end at the
+    /// last sample and change if data arrives anywhere after `range.start`.
+    complete: bool,
+}
+
+/// A small LRU cache of query results. Entries are kept in recency order,
+/// least recent first, so lookups are linear in the capacity.
+///
+/// Each entry records the data version (the owning stream's change counter)
+/// it was computed at, and is only served while the stream is at that
+/// version. On a change, `advance` carries the entries the change can't
+/// affect over to the new version.
+#[derive(Clone)]
+pub(crate) struct QueryCache<T: SampleValue> {
+    capacity: usize,
+    entries: Vec<Entry<T>>,
+    stats: QueryCacheStats,
+}
+
+impl<T: SampleValue> QueryCache<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Vec::with_capacity(capacity),
+            stats: QueryCacheStats::default(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> QueryCacheStats {
+        self.stats
+    }
+
+    /// Returns the cached result for the query at data version `version`,
+    /// if any, and marks it as most recently used. An entry from an older
+    /// version is dropped.
+    pub(crate) fn get(
+        &mut self,
+        range: &Range<TimeStamp>,
+        interval: Interval,
+        op: &str,
+        version: u64,
+    ) -> Option<Arc<AlignedSeries<T>>> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.key.range == *range && e.key.interval == interval && e.key.op == op);
+
+        match index {
+            Some(index) if self.entries[index].version == version => {
+                self.stats.hits += 1;
+                let entry = self.entries.remove(index);
+                let series = entry.series.clone();
+                self.entries.push(entry);
+                Some(series)
+            }
+            Some(index) => {
+                self.stats.misses += 1;
+                self.entries.remove(index);
+                None
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Cache a query result computed at data version `version`, evicting
+    /// the least recently used entry if the cache is full.
+    pub(crate) fn insert(
+        &mut self,
+        range: Range<TimeStamp>,
+        interval: Interval,
+        op: &str,
+        version: u64,
+        series: Arc<AlignedSeries<T>>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+
+        let span = (range.end - range.start).millis();
+        let full_len = (span + interval.millis() - 1) / interval.millis();
+        let complete = series.len() as i64 >= full_len;
+
+        self.entries.push(Entry {
+            key: Key {
+                range,
+                interval,
+                op: op.to_string(),
+            },
+            series,
+            version,
+            complete,
+        });
+    }
+
+    /// Move to data version `version` after data in `start..=end` changed.
+    /// Entries at the previous version whose result can't change are carried
+    /// over; the others are dropped.
+    pub(crate) fn advance(&mut self, start: TimeStamp, end: TimeStamp, version: u64) {
+        self.entries.retain_mut(|e| {
+            let range = &e.key.range;
+            let affected = if e.complete {
+                start < range.end && end >= range.start
+            } else {
+                end >= range.start
+            };
+            if affected || e.version + 1 != version {
+                return false;
+            }
+            e.version = version;
+            true
+        });
+    }
+}
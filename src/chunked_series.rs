@@ -0,0 +1,271 @@
+//! Raw series stored in fixed-size chunks.
+//!
+//! `ChunkedRawSeries` holds its elements in a deque of chunks of up to
+//! `chunk_len` elements, so growing it never reallocates more than one chunk,
+//! and dropping old data releases whole chunks. Only the first chunk may be
+//! partially trimmed and only the last partially filled; all others are full,
+//! which keeps indexing O(1). Chunks are sorted, so their first and last
+//! elements bound their timestamps.
+
+use std::collections::VecDeque;
+
+use crate::{
+    base::{Interval, TimeStamp},
+    element::Element,
+    sample::{Sample, SampleValue},
+    window::{self, Window},
+    RawSeries,
+};
+
+/// Default number of elements per chunk.
+pub const CHUNK_LEN: usize = 4096;
+
+/// A raw series stored in chunks, see the module docs.
+#[derive(Debug, Clone)]
+pub struct ChunkedRawSeries<T: SampleValue> {
+    chunks: VecDeque<Vec<Element<T>>>,
+    chunk_len: usize,
+    len: usize,
+}
+
+impl<T: SampleValue> ChunkedRawSeries<T> {
+    /// Create a new empty series with chunks of `CHUNK_LEN` elements.
+    pub fn new() -> Self {
+        Self::with_chunk_len(CHUNK_LEN)
+    }
+
+    /// Create a new empty series with chunks of `chunk_len` elements.
+    pub fn with_chunk_len(chunk_len: usize) -> Self {
+        assert!(chunk_len > 0, "chunk_len must be positive");
+        Self {
+            chunks: VecDeque::new(),
+            chunk_len,
+            len: 0,
+        }
+    }
+
+    /// Add a new sample to the series. The timestamp must not be less than the
+    /// last sample's timestamp; this is only checked in debug builds.
+    pub fn push(&mut self, ts: TimeStamp, value: T) {
+        self.push_sample(ts, Sample::point(value))
+    }
+
+    /// Sample variant of `push`.
+    pub fn push_sample(&mut self, ts: TimeStamp, sample: Sample<T>) {
+        debug_assert!(
+            self.last().is_none_or(|last| last.0 <= ts),
+            "out of order push at {}",
+            ts
+        );
+
+        match self.chunks.back_mut() {
+            Some(chunk) if chunk.len() < self.chunk_len => chunk.push((ts, sample).into()),
+            _ => {
+                let mut chunk = Vec::with_capacity(self.chunk_len);
+                chunk.push((ts, sample).into());
+                self.chunks.push_back(chunk);
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Returns the number of elements in the series.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the series is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of chunks.
+    pub fn num_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns the element at `index`.
+    pub fn get(&self, index: usize) -> Option<&Element<T>> {
+        let front = self.chunks.front()?;
+        if index < front.len() {
+            return front.get(index);
+        }
+
+        let index = index - front.len();
+        self.chunks
+            .get(1 + index / self.chunk_len)?
+            .get(index % self.chunk_len)
+    }
+
+    /// Returns the oldest element in the series.
+    pub fn first(&self) -> Option<&Element<T>> {
+        self.chunks.front()?.first()
+    }
+
+    /// Returns the newest element in the series.
+    pub fn last(&self) -> Option<&Element<T>> {
+        self.chunks.back()?.last()
+    }
+
+    /// Returns the timestamp of the newest element.
+    pub fn end_ts(&self) -> Option<TimeStamp> {
+        self.last().map(|e| e.0)
+    }
+
+    /// Returns the nearest sample after or equal to the given timestamp.
+    pub fn at_or_after(&self, ts: TimeStamp) -> Option<&Element<T>> {
+        let i = self
+            .chunks
+            .partition_point(|chunk| chunk[chunk.len() - 1].0 < ts);
+        let chunk = self.chunks.get(i)?;
+        chunk.get(chunk.partition_point(|e| e.0 < ts))
+    }
+
+    /// Drop all elements with a timestamp before `ts`. Whole chunks are
+    /// released, and at most one is trimmed. Returns the number of elements
+    /// removed.
+    pub fn truncate_before(&mut self, ts: TimeStamp) -> usize {
+        let len = self.len;
+
+        while let Some(chunk) = self.chunks.front_mut() {
+            if chunk[chunk.len() - 1].0 < ts {
+                self.len -= chunk.len();
+                self.chunks.pop_front();
+            } else {
+                let n = chunk.partition_point(|e| e.0 < ts);
+                chunk.drain(..n);
+                self.len -= n;
+                break;
+            }
+        }
+
+        len - self.len
+    }
+
+    /// Returns an iterator over the elements.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Element<T>> {
+        self.chunks.iter().flatten()
+    }
+
+    /// Returns the windows of the series, with the same index ranges that
+    /// `RawSeries::windows` yields for the unchunked series. Windows may span
+    /// chunks; use `get` to resolve their indices.
+    pub fn windows(
+        &self,
+        window_size: Interval,
+        start_ts: TimeStamp,
+    ) -> impl Iterator<Item = Window> + '_ {
+        window::windows_of(
+            self.iter().map(|e| e.0),
+            self.end_ts(),
+            window_size,
+            start_ts,
+        )
+    }
+
+    /// Copy the series into a contiguous `RawSeries`.
+    pub fn to_raw_series(&self) -> RawSeries<T> {
+        let mut series = RawSeries::with_capacity(self.len);
+        series.values.extend(self.iter().cloned());
+        series
+    }
+}
+
+impl<T: SampleValue> Default for ChunkedRawSeries<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: SampleValue> From<RawSeries<T>> for ChunkedRawSeries<T> {
+    fn from(series: RawSeries<T>) -> Self {
+        let mut chunked = Self::new();
+        for Element(ts, sample) in series.values {
+            chunked.push_sample(ts, sample);
+        }
+        chunked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(chunk_len: usize, timestamps: impl Iterator<Item = i64>) -> ChunkedRawSeries<i64> {
+        let mut series = ChunkedRawSeries::with_chunk_len(chunk_len);
+        for ts in timestamps {
+            series.push(TimeStamp(ts), ts);
+        }
+        series
+    }
+
+    #[test]
+    fn chunks() {
+        let mut series = series(4, (0..10).map(|i| i * 10));
+        assert_eq!(series.len(), 10);
+        assert_eq!(series.num_chunks(), 3);
+        for i in 0..10 {
+            assert_eq!(series.get(i).unwrap().1.val(), i as i64 * 10);
+        }
+        assert!(series.get(10).is_none());
+
+        assert_eq!(series.at_or_after(TimeStamp(31)).unwrap().0, TimeStamp(40));
+        assert_eq!(series.at_or_after(TimeStamp(40)).unwrap().0, TimeStamp(40));
+        assert!(series.at_or_after(TimeStamp(91)).is_none());
+
+        // Drops the first chunk and trims the second.
+        assert_eq!(series.truncate_before(TimeStamp(55)), 6);
+        assert_eq!(series.len(), 4);
+        assert_eq!(series.num_chunks(), 2);
+        assert_eq!(series.first().unwrap().0, TimeStamp(60));
+        assert_eq!(series.get(0).unwrap().0, TimeStamp(60));
+        assert_eq!(series.get(2).unwrap().0, TimeStamp(80));
+        assert_eq!(series.get(3).unwrap().0, TimeStamp(90));
+        assert!(series.get(4).is_none());
+
+        // Indexing stays correct as the last chunk fills up.
+        for ts in 100..106 {
+            series.push(TimeStamp(ts), ts);
+        }
+        assert_eq!(series.len(), 10);
+        let values = (0..10)
+            .map(|i| series.get(i).unwrap().1.val())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![60, 70, 80, 90, 100, 101, 102, 103, 104, 105]);
+        assert_eq!(series.iter().map(|e| e.1.val()).collect::<Vec<_>>(), values);
+
+        assert_eq!(series.truncate_before(TimeStamp(1000)), 10);
+        assert!(series.is_empty());
+        assert!(series.get(0).is_none());
+        assert!(series.at_or_after(TimeStamp(0)).is_none());
+    }
+
+    #[test]
+    fn windows_across_chunks() {
+        // Irregular timestamps, with duplicates and a gap.
+        let timestamps = (0..100)
+            .map(|i| i * 7 + i % 3)
+            .chain([700, 700, 1500, 1501]);
+        let chunked = series(8, timestamps);
+        let raw = chunked.to_raw_series();
+
+        for (size, start) in [(10, 0), (50, 3), (64, -100), (2000, 0)] {
+            let a = chunked
+                .windows(Interval(size), TimeStamp(start))
+                .map(|w| format!("{:?}", w))
+                .collect::<Vec<_>>();
+            let b = raw
+                .windows(Interval(size), TimeStamp(start))
+                .map(|w| format!("{:?}", w))
+                .collect::<Vec<_>>();
+            assert_eq!(a, b);
+        }
+
+        for window in chunked.windows(Interval(50), TimeStamp(0)) {
+            if let Window::Range(start, end) = window {
+                let span = chunked.get(end).unwrap().0 .0 - chunked.get(start).unwrap().0 .0;
+                assert!(span < 50);
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! Compressed in-memory storage for raw series.
+//!
+//! Elements are packed into blocks of `BLOCK_LEN`. Within a block, each
+//! element is written as two varints: the zigzagged delta-of-delta of its
+//! timestamp with the sample tag in the low two bits, then, for `Point` and
+//! `Fake` samples, the value relative to the previous value (see
+//! `Compressible`). Regularly sampled, slowly changing series take two or
+//! three bytes per element instead of `size_of::<Element<T>>()`.
+//!
+//! Reads decode one block at a time, so random access costs O(`BLOCK_LEN`).
+
+use std::marker::PhantomData;
+
+use crate::{
+    base::{Interval, TimeStamp},
+    element::Element,
+    sample::{Sample, SampleValue},
+    window::{self, Window},
+    RawSeries,
+};
+
+const BLOCK_LEN: usize = 256;
+
+const TAG_ERR: u64 = 0;
+const TAG_ZERO: u64 = 1;
+const TAG_POINT: u64 = 2;
+const TAG_FAKE: u64 = 3;
+
+/// Values that can be stored relative to the previous value.
+pub trait Compressible: SampleValue {
+    /// Encode `self` relative to `prev`, as an integer that's small when the
+    /// two are close.
+    fn delta(self, prev: Self) -> u128;
+
+    /// Inverse of `delta`.
+    fn undelta(prev: Self, delta: u128) -> Self;
+}
+
+macro_rules! compressible_int {
+    ($t:ty, $u:ty) => {
+        impl Compressible for $t {
+            fn delta(self, prev: Self) -> u128 {
+                let d = self.wrapping_sub(prev);
+                ((d << 1) ^ (d >> (<$t>::BITS - 1))) as $u as u128
+            }
+
+            fn undelta(prev: Self, delta: u128) -> Self {
+                let d = delta as $u;
+                prev.wrapping_add(((d >> 1) as $t) ^ -((d & 1) as $t))
+            }
+        }
+    };
+}
+
+// XOR of nearby floats has its set bits at the top, so reverse them.
+macro_rules! compressible_float {
+    ($t:ty, $u:ty) => {
+        impl Compressible for $t {
+            fn delta(self, prev: Self) -> u128 {
+                (self.to_bits() ^ prev.to_bits()).reverse_bits() as u128
+            }
+
+            fn undelta(prev: Self, delta: u128) -> Self {
+                <$t>::from_bits(prev.to_bits() ^ (delta as $u).reverse_bits())
+            }
+        }
+    };
+}
+
+compressible_int!(i32, u32);
+compressible_int!(i64, u64);
+compressible_int!(i128, u128);
+compressible_float!(f32, u32);
+compressible_float!(f64, u64);
+
+fn write_varint(bytes: &mut Vec<u8>, mut v: u128) {
+    while v >= 0x80 {
+        bytes.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    bytes.push(v as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> u128 {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        v |= ((byte & 0x7f) as u128) << shift;
+        if byte < 0x80 {
+            return v;
+        }
+        shift += 7;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    start_ts: TimeStamp,
+    end_ts: TimeStamp,
+    len: usize,
+    bytes: Vec<u8>,
+}
+
+impl Block {
+    fn encode<T: Compressible>(elements: &[Element<T>]) -> Self {
+        let mut bytes = vec![];
+        let mut prev_ts = elements[0].0.millis();
+        let mut prev_delta = 0i64;
+        let mut prev_value = T::zero();
+
+        for Element(ts, sample) in elements {
+            let delta = ts.millis().wrapping_sub(prev_ts);
+            let dod = delta.wrapping_sub(prev_delta);
+            let zigzag = ((dod << 1) ^ (dod >> 63)) as u64;
+            prev_ts = ts.millis();
+            prev_delta = delta;
+
+            let (tag, value) = match sample {
+                Sample::Err => (TAG_ERR, None),
+                Sample::Zero => (TAG_ZERO, None),
+                Sample::Point(v) => (TAG_POINT, Some(*v)),
+                Sample::Fake(v) => (TAG_FAKE, Some(*v)),
+            };
+            write_varint(&mut bytes, ((zigzag as u128) << 2) | tag as u128);
+
+            if let Some(v) = value {
+                write_varint(&mut bytes, v.delta(prev_value));
+                prev_value = v;
+            }
+        }
+
+        bytes.shrink_to_fit();
+        Self {
+            start_ts: elements[0].0,
+            end_ts: elements[elements.len() - 1].0,
+            len: elements.len(),
+            bytes,
+        }
+    }
+
+    fn decode<T: Compressible>(&self) -> Vec<Element<T>> {
+        let mut elements = Vec::with_capacity(self.len);
+        let mut pos = 0;
+        let mut prev_ts = self.start_ts.millis();
+        let mut prev_delta = 0i64;
+        let mut prev_value = T::zero();
+
+        for _ in 0..self.len {
+            let head = read_varint(&self.bytes, &mut pos);
+            let zigzag = (head >> 2) as u64;
+            let dod = ((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64);
+            prev_delta = prev_delta.wrapping_add(dod);
+            prev_ts = prev_ts.wrapping_add(prev_delta);
+
+            let sample = match head as u64 & 0b11 {
+                TAG_ERR => Sample::Err,
+                TAG_ZERO => Sample::Zero,
+                tag => {
+                    prev_value = T::undelta(prev_value, read_varint(&self.bytes, &mut pos));
+                    if tag == TAG_POINT {
+                        Sample::Point(prev_value)
+                    } else {
+                        Sample::Fake(prev_value)
+                    }
+                }
+            };
+            elements.push((TimeStamp(prev_ts), sample).into());
+        }
+
+        elements
+    }
+}
+
+/// A read-only, compressed copy of a `RawSeries`, see `RawSeries::compress`.
+#[derive(Debug, Clone)]
+pub struct CompressedSeries<T: Compressible> {
+    blocks: Vec<Block>,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Compressible> CompressedSeries<T> {
+    /// Returns the number of elements in the series.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the series is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the approximate number of bytes used by the series.
+    pub fn size_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self
+                .blocks
+                .iter()
+                .map(|b| std::mem::size_of::<Block>() + b.bytes.capacity())
+                .sum::<usize>()
+    }
+
+    /// Returns the element at `index`.
+    pub fn get(&self, index: usize) -> Option<Element<T>> {
+        let block = self.blocks.get(index / BLOCK_LEN)?;
+        block.decode().into_iter().nth(index % BLOCK_LEN)
+    }
+
+    /// Returns the timestamp of the newest element.
+    pub fn end_ts(&self) -> Option<TimeStamp> {
+        self.blocks.last().map(|b| b.end_ts)
+    }
+
+    /// Returns the nearest sample after or equal to the given timestamp.
+    pub fn at_or_after(&self, ts: TimeStamp) -> Option<Element<T>> {
+        let i = self.blocks.partition_point(|b| b.end_ts < ts);
+        let block = self.blocks.get(i)?;
+        block.decode().into_iter().find(|e| e.0 >= ts)
+    }
+
+    /// Returns an iterator over the elements, decoding a block at a time.
+    pub fn iter(&self) -> impl Iterator<Item = Element<T>> + '_ {
+        self.blocks.iter().flat_map(|b| b.decode())
+    }
+
+    /// Returns the windows of the series, with the same index ranges that
+    /// `RawSeries::windows` yields for the uncompressed series.
+    pub fn windows(
+        &self,
+        window_size: Interval,
+        start_ts: TimeStamp,
+    ) -> impl Iterator<Item = Window> + '_ {
+        window::windows_of(
+            self.iter().map(|e| e.0),
+            self.end_ts(),
+            window_size,
+            start_ts,
+        )
+    }
+
+    /// Decode back into a `RawSeries`.
+    pub fn decompress(&self) -> RawSeries<T> {
+        let mut series = RawSeries::with_capacity(self.len);
+        series.values.extend(self.iter());
+        series
+    }
+}
+
+impl<T: Compressible> RawSeries<T> {
+    /// Returns a compressed copy of the series.
+    pub fn compress(&self) -> CompressedSeries<T> {
+        CompressedSeries {
+            blocks: self.values.chunks(BLOCK_LEN).map(Block::encode).collect(),
+            len: self.len(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same<T: Compressible>(a: &RawSeries<T>, b: &RawSeries<T>) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert_eq!(x.0, y.0);
+            assert!(x.1.approx_eq(&y.1, 0.0), "{} != {}", x, y);
+        }
+    }
+
+    #[test]
+    fn round_trip_i64() {
+        // A day of one-second counter samples, with some jitter and gaps.
+        let mut series = RawSeries::new();
+        let mut value = 0i64;
+        for i in 0..86_400i64 {
+            let ts = TimeStamp(1_700_000_000_000 + i * 1000 + (i % 7) * 3);
+            value += i % 13;
+            let sample = match i {
+                _ if i % 5000 == 0 => Sample::Err,
+                _ if i % 7001 == 0 => Sample::Zero,
+                _ if i % 3001 == 0 => Sample::Fake(-value),
+                _ => Sample::Point(value),
+            };
+            series.push_sample(ts, sample);
+        }
+        series.push(TimeStamp(i64::MAX), i64::MIN);
+
+        let compressed = series.compress();
+        assert_eq!(compressed.len(), series.len());
+        assert_same(&compressed.decompress(), &series);
+
+        let raw_size = series.len() * std::mem::size_of::<Element<i64>>();
+        assert!(
+            compressed.size_bytes() * 5 < raw_size,
+            "{} compressed bytes for {} raw bytes",
+            compressed.size_bytes(),
+            raw_size
+        );
+    }
+
+    #[test]
+    fn round_trip_floats() {
+        let mut series = RawSeries::new();
+        for i in 0..1000 {
+            series.push(TimeStamp(i * 10), (i / 10) as f64 * 0.5);
+        }
+        series.push(TimeStamp(10_000), f64::NAN);
+        series.push(TimeStamp(10_000), -0.0);
+        series.push(TimeStamp(10_001), f64::MAX);
+
+        let decompressed = series.compress().decompress();
+        assert_eq!(decompressed.len(), series.len());
+        for (x, y) in decompressed.iter().zip(series.iter()) {
+            assert_eq!(x.0, y.0);
+            assert_eq!(x.1.val().to_bits(), y.1.val().to_bits());
+        }
+
+        let series =
+            RawSeries::<f32>::from_vec(vec![(TimeStamp(5), 1.5), (TimeStamp(6), -2.0)], false)
+                .unwrap();
+        assert_same(&series.compress().decompress(), &series);
+
+        let empty = RawSeries::<i32>::new().compress();
+        assert!(empty.is_empty());
+        assert!(empty.get(0).is_none());
+        assert!(empty.at_or_after(TimeStamp(0)).is_none());
+        assert_eq!(empty.windows(Interval(10), TimeStamp(0)).count(), 0);
+    }
+
+    #[test]
+    fn read_api() {
+        let mut series = RawSeries::new();
+        for i in 0..1000i64 {
+            // Leave a gap so some windows are empty.
+            if !(400..450).contains(&i) {
+                series.push(TimeStamp(i * 100 + i % 3), i);
+            }
+        }
+        let compressed = series.compress();
+
+        for i in [0, 1, 255, 256, 257, 949] {
+            let (a, b) = (compressed.get(i).unwrap(), series.get(i).unwrap());
+            assert_eq!(a.0, b.0);
+            assert_eq!(a.1.val(), b.1.val());
+        }
+        assert!(compressed.get(950).is_none());
+
+        for ts in [-5, 0, 1, 25_600, 25_601, 40_000, 99_900, 99_901] {
+            let a = compressed.at_or_after(TimeStamp(ts)).map(|e| e.0);
+            let b = series.at_or_after(TimeStamp(ts)).map(|e| e.0);
+            assert_eq!(a, b, "at_or_after({})", ts);
+        }
+        assert!(compressed.at_or_after(TimeStamp(99_903)).is_none());
+
+        for (size, start) in [(1000, 0), (350, 120), (10_000, -500)] {
+            let a = compressed
+                .windows(Interval(size), TimeStamp(start))
+                .map(|w| format!("{:?}", w))
+                .collect::<Vec<_>>();
+            let b = series
+                .windows(Interval(size), TimeStamp(start))
+                .map(|w| format!("{:?}", w))
+                .collect::<Vec<_>>();
+            assert_eq!(a, b);
+        }
+    }
+}
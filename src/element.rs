@@ -20,3 +20,91 @@ impl<T: SampleValue> fmt::Display for Element<T> {
         write!(f, "{} {}", self.0, self.1)
     }
 }
+
+/// Elements serialize compactly as `[ts, value]` pairs. `Point` values are
+/// written bare and other samples tagged, as `Sample` serializes them, e.g.
+/// `[1000, 3]` and `[2000, {"Err": null}]`.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Compact<T: SampleValue> {
+        Point(T),
+        Tagged(Sample<T>),
+    }
+
+    impl<T: SampleValue + Serialize> Serialize for Element<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match &self.1 {
+                Sample::Point(v) => (self.0, v).serialize(serializer),
+                sample => (self.0, sample).serialize(serializer),
+            }
+        }
+    }
+
+    impl<'de, T: SampleValue + Deserialize<'de>> Deserialize<'de> for Element<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let (ts, compact) = <(TimeStamp, Compact<T>)>::deserialize(deserializer)?;
+            Ok(match compact {
+                Compact::Point(v) => Self(ts, Sample::Point(v)),
+                Compact::Tagged(sample) => Self(ts, sample),
+            })
+        }
+    }
+}
+
+/// A change in value between consecutive valid samples, see
+/// `AlignedSeries::changes` and `RawSeries::changes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent<T: SampleValue> {
+    /// Timestamp of the sample with the new value.
+    pub ts: TimeStamp,
+    pub from: T,
+    pub to: T,
+}
+
+/// Returns an event for every sample whose value differs from the previous
+/// valid sample by more than `eps`. `Err` samples are skipped, and so are
+/// `Fake` samples if `skip_fake` is set.
+///
+/// Values are compared in `T` first, so with an `eps` of zero every change
+/// is reported, even between integers too large for an exact `f64`. A
+/// positive `eps` is checked against the difference in `f64`.
+pub(crate) fn changes<T: SampleValue>(
+    samples: impl Iterator<Item = (TimeStamp, Sample<T>)>,
+    eps: f64,
+    skip_fake: bool,
+) -> Vec<ChangeEvent<T>> {
+    let mut events = vec![];
+    let mut prev: Option<T> = None;
+
+    for (ts, sample) in samples {
+        if sample.is_err() || (skip_fake && matches!(sample, Sample::Fake(_))) {
+            continue;
+        }
+
+        let v = sample.val();
+        if let Some(p) = prev {
+            let diff = match (v.to_f64(), p.to_f64()) {
+                (Some(v), Some(p)) => (v - p).abs(),
+                _ => f64::INFINITY,
+            };
+
+            if v != p && (eps <= 0.0 || diff > eps) {
+                events.push(ChangeEvent { ts, from: p, to: v });
+            } else {
+                // Stay anchored to the last reported value, so slow drift
+                // below eps still surfaces once it adds up.
+                continue;
+            }
+        }
+
+        prev = Some(v);
+    }
+
+    events
+}
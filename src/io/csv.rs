@@ -0,0 +1,174 @@
+//! CSV export of series.
+//!
+//! Each element becomes a `timestamp_millis,value,kind` row, after a header
+//! row of the same names. `kind` is one of `point`, `zero`, `err` or `fake`;
+//! `zero` and `err` rows have an empty value.
+
+use std::{
+    io::{BufRead, Write},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    base::TimeStamp,
+    sample::{Sample, SampleValue},
+    AlignedSeries, RawSeries,
+};
+
+const HEADER: &str = "timestamp_millis,value,kind";
+
+fn write_rows<W: Write, T: SampleValue>(
+    w: &mut W,
+    elements: impl Iterator<Item = (TimeStamp, Sample<T>)>,
+) -> std::io::Result<()> {
+    writeln!(w, "{}", HEADER)?;
+
+    for (ts, sample) in elements {
+        match sample {
+            Sample::Err => writeln!(w, "{},,err", ts.millis())?,
+            Sample::Zero => writeln!(w, "{},,zero", ts.millis())?,
+            Sample::Point(v) => writeln!(w, "{},{},point", ts.millis(), v)?,
+            Sample::Fake(v) => writeln!(w, "{},{},fake", ts.millis(), v)?,
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_row<T: SampleValue + FromStr>(line: &str) -> Result<(TimeStamp, Sample<T>)> {
+    let fields = line.trim_end().split(',').collect::<Vec<_>>();
+    let [ts, value, kind] = fields[..] else {
+        bail!("expected 3 fields in row {:?}", line);
+    };
+
+    let ts = TimeStamp(ts.parse()?);
+    let value = || -> Result<T> {
+        value
+            .parse()
+            .map_err(|_| anyhow!("invalid value {:?}", value))
+    };
+
+    let sample = match kind {
+        "err" => Sample::Err,
+        "zero" => Sample::Zero,
+        "point" => Sample::Point(value()?),
+        "fake" => Sample::Fake(value()?),
+        _ => bail!("unknown sample kind {:?}", kind),
+    };
+    Ok((ts, sample))
+}
+
+impl<T: SampleValue> RawSeries<T> {
+    /// Write the series as CSV.
+    pub fn to_csv<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        write_rows(w, self.iter().map(|e| (e.0, e.1)))
+    }
+
+    /// Read a series written by `to_csv`. Values are parsed directly as `T`,
+    /// so integer series round-trip exactly.
+    pub fn from_csv<R: BufRead>(r: R) -> Result<Self>
+    where
+        T: FromStr,
+    {
+        let mut lines = r.lines();
+        let header = lines.next().transpose()?;
+        if header.as_deref().map(str::trim_end) != Some(HEADER) {
+            bail!("missing CSV header");
+        }
+
+        let mut elements = vec![];
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            elements.push(parse_row(&line)?.into());
+        }
+        Ok(Self::from_elements(elements, false)?)
+    }
+}
+
+impl<T: SampleValue> AlignedSeries<T> {
+    /// Write the series as CSV.
+    pub fn to_csv<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        write_rows(w, self.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sample::SampleEquals, Interval};
+
+    #[test]
+    fn raw_round_trip() {
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(10), 1.5);
+        series.push_sample(TimeStamp(20), Sample::Zero);
+        series.push_sample(TimeStamp(35), Sample::Err);
+        series.push_sample(TimeStamp(40), Sample::Fake(-2.25));
+
+        let mut buf = vec![];
+        series.to_csv(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf.clone()).unwrap(),
+            "timestamp_millis,value,kind\n10,1.5,point\n20,,zero\n35,,err\n40,-2.25,fake\n"
+        );
+
+        let decoded = RawSeries::<f64>::from_csv(buf.as_slice()).unwrap();
+        assert_eq!(decoded.len(), series.len());
+        for (a, b) in decoded.iter().zip(series.iter()) {
+            assert_eq!(a.0, b.0);
+            assert!(a.1.approx_eq(&b.1, 0.0));
+        }
+
+        assert!(RawSeries::<f64>::from_csv("10,1,point\n".as_bytes()).is_err());
+        assert!(
+            RawSeries::<f64>::from_csv("timestamp_millis,value,kind\n10,1\n".as_bytes()).is_err()
+        );
+        assert!(
+            RawSeries::<f64>::from_csv("timestamp_millis,value,kind\n10,1,nope\n".as_bytes())
+                .is_err()
+        );
+        assert!(RawSeries::<f64>::from_csv(
+            "timestamp_millis,value,kind\n20,1,point\n10,1,point\n".as_bytes()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn raw_round_trip_integers() {
+        let big = i64::MAX - 1;
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(10), big);
+        series.push_sample(TimeStamp(20), Sample::Fake(-7));
+
+        let mut buf = vec![];
+        series.to_csv(&mut buf).unwrap();
+        let decoded = RawSeries::<i64>::from_csv(buf.as_slice()).unwrap();
+        assert!(decoded.values[0].1.equals(&Sample::Point(big)));
+        assert!(matches!(decoded.values[1].1, Sample::Fake(-7)));
+
+        assert!(RawSeries::<i64>::from_csv(
+            "timestamp_millis,value,kind\n10,1.5,point\n".as_bytes()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn aligned_timestamps() {
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        series.push(3);
+        series.push_sample(Sample::Err);
+        series.push_sample(Sample::Fake(5));
+
+        let mut buf = vec![];
+        series.to_csv(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "timestamp_millis,value,kind\n1000,3,point\n1100,,err\n1200,5,fake\n"
+        );
+    }
+}
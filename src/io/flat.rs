@@ -0,0 +1,316 @@
+//! Flat, numpy-friendly export of series.
+//!
+//! A series is flattened into three parallel arrays: timestamps (`i64`
+//! milliseconds), values (`f64`) and flags (`u8`, the `Sample` variant). The
+//! binary form written by `write_flat` is a 16 byte header followed by the
+//! three arrays, all little-endian:
+//!
+//! ```text
+//! bytes 0..8    element count (u64)
+//! bytes 8..11   dtype codes for the arrays: b'q' (i64), b'd' (f64), b'B' (u8)
+//! bytes 11..16  zero padding
+//! ...           count * i64 timestamps
+//! ...           count * f64 values
+//! ...           count * u8 flags
+//! ```
+//!
+//! With numpy: `np.fromfile(f, dtype='<i8', count=n, offset=16)` and so on.
+
+use std::io::{Read, Write};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    base::{Interval, TimeStamp},
+    sample::{Sample, SampleValue},
+    AlignedSeries, RawSeries,
+};
+
+pub const FLAG_ERR: u8 = 0;
+pub const FLAG_ZERO: u8 = 1;
+pub const FLAG_POINT: u8 = 2;
+pub const FLAG_FAKE: u8 = 3;
+
+const HEADER_LEN: usize = 16;
+const DTYPES: [u8; 3] = [b'q', b'd', b'B'];
+
+/// Bytes per element across the three arrays.
+const ELEMENT_LEN: u64 = 8 + 8 + 1;
+
+/// Parallel timestamp, value and flag arrays.
+pub type FlatArrays = (Vec<i64>, Vec<f64>, Vec<u8>);
+
+fn flatten<T: SampleValue>(
+    elements: impl ExactSizeIterator<Item = (TimeStamp, Sample<T>)>,
+) -> FlatArrays {
+    let mut timestamps = Vec::with_capacity(elements.len());
+    let mut values = Vec::with_capacity(elements.len());
+    let mut flags = Vec::with_capacity(elements.len());
+
+    for (ts, sample) in elements {
+        let (value, flag) = match sample {
+            Sample::Err => (0.0, FLAG_ERR),
+            Sample::Zero => (0.0, FLAG_ZERO),
+            Sample::Point(v) => (v.to_f64().unwrap_or(f64::NAN), FLAG_POINT),
+            Sample::Fake(v) => (v.to_f64().unwrap_or(f64::NAN), FLAG_FAKE),
+        };
+
+        timestamps.push(ts.millis());
+        values.push(value);
+        flags.push(flag);
+    }
+
+    (timestamps, values, flags)
+}
+
+fn unflatten_sample<T: SampleValue>(value: f64, flag: u8) -> Result<Sample<T>> {
+    let cast = || match T::from(value) {
+        Some(v) => Ok(v),
+        None => bail!("value {} is not representable", value),
+    };
+
+    Ok(match flag {
+        FLAG_ERR => Sample::Err,
+        FLAG_ZERO => Sample::Zero,
+        FLAG_POINT => Sample::Point(cast()?),
+        FLAG_FAKE => Sample::Fake(cast()?),
+        _ => bail!("unknown sample flag {}", flag),
+    })
+}
+
+fn check_lengths(timestamps: &[i64], values: &[f64], flags: &[u8]) -> Result<()> {
+    if timestamps.len() != values.len() || timestamps.len() != flags.len() {
+        bail!(
+            "array lengths differ: {} timestamps, {} values, {} flags",
+            timestamps.len(),
+            values.len(),
+            flags.len()
+        );
+    }
+    Ok(())
+}
+
+fn write_arrays<W: Write>(w: &mut W, (timestamps, values, flags): &FlatArrays) -> Result<()> {
+    let mut header = [0u8; HEADER_LEN];
+    header[0..8].copy_from_slice(&(timestamps.len() as u64).to_le_bytes());
+    header[8..11].copy_from_slice(&DTYPES);
+    w.write_all(&header)?;
+
+    for ts in timestamps {
+        w.write_all(&ts.to_le_bytes())?;
+    }
+    for v in values {
+        w.write_all(&v.to_le_bytes())?;
+    }
+    w.write_all(flags)?;
+
+    Ok(())
+}
+
+fn read_arrays<R: Read>(r: &mut R) -> Result<FlatArrays> {
+    let mut header = [0u8; HEADER_LEN];
+    r.read_exact(&mut header)?;
+
+    if header[8..11] != DTYPES {
+        bail!("unexpected dtype codes {:?}", &header[8..11]);
+    }
+    let count = u64::from_le_bytes(header[0..8].try_into()?);
+
+    // Read the arrays before allocating for them, so a corrupt count fails
+    // on the input length instead of on a huge allocation.
+    let Some(body_len) = count.checked_mul(ELEMENT_LEN) else {
+        bail!("element count {} exceeds input length", count);
+    };
+    let mut body = vec![];
+    r.take(body_len).read_to_end(&mut body)?;
+    if (body.len() as u64) < body_len {
+        bail!("element count {} exceeds input length", count);
+    }
+
+    let count = count as usize;
+    let (timestamps, rest) = body.split_at(count * 8);
+    let (values, flags) = rest.split_at(count * 8);
+    let word = |chunk: &[u8]| -> [u8; 8] { chunk.try_into().unwrap() };
+
+    Ok((
+        timestamps
+            .chunks_exact(8)
+            .map(|c| i64::from_le_bytes(word(c)))
+            .collect(),
+        values
+            .chunks_exact(8)
+            .map(|c| f64::from_le_bytes(word(c)))
+            .collect(),
+        flags.to_vec(),
+    ))
+}
+
+impl<T: SampleValue> RawSeries<T> {
+    /// Returns parallel timestamp, value and flag arrays for the series.
+    pub fn to_flat_f64(&self) -> FlatArrays {
+        flatten(self.values.iter().map(|e| (e.0, e.1)))
+    }
+
+    /// Build a series from parallel timestamp, value and flag arrays. The
+    /// timestamps must not decrease.
+    pub fn from_flat(timestamps: &[i64], values: &[f64], flags: &[u8]) -> Result<Self> {
+        check_lengths(timestamps, values, flags)?;
+
+        let mut series = Self::new();
+        for i in 0..timestamps.len() {
+            if i > 0 && timestamps[i] < timestamps[i - 1] {
+                bail!("timestamp {} at index {} is out of order", timestamps[i], i);
+            }
+            series.push_sample(
+                TimeStamp(timestamps[i]),
+                unflatten_sample(values[i], flags[i])?,
+            );
+        }
+        Ok(series)
+    }
+
+    /// Write the series in the flat binary format.
+    pub fn write_flat<W: Write>(&self, w: &mut W) -> Result<()> {
+        write_arrays(w, &self.to_flat_f64())
+    }
+
+    /// Read a series written by `write_flat`.
+    pub fn read_flat<R: Read>(r: &mut R) -> Result<Self> {
+        let (timestamps, values, flags) = read_arrays(r)?;
+        Self::from_flat(&timestamps, &values, &flags)
+    }
+}
+
+impl<T: SampleValue> AlignedSeries<T> {
+    /// Returns parallel timestamp, value and flag arrays for the series.
+    pub fn to_flat_f64(&self) -> FlatArrays {
+        flatten(self.iter())
+    }
+
+    /// Build a series from parallel timestamp, value and flag arrays. The
+    /// timestamps must be spaced exactly `interval` apart.
+    pub fn from_flat(
+        interval: Interval,
+        timestamps: &[i64],
+        values: &[f64],
+        flags: &[u8],
+    ) -> Result<Self> {
+        check_lengths(timestamps, values, flags)?;
+
+        let start_ts = TimeStamp(timestamps.first().copied().unwrap_or(0));
+        let mut series = Self::new(interval, start_ts);
+        for i in 0..timestamps.len() {
+            if timestamps[i] != start_ts.millis() + (i as i64 * interval.millis()) {
+                bail!("timestamp {} at index {} is not aligned", timestamps[i], i);
+            }
+            series.push_sample(unflatten_sample(values[i], flags[i])?);
+        }
+        Ok(series)
+    }
+
+    /// Write the series in the flat binary format.
+    pub fn write_flat<W: Write>(&self, w: &mut W) -> Result<()> {
+        write_arrays(w, &self.to_flat_f64())
+    }
+
+    /// Read a series written by `write_flat`.
+    pub fn read_flat<R: Read>(interval: Interval, r: &mut R) -> Result<Self> {
+        let (timestamps, values, flags) = read_arrays(r)?;
+        Self::from_flat(interval, &timestamps, &values, &flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_round_trip() {
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(10), 1.5);
+        series.push_sample(TimeStamp(20), Sample::Zero);
+        series.push_sample(TimeStamp(35), Sample::Err);
+        series.push_sample(TimeStamp(40), Sample::Fake(-2.25));
+
+        let (timestamps, values, flags) = series.to_flat_f64();
+        assert_eq!(timestamps, vec![10, 20, 35, 40]);
+        assert_eq!(values, vec![1.5, 0.0, 0.0, -2.25]);
+        assert_eq!(flags, vec![FLAG_POINT, FLAG_ZERO, FLAG_ERR, FLAG_FAKE]);
+
+        let mut buf = vec![];
+        series.write_flat(&mut buf).unwrap();
+        assert_eq!(buf.len(), HEADER_LEN + 4 * 17);
+
+        let decoded = RawSeries::<f64>::read_flat(&mut buf.as_slice()).unwrap();
+        assert_eq!(decoded.len(), series.len());
+        for (a, b) in decoded.values.iter().zip(series.values.iter()) {
+            assert_eq!(a.0, b.0);
+            assert!(a.1.approx_eq(&b.1, 0.0));
+        }
+
+        assert!(RawSeries::<f64>::from_flat(&[1, 2], &[1.0], &[FLAG_POINT]).is_err());
+        assert!(RawSeries::<f64>::from_flat(&[1], &[1.0], &[9]).is_err());
+        assert!(
+            RawSeries::<f64>::from_flat(&[2, 1], &[1.0, 2.0], &[FLAG_POINT, FLAG_POINT]).is_err()
+        );
+    }
+
+    #[test]
+    fn corrupt_count() {
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(10), 1.5);
+        let mut buf = vec![];
+        series.write_flat(&mut buf).unwrap();
+
+        // A count past the input, or one that overflows the byte length,
+        // fails without allocating for it.
+        for count in [2, u64::MAX / 8, u64::MAX] {
+            let mut corrupt = buf.clone();
+            corrupt[0..8].copy_from_slice(&count.to_le_bytes());
+            assert!(RawSeries::<f64>::read_flat(&mut corrupt.as_slice()).is_err());
+        }
+
+        // Truncated input fails too.
+        assert!(RawSeries::<f64>::read_flat(&mut &buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn aligned_round_trip() {
+        let mut series = AlignedSeries::new(Interval(100), TimeStamp(1000));
+        series.push(3);
+        series.push_sample(Sample::Err);
+        series.push_sample(Sample::Fake(5));
+
+        let mut buf = vec![];
+        series.write_flat(&mut buf).unwrap();
+
+        #[rustfmt::skip]
+        let golden: Vec<u8> = vec![
+            // header: count, dtypes, padding
+            3, 0, 0, 0, 0, 0, 0, 0, b'q', b'd', b'B', 0, 0, 0, 0, 0,
+            // timestamps: 1000, 1100, 1200
+            0xe8, 0x03, 0, 0, 0, 0, 0, 0,
+            0x4c, 0x04, 0, 0, 0, 0, 0, 0,
+            0xb0, 0x04, 0, 0, 0, 0, 0, 0,
+            // values: 3.0, 0.0, 5.0
+            0, 0, 0, 0, 0, 0, 0x08, 0x40,
+            0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0x14, 0x40,
+            // flags: Point, Err, Fake
+            2, 0, 3,
+        ];
+        assert_eq!(buf, golden);
+
+        let decoded = AlignedSeries::<i64>::read_flat(Interval(100), &mut buf.as_slice()).unwrap();
+        assert!(decoded.approx_eq(&series, 0.0));
+
+        // Misaligned timestamps are rejected.
+        assert!(AlignedSeries::<i64>::from_flat(
+            Interval(100),
+            &[1000, 1150],
+            &[1.0, 2.0],
+            &[FLAG_POINT, FLAG_POINT]
+        )
+        .is_err());
+    }
+}
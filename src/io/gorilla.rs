@@ -0,0 +1,264 @@
+//! Gorilla-style compression of aligned series.
+//!
+//! Timestamps of an aligned series are implicit, so only `start_ts`,
+//! `interval` and the sample count are stored, in a 24 byte little-endian
+//! header. Each sample follows as a bit stream:
+//!
+//! ```text
+//! 2 bits    tag: 00 Err, 01 Zero, 10 Point, 11 Fake
+//! ...       for Point and Fake, the value XORed with the previous value:
+//!           0                        same value
+//!           10 <bits>                meaningful bits fit the previous window
+//!           11 <5> <6> <bits>        new window: leading zeros, length
+//! ```
+//!
+//! The previous value starts at `0.0` and is only updated by Point and Fake
+//! samples. The stream is padded with zero bits to a whole byte.
+
+use anyhow::{bail, Result};
+
+use crate::{
+    base::{Interval, TimeStamp},
+    sample::Sample,
+    AlignedSeries,
+};
+
+const HEADER_LEN: usize = 24;
+
+const TAG_ERR: u64 = 0b00;
+const TAG_ZERO: u64 = 0b01;
+const TAG_POINT: u64 = 0b10;
+const TAG_FAKE: u64 = 0b11;
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, len: u32) {
+        for i in (0..len).rev() {
+            if self.bits == 0 {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> self.bits;
+            }
+            self.bits = (self.bits + 1) % 8;
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, len: u32) -> Result<u64> {
+        let mut value = 0;
+        for _ in 0..len {
+            let Some(byte) = self.bytes.get(self.pos / 8) else {
+                bail!("unexpected end of input");
+            };
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | bit as u64;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Compress an aligned series.
+pub fn encode(series: &AlignedSeries<f64>) -> Vec<u8> {
+    let mut w = BitWriter {
+        bytes: Vec::with_capacity(HEADER_LEN + series.len() / 2),
+        bits: 0,
+    };
+    w.bytes
+        .extend_from_slice(&series.start_ts.millis().to_le_bytes());
+    w.bytes
+        .extend_from_slice(&series.interval.millis().to_le_bytes());
+    w.bytes
+        .extend_from_slice(&(series.len() as u64).to_le_bytes());
+
+    let mut prev = 0u64;
+    // Leading zeros and length of the previous meaningful bit window.
+    let mut window: Option<(u32, u32)> = None;
+
+    for sample in series.values.iter() {
+        let (tag, value) = match sample {
+            Sample::Err => (TAG_ERR, None),
+            Sample::Zero => (TAG_ZERO, None),
+            Sample::Point(v) => (TAG_POINT, Some(v)),
+            Sample::Fake(v) => (TAG_FAKE, Some(v)),
+        };
+        w.write(tag, 2);
+
+        let Some(value) = value else {
+            continue;
+        };
+        let bits = value.to_bits();
+        let xor = bits ^ prev;
+        prev = bits;
+
+        if xor == 0 {
+            w.write(0, 1);
+            continue;
+        }
+
+        let leading = xor.leading_zeros().min(31);
+        let trailing = xor.trailing_zeros();
+        match window {
+            Some((prev_leading, prev_len))
+                if leading >= prev_leading && trailing >= 64 - prev_leading - prev_len =>
+            {
+                w.write(0b10, 2);
+                w.write(xor >> (64 - prev_leading - prev_len), prev_len);
+            }
+            _ => {
+                let len = 64 - leading - trailing;
+                w.write(0b11, 2);
+                w.write(leading as u64, 5);
+                // A length of 64 doesn't fit in 6 bits and is written as 0.
+                w.write(len as u64 % 64, 6);
+                w.write(xor >> trailing, len);
+                window = Some((leading, len));
+            }
+        }
+    }
+
+    w.bytes
+}
+
+/// Decompress a series written by `encode`.
+pub fn decode(bytes: &[u8]) -> Result<AlignedSeries<f64>> {
+    if bytes.len() < HEADER_LEN {
+        bail!("input shorter than the header");
+    }
+    let field = |i: usize| i64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+    let start_ts = TimeStamp(field(0));
+    let interval = Interval(field(1));
+    let count = field(2) as u64;
+
+    let mut r = BitReader {
+        bytes: &bytes[HEADER_LEN..],
+        pos: 0,
+    };
+    // Each sample takes at least 2 bits, so this bounds the allocation.
+    if count > (r.bytes.len() as u64) * 4 {
+        bail!("sample count {} exceeds input length", count);
+    }
+
+    let mut series = AlignedSeries::with_capacity(interval, start_ts, count as usize);
+    let mut prev = 0u64;
+    let mut window: Option<(u32, u32)> = None;
+
+    for _ in 0..count {
+        let tag = r.read(2)?;
+        if tag == TAG_ERR || tag == TAG_ZERO {
+            series.push_sample(if tag == TAG_ERR {
+                Sample::Err
+            } else {
+                Sample::Zero
+            });
+            continue;
+        }
+
+        if r.read(1)? == 1 {
+            let (leading, len) = if r.read(1)? == 0 {
+                match window {
+                    Some(window) => window,
+                    None => bail!("value reuses a window before one was set"),
+                }
+            } else {
+                let leading = r.read(5)? as u32;
+                let len = match r.read(6)? as u32 {
+                    0 => 64,
+                    len => len,
+                };
+                if leading + len > 64 {
+                    bail!("invalid value window");
+                }
+                window = Some((leading, len));
+                (leading, len)
+            };
+            prev ^= r.read(len)? << (64 - leading - len);
+        }
+
+        let value = f64::from_bits(prev);
+        series.push_sample(if tag == TAG_POINT {
+            Sample::Point(value)
+        } else {
+            Sample::Fake(value)
+        });
+    }
+
+    Ok(series)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut series = AlignedSeries::new(Interval::from_secs(10), TimeStamp(1_700_000_000_000));
+        for i in 0..10_000 {
+            let sample = match i {
+                _ if i % 997 == 0 => Sample::Err,
+                _ if i % 1009 == 0 => Sample::Zero,
+                _ if i % 503 == 0 => Sample::Fake(f64::NAN),
+                // Slowly changing values with some noise now and then.
+                _ if i % 50 == 0 => Sample::Point((i / 100) as f64 + 0.123456789),
+                _ => Sample::Point((i / 100) as f64 * 1.5),
+            };
+            series.push_sample(sample);
+        }
+
+        let bytes = encode(&series);
+        assert!(
+            bytes.len() < 2 * series.len(),
+            "{} bytes for {} samples",
+            bytes.len(),
+            series.len()
+        );
+
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.start_ts, series.start_ts);
+        assert_eq!(decoded.interval, series.interval);
+        assert_eq!(decoded.len(), series.len());
+        for (a, b) in decoded.values.iter().zip(series.values.iter()) {
+            match (a, b) {
+                (Sample::Point(x), Sample::Point(y)) | (Sample::Fake(x), Sample::Fake(y)) => {
+                    assert_eq!(x.to_bits(), y.to_bits())
+                }
+                (Sample::Err, Sample::Err) | (Sample::Zero, Sample::Zero) => {}
+                _ => panic!("{} != {}", a, b),
+            }
+        }
+    }
+
+    #[test]
+    fn edge_cases() {
+        let empty = AlignedSeries::<f64>::new(Interval(5), TimeStamp(0));
+        let bytes = encode(&empty);
+        assert_eq!(bytes.len(), HEADER_LEN);
+        assert!(decode(&bytes).unwrap().is_empty());
+
+        // Values whose XOR needs all 64 bits.
+        let mut series = AlignedSeries::new(Interval(5), TimeStamp(0));
+        for v in [-1.0, f64::MIN_POSITIVE, -f64::MAX, 0.0, -0.0] {
+            series.push(v);
+        }
+        let decoded = decode(&encode(&series)).unwrap();
+        for (a, b) in decoded.values.iter().zip(series.values.iter()) {
+            assert_eq!(a.val().to_bits(), b.val().to_bits());
+        }
+
+        assert!(decode(&[0; 10]).is_err());
+        let mut truncated = encode(&series);
+        truncated.pop();
+        assert!(decode(&truncated).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! Import of Grafana-style JSON time series.
+//!
+//! Grafana exports a series as a `datapoints` array of `[value, timestamp_ms]`
+//! pairs, either bare or inside a `{"target": ..., "datapoints": [...]}`
+//! object. `null` values mark missing data.
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{base::TimeStamp, sample::Sample, RawSeries};
+
+type Datapoint = (Option<f64>, i64);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Grafana {
+    Datapoints(Vec<Datapoint>),
+    Target { datapoints: Vec<Datapoint> },
+}
+
+/// Parse a Grafana `datapoints` array, or an object holding one, into a
+/// series. `null` values become `Sample::Err`. The datapoints need not be
+/// sorted; ties keep their input order.
+pub fn from_grafana_json(s: &str) -> Result<RawSeries<f64>> {
+    let datapoints = match serde_json::from_str(s)? {
+        Grafana::Datapoints(datapoints) | Grafana::Target { datapoints } => datapoints,
+    };
+    let elements = datapoints
+        .into_iter()
+        .map(|(value, ts)| {
+            let sample = match value {
+                Some(v) => Sample::Point(v),
+                None => Sample::Err,
+            };
+            (TimeStamp(ts), sample).into()
+        })
+        .collect();
+    Ok(RawSeries::from_elements(elements, true)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datapoints() {
+        let series =
+            from_grafana_json("[[1.5, 1000], [null, 3000], [2, 2000], [4.25, 4000]]").unwrap();
+        let elements = series
+            .iter()
+            .map(|e| (e.0.millis(), e.1))
+            .collect::<Vec<_>>();
+        assert_eq!(elements.len(), 4);
+        assert!(elements[0].1.approx_eq(&Sample::Point(1.5), 0.0));
+        assert!(elements[1].1.approx_eq(&Sample::Point(2.0), 0.0));
+        assert!(elements[2].1.is_err());
+        assert!(elements[3].1.approx_eq(&Sample::Point(4.25), 0.0));
+        assert_eq!(
+            elements.iter().map(|e| e.0).collect::<Vec<_>>(),
+            vec![1000, 2000, 3000, 4000]
+        );
+
+        let series =
+            from_grafana_json(r#"{"target": "cpu", "datapoints": [[7, 10], [8, 20]]}"#).unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series.last_val(), 8.0);
+
+        assert!(from_grafana_json("[]").unwrap().is_empty());
+        assert!(from_grafana_json("[[1, 2, 3]]").is_err());
+        assert!(from_grafana_json(r#"[["high", 1000]]"#).is_err());
+    }
+}
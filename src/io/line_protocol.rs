@@ -0,0 +1,268 @@
+//! Parser for InfluxDB line protocol.
+//!
+//! Each line has the form
+//!
+//! ```text
+//! measurement[,tag=value...] field=value[,field=value...] [timestamp]
+//! ```
+//!
+//! Commas, spaces and equals signs in names and tag values are escaped with
+//! a backslash. Every field becomes its own series named
+//! `measurement.field`.
+
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    base::TimeStamp,
+    metric::{TagName, TagValue},
+    sample::SampleValue,
+    RawSeries,
+};
+
+/// A parsed series: its name, tags and points.
+pub type TaggedSeries<T> = (String, Vec<(TagName, TagValue)>, RawSeries<T>);
+
+/// Parse line protocol into one series per measurement, tag set and field,
+/// in order of first appearance. Empty lines and `#` comments are skipped.
+///
+/// The timestamp precision is inferred from its magnitude: seconds,
+/// milliseconds, microseconds or nanoseconds, all converted to milliseconds.
+/// Lines without a timestamp are stamped with the current time. Integer
+/// field values may carry an `i` or `u` suffix; other non-numeric values
+/// are rejected.
+pub fn parse_line_protocol<T: SampleValue + FromStr>(input: &str) -> Result<Vec<TaggedSeries<T>>> {
+    let mut series: Vec<TaggedSeries<T>> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for (n, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let context = |e: anyhow::Error| anyhow!("line {}: {}", n + 1, e);
+        let point = parse_line::<T>(line).map_err(context)?;
+
+        for (field, value) in point.fields {
+            let name = format!("{}.{}", point.measurement, field);
+            let key = std::iter::once(name.as_str())
+                .chain(
+                    point
+                        .tags
+                        .iter()
+                        .flat_map(|(k, v)| [k.as_str(), v.as_str()]),
+                )
+                .collect::<Vec<_>>()
+                .join("\0");
+
+            let i = *index.entry(key).or_insert_with(|| {
+                let tags = point
+                    .tags
+                    .iter()
+                    .map(|(k, v)| (TagName(k.clone()), TagValue::String(v.clone())))
+                    .collect();
+                series.push((name, tags, RawSeries::new()));
+                series.len() - 1
+            });
+            series[i].2.push_unordered(point.ts, value);
+        }
+    }
+
+    Ok(series)
+}
+
+struct Point<T> {
+    measurement: String,
+
+    /// Tags sorted by key, so that tag order doesn't split series.
+    tags: Vec<(String, String)>,
+    fields: Vec<(String, T)>,
+    ts: TimeStamp,
+}
+
+fn parse_line<T: SampleValue + FromStr>(line: &str) -> Result<Point<T>> {
+    let sections = split_unescaped(line, ' ');
+    let (key, fields, ts) = match sections[..] {
+        [key, fields] => (key, fields, None),
+        [key, fields, ts] => (key, fields, Some(ts)),
+        _ => bail!("expected 2 or 3 space separated sections"),
+    };
+
+    let mut key = split_unescaped(key, ',').into_iter();
+    let measurement = unescape(key.next().unwrap_or_default());
+    if measurement.is_empty() {
+        bail!("missing measurement");
+    }
+
+    let mut tags = key
+        .map(|tag| {
+            let (k, v) = split_pair(tag)?;
+            Ok((unescape(k), unescape(v)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    tags.sort();
+
+    let fields = split_unescaped(fields, ',')
+        .into_iter()
+        .map(|field| {
+            let (k, v) = split_pair(field)?;
+            let number = v.strip_suffix(['i', 'u']).unwrap_or(v);
+            let value = number
+                .parse::<T>()
+                .map_err(|_| anyhow!("invalid value {:?} for field {:?}", v, k))?;
+            Ok((unescape(k), value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let ts = match ts {
+        Some(ts) => to_millis(ts.parse()?),
+        None => TimeStamp::now(),
+    };
+
+    Ok(Point {
+        measurement,
+        tags,
+        fields,
+        ts,
+    })
+}
+
+/// Convert a timestamp of unknown precision to milliseconds.
+fn to_millis(ts: i64) -> TimeStamp {
+    match ts.unsigned_abs() {
+        v if v >= 100_000_000_000_000_000 => TimeStamp(ts / 1_000_000),
+        v if v >= 100_000_000_000_000 => TimeStamp(ts / 1_000),
+        v if v >= 100_000_000_000 => TimeStamp(ts),
+        _ => TimeStamp(ts * 1_000),
+    }
+}
+
+/// Split `s` on `sep`, ignoring escaped separators and separators inside
+/// double quotes. Escapes are kept.
+fn split_unescaped(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+    let mut quoted = false;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+
+    parts
+}
+
+/// Split `key=value` on the first unescaped `=`.
+fn split_pair(s: &str) -> Result<(&str, &str)> {
+    match split_unescaped(s, '=')[..] {
+        [k, _, ..] if !k.is_empty() => Ok((k, &s[k.len() + 1..])),
+        _ => bail!("expected key=value, got {:?}", s),
+    }
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(series: &TaggedSeries<f64>) -> Vec<(String, String)> {
+        series
+            .1
+            .iter()
+            .map(|(k, v)| (k.0.clone(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn multi_line() {
+        let input = "
+# cpu usage
+cpu,host=a,region=us usage=1.5,idle=98i 1700000000000
+cpu,region=us,host=a usage=2.5,idle=97i 1700000001
+cpu,host=b usage=3 1700000002000000000
+
+mem,host=a used=10u 1700000000500000
+";
+        let series = parse_line_protocol::<f64>(input).unwrap();
+        let names = series.iter().map(|s| s.0.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["cpu.usage", "cpu.idle", "cpu.usage", "mem.used"]
+        );
+
+        // Tag order doesn't matter, and all precisions end up in millis.
+        let usage = &series[0];
+        assert_eq!(
+            tags(usage),
+            vec![("host".into(), "a".into()), ("region".into(), "us".into())]
+        );
+        let points = usage
+            .2
+            .iter()
+            .map(|e| (e.0 .0, e.1.val()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            points,
+            vec![(1_700_000_000_000, 1.5), (1_700_000_001_000, 2.5)]
+        );
+        assert_eq!(series[1].2.last_val(), 97.0);
+
+        assert_eq!(tags(&series[2]), vec![("host".into(), "b".into())]);
+        assert_eq!(series[2].2.start_ts(), Some(TimeStamp(1_700_000_002_000)));
+        assert_eq!(series[3].2.start_ts(), Some(TimeStamp(1_700_000_000_500)));
+    }
+
+    #[test]
+    fn escapes() {
+        let input = r"disk\ io,path=C:\\data,label=a\,b\=c\ d read\ bytes=5 1700000000000";
+        let series = parse_line_protocol::<i64>(input).unwrap();
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].0, "disk io.read bytes");
+
+        let tags = series[0]
+            .1
+            .iter()
+            .map(|(k, v)| (k.0.as_str(), v.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            vec![("label", "a,b=c d".into()), ("path", r"C:\data".into())]
+        );
+        assert_eq!(series[0].2.last_val(), 5);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_line_protocol::<f64>("cpu").is_err());
+        assert!(parse_line_protocol::<f64>("cpu usage 1700000000000").is_err());
+        assert!(parse_line_protocol::<f64>("cpu usage=\"high\" 1700000000000").is_err());
+        assert!(parse_line_protocol::<f64>("cpu,host usage=1 1700000000000").is_err());
+        assert!(parse_line_protocol::<f64>("cpu usage=1 soon").is_err());
+
+        let err = parse_line_protocol::<f64>("cpu usage=1 1\ncpu usage=x 2").unwrap_err();
+        assert!(err.to_string().starts_with("line 2:"));
+    }
+}
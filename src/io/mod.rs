@@ -0,0 +1,6 @@
+pub mod csv;
+pub mod flat;
+pub mod gorilla;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod line_protocol;
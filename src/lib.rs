@@ -1,15 +1,28 @@
+pub mod alert;
 pub mod aligned_series;
 pub mod base;
+pub mod cache;
+pub mod chunked_series;
+pub mod compressed_series;
 pub mod element;
+pub mod io;
 pub mod metric;
 pub mod ops;
+pub mod prelude;
 pub mod raw_series;
+pub mod registry;
 pub mod sample;
+pub mod summary;
+pub mod unit;
+#[doc(hidden)]
 pub mod util;
 pub mod window;
 
 pub use aligned_series::AlignedSeries;
-pub use base::{Interval, TimeStamp};
+pub use base::{CalendarUnit, Duration, Interval, TimeStamp};
+pub use compressed_series::CompressedSeries;
 pub use element::Element;
+pub use metric::{Metric, PreAggOp, Stream, TagName, TagValue};
 pub use raw_series::RawSeries;
 pub use sample::Sample;
+pub use unit::Unit;
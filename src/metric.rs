@@ -1,6 +1,18 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    sync::Arc,
+};
 
-use crate::{sample::{SampleValue, SampleValueOp}, AlignedSeries, Interval, RawSeries, TimeStamp, ops};
+use anyhow::Result;
+
+use crate::{
+    cache::{QueryCache, QueryCacheStats},
+    ops,
+    raw_series::{check_batch, merged_iter_from, PushError},
+    sample::{Sample, SampleValue, SampleValueOp},
+    AlignedSeries, Duration, Interval, RawSeries, TimeStamp, Unit,
+};
 use derive_more::{Display, From, Into};
 
 #[repr(transparent)]
@@ -13,10 +25,14 @@ pub enum TagValue {
     Int(i64),
 }
 
+#[derive(Clone)]
 pub struct Metric<T: SampleValue> {
     pub name: String,
     pub tags: Vec<(TagName, TagValue)>,
     pub stream: Stream<T>,
+
+    /// Unit of the metric's values, if known.
+    pub unit: Option<Unit>,
 }
 
 impl<T: SampleValueOp<T>> Metric<T> {
@@ -25,9 +41,15 @@ impl<T: SampleValueOp<T>> Metric<T> {
             name,
             tags: vec![],
             stream: Stream::new(),
+            unit: None,
         }
     }
 
+    pub fn with_unit(mut self, unit: Unit) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
     pub fn add_tag(&mut self, name: TagName, value: TagValue) {
         self.tags.push((name, value));
     }
@@ -35,6 +57,164 @@ impl<T: SampleValueOp<T>> Metric<T> {
     pub fn push_raw(&mut self, ts: TimeStamp, value: T) {
         self.stream.push_raw(ts, value);
     }
+
+    /// Push a batch of raw samples, see `Stream::push_raw_batch`.
+    pub fn push_raw_batch(&mut self, batch: &[(TimeStamp, T)]) -> Result<usize, PushError> {
+        self.stream.push_raw_batch(batch)
+    }
+
+    /// Query the metric's stream, see `Stream::query`. The result carries
+    /// the metric's unit, if known.
+    pub fn query(
+        &mut self,
+        range: Range<TimeStamp>,
+        interval: Interval,
+        op: &str,
+    ) -> Result<Arc<AlignedSeries<T>>> {
+        let series = self.stream.query(range, interval, op)?;
+        match &self.unit {
+            Some(unit) if series.unit.as_ref() != Some(unit) => {
+                Ok(Arc::new((*series).clone().with_unit(unit.clone())))
+            }
+            _ => Ok(series),
+        }
+    }
+
+    /// Render the latest valid raw sample in the Prometheus text exposition
+    /// format, e.g. `name{tag="value"} 42 1700000000000`. `Zero` renders as
+    /// `0`. Returns an empty string if there's no valid sample.
+    pub fn to_prometheus(&self) -> String {
+        let latest = self
+            .stream
+            .raw
+            .last()
+            .and_then(|s| s.iter().rev().find(|e| !e.1.is_err()));
+        let Some(element) = latest else {
+            return String::new();
+        };
+
+        let labels = self
+            .tags
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name.0, escape_label(&value.to_string())))
+            .collect::<Vec<_>>();
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels.join(","))
+        };
+
+        format!(
+            "{}{} {} {}\n",
+            self.name,
+            labels,
+            element.1.val(),
+            element.0.millis()
+        )
+    }
+
+    /// Treat the metric as a counter and return its per-second rate, its
+    /// cumulative total and the timestamps of detected resets over `range`,
+    /// in slots of `interval`.
+    ///
+    /// A reset is either an explicit `Sample::Zero` marker, which anchors the
+    /// baseline at zero, or a decrease, where the new value is counted as the
+    /// increase since the reset. The total starts at the first value in range
+    /// and keeps growing across resets. Slots without valid samples are `Err`
+    /// in both series.
+    pub fn counter_view(
+        &self,
+        range: std::ops::Range<TimeStamp>,
+        interval: Interval,
+    ) -> Result<CounterView<T>> {
+        if range.end < range.start {
+            anyhow::bail!("range end must be greater than or equal to start");
+        }
+        if interval.millis() <= 0 {
+            anyhow::bail!("interval must be positive");
+        }
+        let Some(raw) = self.stream.raw.last() else {
+            anyhow::bail!("no raw data for metric {}", self.name);
+        };
+
+        let span = (range.end - range.start).millis();
+        let num_slots = ((span + interval.millis() - 1) / interval.millis()) as usize;
+        let secs = interval.millis() as f64 / 1000.0;
+
+        // Per-slot increase, or None if the slot saw no valid samples.
+        let mut increases: Vec<Option<T>> = vec![None; num_slots];
+        let mut resets = vec![];
+        let mut prev: Option<T> = None;
+        let mut total = T::zero();
+
+        for element in raw.values.iter() {
+            if element.0 < range.start || element.0 >= range.end || element.1.is_err() {
+                continue;
+            }
+
+            let slot = ((element.0 - range.start).millis() / interval.millis()) as usize;
+            let v = element.1.val();
+            let increase = match prev {
+                None => {
+                    total = v;
+                    T::zero()
+                }
+                Some(_) if element.1.is_zero() => {
+                    resets.push(element.0);
+                    T::zero()
+                }
+                Some(p) if v < p => {
+                    resets.push(element.0);
+                    v
+                }
+                Some(p) => v - p,
+            };
+
+            prev = Some(v);
+            increases[slot] = Some(increases[slot].unwrap_or(T::zero()) + increase);
+        }
+
+        let mut view = CounterView {
+            rate: AlignedSeries::new(interval, range.start),
+            total: AlignedSeries::new(interval, range.start),
+            resets,
+        };
+
+        for increase in increases {
+            match increase {
+                Some(increase) => {
+                    total = total + increase;
+                    view.total.push(total);
+                    match increase.to_f64() {
+                        Some(inc) => view.rate.push(inc / secs),
+                        None => view.rate.push_sample(Sample::Err),
+                    }
+                }
+                None => {
+                    view.total.push_sample(Sample::Err);
+                    view.rate.push_sample(Sample::Err);
+                }
+            }
+        }
+
+        Ok(view)
+    }
+}
+
+/// Escape a Prometheus label value: backslashes, double quotes and newlines.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Rate, cumulative total and resets of a counter, see
+/// `Metric::counter_view`.
+pub struct CounterView<T: SampleValue> {
+    pub rate: AlignedSeries<f64>,
+    pub total: AlignedSeries<T>,
+    pub resets: Vec<TimeStamp>,
 }
 
 pub struct DownSampler {
@@ -43,6 +223,28 @@ pub struct DownSampler {
     pub ops: Vec<String>,
 }
 
+impl DownSampler {
+    /// Create a new downsampler whose id is the interval's canonical name.
+    pub fn new(interval: Interval, ops: Vec<String>) -> Self {
+        Self {
+            id: interval.canonical_name(),
+            interval,
+            ops,
+        }
+    }
+
+    /// Resolve `ops` to element ops, failing on the first unknown name.
+    pub fn element_ops<T: SampleValueOp<T>>(&self) -> Result<Vec<ops::element::Op<T>>> {
+        self.ops
+            .iter()
+            .map(|name| {
+                ops::element::from_str(name)
+                    .ok_or_else(|| anyhow::anyhow!("unknown element op {}", name))
+            })
+            .collect()
+    }
+}
+
 // downsample string: [1m, 5m, 1h, 24h, 7d] [min, max, mean, rate]
 // maybe: min-1m, mean-5m, rate-5m
 
@@ -63,9 +265,22 @@ pub struct DownSampleConfigs {
     pub tags: Vec<(TagName, TagValue)>, // maybe ignore for now
 }
 
+#[derive(Clone)]
 pub struct Stream<T: SampleValue> {
-    pub raw: Vec<RawSeries<T>>,
+    raw: Vec<RawSeries<T>>,
     pub aligned: HashMap<Interval, BTreeMap<TimeStamp, AlignedSeries<T>>>,
+
+    /// In-flight bucket for ingest-side pre-aggregation, if enabled.
+    preagg: Option<PreAggregator<T>>,
+
+    /// Cache of `query` results, if enabled.
+    cache: Option<QueryCache<T>>,
+
+    /// Number of changes made to the raw data, for the query cache.
+    version: u64,
+
+    /// Incremental `align` state, per interval and start.
+    cursors: HashMap<(Interval, TimeStamp), AlignCursor<T>>,
 }
 
 impl<T: SampleValueOp<T>> Stream<T> {
@@ -73,54 +288,1315 @@ impl<T: SampleValueOp<T>> Stream<T> {
         Self {
             raw: vec![],
             aligned: HashMap::new(),
+            preagg: None,
+            cache: None,
+            version: 0,
+            cursors: HashMap::new(),
         }
     }
 
+    /// The raw segments, oldest first. Raw data is only changed through the
+    /// stream, which keeps aligned state and the query cache in step.
+    pub fn raw(&self) -> &[RawSeries<T>] {
+        &self.raw
+    }
+
     pub fn add_raw_series(&mut self, series: RawSeries<T>) {
         self.raw.push(series);
+        self.cursors.clear();
+        self.touch(TimeStamp(i64::MIN), TimeStamp(i64::MAX));
     }
 
     pub fn new_interval(&mut self, interval: Interval, start_ts: TimeStamp) {
         self.aligned
             .entry(interval)
-            .or_insert_with(BTreeMap::new)
+            .or_default()
             .insert(start_ts, AlignedSeries::new(interval, start_ts));
     }
 
     pub fn push_raw(&mut self, ts: TimeStamp, value: T) {
+        let element = match self.preagg.as_mut() {
+            Some(preagg) => preagg.fold(ts, value),
+            None => Some((ts, Sample::Point(value))),
+        };
+
+        if let Some((ts, sample)) = element {
+            self.push_raw_sample(ts, sample);
+        }
+    }
+
+    /// Push a batch of raw samples, with the semantics of
+    /// `RawSeries::push_batch`: an out of order batch is rejected whole. With
+    /// pre-aggregation enabled, the samples are folded as by `push_raw`.
+    pub fn push_raw_batch(&mut self, batch: &[(TimeStamp, T)]) -> Result<usize, PushError> {
+        let (Some(first), Some(last)) = (batch.first(), batch.last()) else {
+            return Ok(0);
+        };
+
+        if let Some(preagg) = self.preagg.as_ref() {
+            // The in-flight bucket may hold values newer than the raw tail.
+            let tail = self.raw.last().and_then(|raw| raw.end_ts());
+            check_batch(tail.max(preagg.last_ts()), batch)?;
+            for (ts, value) in batch {
+                self.push_raw(*ts, *value);
+            }
+            return Ok(batch.len());
+        }
+
+        if self.raw.is_empty() {
+            self.add_raw_series(RawSeries::new());
+        }
+        let pushed = self.raw.last_mut().unwrap().push_batch(batch)?;
+        self.touch(first.0, last.0);
+        Ok(pushed)
+    }
+
+    fn push_raw_sample(&mut self, ts: TimeStamp, sample: Sample<T>) {
         if self.raw.is_empty() {
             self.add_raw_series(RawSeries::new());
         }
 
-        self.raw.last_mut().unwrap().push(ts, value);
+        self.raw.last_mut().unwrap().push_sample(ts, sample);
+        self.touch(ts, ts);
+    }
+
+    /// Aggregate the raw data over `range`, from all raw segments as in
+    /// `align`, into slots of `interval` with the element op named `op`. If the query cache is enabled,
+    /// repeated queries are served from it until data they depend on
+    /// changes.
+    pub fn query(
+        &mut self,
+        range: Range<TimeStamp>,
+        interval: Interval,
+        op: &str,
+    ) -> Result<Arc<AlignedSeries<T>>> {
+        if let Some(series) = self
+            .cache
+            .as_mut()
+            .and_then(|cache| cache.get(&range, interval, op, self.version))
+        {
+            return Ok(series);
+        }
+
+        if range.end < range.start {
+            anyhow::bail!("range end must be greater than or equal to start");
+        }
+        if interval.millis() <= 0 {
+            anyhow::bail!("interval must be positive");
+        }
+        let Some(op_fn) = ops::element::from_str(op) else {
+            anyhow::bail!("unknown op {}", op);
+        };
+        if self.raw.iter().all(|raw| raw.is_empty()) {
+            anyhow::bail!("no raw data");
+        }
+
+        let series = Arc::new(AlignedSeries::from_elements(
+            merged_iter_from(&self.raw, range.start),
+            interval,
+            range.start,
+            Some(range.end),
+            op_fn,
+        )?);
+
+        if let Some(cache) = self.cache.as_mut() {
+            cache.insert(range, interval, op, self.version, series.clone());
+        }
+        Ok(series)
+    }
+
+    /// Cache up to `capacity` `query` results, evicting the least recently
+    /// used. Entries are invalidated when data in their range changes.
+    pub fn enable_query_cache(&mut self, capacity: usize) {
+        self.cache = Some(QueryCache::new(capacity));
+    }
+
+    pub fn disable_query_cache(&mut self) {
+        self.cache = None;
+    }
+
+    /// Returns the query cache hit and miss counters, if the cache is enabled.
+    pub fn query_cache_stats(&self) -> Option<QueryCacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
+    /// Record a change to raw data in `start..=end`, advancing the data
+    /// version.
+    fn touch(&mut self, start: TimeStamp, end: TimeStamp) {
+        self.version += 1;
+        if let Some(cache) = self.cache.as_mut() {
+            cache.advance(start, end, self.version);
+        }
+    }
+
+    /// Drop raw elements older than `ts` from all raw series. Returns the
+    /// number of elements removed.
+    pub fn truncate_before(&mut self, ts: TimeStamp) -> usize {
+        let removed = self.raw.iter_mut().map(|raw| raw.truncate_before(ts)).sum();
+        if removed > 0 {
+            self.cursors.clear();
+            self.touch(
+                TimeStamp(i64::MIN),
+                TimeStamp(ts.millis().saturating_sub(1)),
+            );
+        }
+        removed
     }
 
+    /// Fold values pushed with `push_raw` into buckets of `bucket` before
+    /// they reach the raw series. Only the in-flight bucket is kept in
+    /// memory; when a value lands in a later bucket, the in-flight one is
+    /// closed and a single element, timestamped at the bucket start and
+    /// carrying the value picked by `op`, is appended to the raw series.
+    ///
+    /// Values must be pushed in timestamp order. Any in-flight bucket from a
+    /// previous configuration is flushed first.
+    pub fn enable_preaggregation(&mut self, bucket: Duration, op: PreAggOp) -> Result<()> {
+        if bucket.millis() <= 0 {
+            anyhow::bail!("bucket must be positive");
+        }
+
+        self.flush_preaggregation();
+        self.preagg = Some(PreAggregator {
+            bucket,
+            op,
+            current: None,
+        });
+        Ok(())
+    }
+
+    /// Close the in-flight pre-aggregation bucket, if any, and stop
+    /// pre-aggregating.
+    pub fn disable_preaggregation(&mut self) {
+        self.flush_preaggregation();
+        self.preagg = None;
+    }
+
+    /// Close the in-flight pre-aggregation bucket, if any, appending its
+    /// element to the raw series.
+    pub fn flush_preaggregation(&mut self) {
+        if let Some((ts, sample)) = self.preagg.as_mut().and_then(|p| p.close()) {
+            self.push_raw_sample(ts, sample);
+        }
+    }
+
+    /// Align the raw data into deltas between the youngest samples of
+    /// consecutive windows of `interval`, from `start_ts` up to `end_ts` or
+    /// the last raw sample, and merge them into the aligned segments.
+    ///
+    /// The deltas of closed windows are kept between calls with the same
+    /// interval and start, so repeated calls only window the raw data
+    /// appended since and merge only the slots not merged before. Raw data
+    /// added or removed other than by appending resets this.
     pub fn align(&mut self, interval: Interval, start_ts: TimeStamp, end_ts: Option<TimeStamp>) {
         if self.raw.is_empty() {
             return;
         }
 
-        let raw_series = self.raw.last().unwrap();
-        let aligned_series = AlignedSeries::from_raw_series(
-            raw_series,
+        let segments = self.aligned.entry(interval).or_default();
+        let deltas = self
+            .cursors
+            .entry((interval, start_ts))
+            .or_insert_with(|| AlignCursor::new(interval, start_ts))
+            .align(&self.raw, segments, end_ts)
+            .unwrap();
+
+        if let Some(deltas) = deltas {
+            Self::merge_segment(interval, segments, deltas);
+        }
+    }
+
+    /// Merge `segment` into the canonical `segments`, taking precedence over
+    /// whatever it overlaps as in `merge_segments`. A segment that only
+    /// overlaps or extends a single existing one is written into it in
+    /// place.
+    fn merge_segment(
+        interval: Interval,
+        segments: &mut BTreeMap<TimeStamp, AlignedSeries<T>>,
+        segment: AlignedSeries<T>,
+    ) {
+        let step = interval.millis();
+        let end = segment.start_ts.millis() + segment.len() as i64 * step;
+
+        let target = segments
+            .range_mut(..=segment.start_ts)
+            .next_back()
+            .map(|(_, target)| target)
+            .filter(|target| {
+                let offset = (segment.start_ts - target.start_ts).millis();
+                !target.is_empty()
+                    && !segment.is_empty()
+                    && offset % step == 0
+                    && offset <= target.len() as i64 * step
+            });
+
+        if let Some(target) = target {
+            // Extending the target must not reach the next segment, which
+            // would have to be joined with it.
+            let target_start = target.start_ts;
+            let reach = end.max(target_start.millis() + target.len() as i64 * step);
+            let offset = ((segment.start_ts - target_start).millis() / step) as usize;
+            let next = segments
+                .range(TimeStamp(target_start.millis() + 1)..=TimeStamp(reach))
+                .next();
+
+            if next.is_none() {
+                let target = segments.get_mut(&target_start).unwrap();
+                for (i, sample) in segment.values.into_iter().enumerate() {
+                    match target.values.get_mut(offset + i) {
+                        Some(slot) if sample.is_err() && !slot.is_err() => {}
+                        Some(slot) => *slot = sample,
+                        None => target.push_sample(sample),
+                    }
+                }
+                return;
+            }
+        }
+
+        let mut by_priority = vec![segment];
+        by_priority.extend(std::mem::take(segments).into_values().rev());
+        *segments = Self::merge_segments(interval, by_priority);
+    }
+
+    /// Merge overlapping or duplicate aligned segments for `interval` into a
+    /// canonical, non-overlapping set. Where segments overlap, `policy`
+    /// picks the winner; `Err` slots never override valid data.
+    pub fn normalize_aligned(&mut self, interval: Interval, policy: OverlapPolicy) {
+        let Some(segments) = self.aligned.get_mut(&interval) else {
+            return;
+        };
+
+        let segments_by_start = std::mem::take(segments).into_values();
+        let by_priority = match policy {
+            OverlapPolicy::PreferLater => segments_by_start.rev().collect(),
+            OverlapPolicy::PreferEarlier => segments_by_start.collect(),
+        };
+        *segments = Self::merge_segments(interval, by_priority);
+    }
+
+    /// Merge segments given in priority order (highest first) slot by slot. A
+    /// slot is kept unless it overlaps a slot already kept, with all valid
+    /// slots considered before any `Err` slot. Kept slots are regrouped into
+    /// contiguous segments.
+    fn merge_segments(
+        interval: Interval,
+        by_priority: Vec<AlignedSeries<T>>,
+    ) -> BTreeMap<TimeStamp, AlignedSeries<T>> {
+        let step = interval.millis();
+        let mut kept: BTreeMap<TimeStamp, Sample<T>> = BTreeMap::new();
+        let mut merged = BTreeMap::new();
+
+        for errs in [false, true] {
+            for segment in by_priority.iter() {
+                for (i, sample) in segment.values.iter().enumerate() {
+                    if sample.is_err() != errs {
+                        continue;
+                    }
+
+                    let ts = TimeStamp(segment.start_ts.millis() + i as i64 * step);
+                    let overlaps_prev = kept
+                        .range(..=ts)
+                        .next_back()
+                        .is_some_and(|(t, _)| t.millis() + step > ts.millis());
+                    let overlaps_next = kept
+                        .range(ts..)
+                        .next()
+                        .is_some_and(|(t, _)| t.millis() < ts.millis() + step);
+
+                    if !overlaps_prev && !overlaps_next {
+                        kept.insert(ts, *sample);
+                    }
+                }
+            }
+        }
+
+        // Empty segments can't overlap anything, keep them as they are.
+        for segment in by_priority.into_iter().filter(|s| s.is_empty()) {
+            merged.insert(segment.start_ts, segment);
+        }
+
+        let mut current: Option<AlignedSeries<T>> = None;
+        for (ts, sample) in kept {
+            if let Some(series) = current.as_mut() {
+                if series.start_ts.millis() + series.len() as i64 * step == ts.millis() {
+                    series.push_sample(sample);
+                    continue;
+                }
+            }
+
+            if let Some(series) = current.take() {
+                merged.insert(series.start_ts, series);
+            }
+            let mut series = AlignedSeries::new(interval, ts);
+            series.push_sample(sample);
+            current = Some(series);
+        }
+        if let Some(series) = current {
+            merged.insert(series.start_ts, series);
+        }
+
+        merged
+    }
+
+    /// The alignment pipeline used by `align`: the youngest sample of each
+    /// window of the merged raw segments, followed by deltas between
+    /// consecutive windows.
+    fn align_deltas(
+        raw: &[RawSeries<T>],
+        interval: Interval,
+        start_ts: TimeStamp,
+        end_ts: Option<TimeStamp>,
+    ) -> Result<AlignedSeries<T>> {
+        let aligned_series = AlignedSeries::from_elements(
+            merged_iter_from(raw, start_ts),
             interval,
             start_ts,
             end_ts,
             crate::ops::element::youngest,
-        )
-        .unwrap();
+        )?;
 
-        let deltas = aligned_series.sliding_aggregate(2, ops::sample::delta).unwrap();
+        aligned_series.sliding_aggregate(2, ops::sample::delta)
+    }
 
-        self.aligned
-            .entry(interval)
-            .or_insert_with(BTreeMap::new)
-            .insert(start_ts, deltas);
+    /// Merge late (historical) raw data into the stream and recompute the
+    /// aligned slots it affects.
+    ///
+    /// Each element goes into the latest raw segment that starts at or before
+    /// it; elements older than every segment go into a new segment at the
+    /// front. Aligned series are derived from all raw segments (as in
+    /// `align`), and only the slots whose windows overlap the backfilled time
+    /// range are recomputed, plus the slot after each range since deltas
+    /// depend on the preceding window.
+    pub fn backfill(&mut self, series: RawSeries<T>) -> Result<BackfillReport> {
+        let mut report = BackfillReport::default();
+        if series.is_empty() {
+            return Ok(report);
+        }
+
+        let min_ts = series.values.iter().map(|e| e.0).min().unwrap();
+        let max_ts = series.values.iter().map(|e| e.0).max().unwrap();
+        self.cursors.clear();
+        self.touch(min_ts, max_ts);
+
+        let mut front = RawSeries::new();
+        let mut touched = vec![false; self.raw.len()];
+        for element in series.values.into_iter() {
+            let segment = self
+                .raw
+                .iter()
+                .rposition(|s| s.get(0).is_some_and(|first| first.0 <= element.0));
+
+            match segment {
+                Some(i) => {
+                    self.raw[i].values.push(element);
+                    touched[i] = true;
+                }
+                None => front.values.push(element),
+            }
+            report.merged += 1;
+        }
+
+        // Only the segments that received elements need sorting again.
+        for (segment, _) in self.raw.iter_mut().zip(touched).filter(|(_, t)| *t) {
+            segment.values.sort_by_key(|e| e.0);
+        }
+
+        if !front.is_empty() {
+            front.values.sort_by_key(|e| e.0);
+            self.raw.insert(0, front);
+        }
+
+        for (interval, segments) in self.aligned.iter_mut() {
+            let mut recomputed = 0;
+
+            for (start_ts, aligned) in segments.iter_mut() {
+                if aligned.is_empty() || max_ts < *start_ts {
+                    continue;
+                }
+
+                let slot = |ts: TimeStamp| (ts - *start_ts).millis().div_euclid(interval.millis());
+                let first = slot(min_ts).max(1);
+                let last = (slot(max_ts) + 1).min(aligned.len() as i64 - 1);
+                if first > last {
+                    continue;
+                }
+
+                // Recompute windows first-1..=last, which yields deltas for
+                // slots first..=last.
+                let fresh = Self::align_deltas(
+                    &self.raw,
+                    *interval,
+                    TimeStamp(start_ts.millis() + (first - 1) * interval.millis()),
+                    Some(TimeStamp(
+                        start_ts.millis() + (last + 1) * interval.millis(),
+                    )),
+                )?;
+
+                for (i, sample) in fresh.values.into_iter().skip(1).enumerate() {
+                    aligned.values[first as usize + i] = sample;
+                    recomputed += 1;
+                }
+            }
+
+            report.recomputed.insert(*interval, recomputed);
+        }
+
+        Ok(report)
+    }
+}
+
+/// Which segment wins where aligned segments overlap, see
+/// `Stream::normalize_aligned`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Prefer the segment with the later start timestamp, i.e. the one
+    /// computed from newer data.
+    PreferLater,
+
+    /// Prefer the segment with the earlier start timestamp.
+    PreferEarlier,
+}
+
+/// The value a closed pre-aggregation bucket is represented by, see
+/// `Stream::enable_preaggregation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreAggOp {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Mean,
+    Last,
+}
+
+/// Accumulator for the values in a single pre-aggregation bucket.
+#[derive(Clone)]
+struct Bucket<T: SampleValue> {
+    start_ts: TimeStamp,
+    last_ts: TimeStamp,
+    count: usize,
+    sum: T,
+    min: T,
+    max: T,
+    last: T,
+}
+
+#[derive(Clone)]
+struct PreAggregator<T: SampleValue> {
+    bucket: Duration,
+    op: PreAggOp,
+    current: Option<Bucket<T>>,
+}
+
+impl<T: SampleValueOp<T>> PreAggregator<T> {
+    /// Add a value to its bucket. Returns the element for the previous
+    /// bucket if this value closed it.
+    fn fold(&mut self, ts: TimeStamp, value: T) -> Option<(TimeStamp, Sample<T>)> {
+        let start_ts = ts.align_millis(self.bucket.millis());
+
+        let closed = match &self.current {
+            Some(bucket) if bucket.start_ts != start_ts => self.close(),
+            _ => None,
+        };
+
+        match self.current.as_mut() {
+            Some(bucket) => {
+                bucket.last_ts = ts;
+                bucket.count += 1;
+                bucket.sum = bucket.sum + value;
+                if value < bucket.min {
+                    bucket.min = value;
+                }
+                if value > bucket.max {
+                    bucket.max = value;
+                }
+                bucket.last = value;
+            }
+            None => {
+                self.current = Some(Bucket {
+                    start_ts,
+                    last_ts: ts,
+                    count: 1,
+                    sum: value,
+                    min: value,
+                    max: value,
+                    last: value,
+                })
+            }
+        }
+
+        closed
+    }
+
+    /// Returns the timestamp of the last value folded into the in-flight
+    /// bucket, if any.
+    fn last_ts(&self) -> Option<TimeStamp> {
+        self.current.as_ref().map(|bucket| bucket.last_ts)
+    }
+
+    /// Close the in-flight bucket, returning its element.
+    fn close(&mut self) -> Option<(TimeStamp, Sample<T>)> {
+        let bucket = self.current.take()?;
+
+        let value = match self.op {
+            PreAggOp::Count => T::from(bucket.count),
+            PreAggOp::Sum => Some(bucket.sum),
+            PreAggOp::Min => Some(bucket.min),
+            PreAggOp::Max => Some(bucket.max),
+            PreAggOp::Mean => T::from(bucket.count).map(|count| bucket.sum / count),
+            PreAggOp::Last => Some(bucket.last),
+        };
+
+        Some((bucket.start_ts, value.map_or(Sample::Err, Sample::Point)))
     }
 }
 
+/// The deltas of the closed windows for `Stream::align`, and how far into
+/// the raw data and the aligned segments they reach.
+#[derive(Clone)]
+struct AlignCursor<T: SampleValue> {
+    /// Deltas between the youngest samples of consecutive closed windows,
+    /// padded with `Err` at the start as by `sliding_aggregate`.
+    deltas: AlignedSeries<T>,
+
+    /// The youngest sample of the last closed window.
+    last: Option<Sample<T>>,
+
+    /// Number of raw elements, over all segments, before the first open
+    /// window, to spot raw data added or removed under the cursor.
+    consumed: usize,
+
+    /// Number of leading `deltas` slots already merged into the segments.
+    merged: usize,
+}
+
+impl<T: SampleValueOp<T>> AlignCursor<T> {
+    fn new(interval: Interval, start_ts: TimeStamp) -> Self {
+        Self {
+            deltas: AlignedSeries::new(interval, start_ts),
+            last: None,
+            consumed: 0,
+            merged: 0,
+        }
+    }
+
+    /// Returns the deltas up to `end_ts`, as `Stream::align_deltas` would,
+    /// less the leading slots already merged into `segments`, or `None` if
+    /// there's nothing new. Closed windows are extended first, and only the
+    /// still-open ones are aggregated from scratch.
+    fn align(
+        &mut self,
+        raw: &[RawSeries<T>],
+        segments: &BTreeMap<TimeStamp, AlignedSeries<T>>,
+        end_ts: Option<TimeStamp>,
+    ) -> Result<Option<AlignedSeries<T>>> {
+        let interval = self.deltas.interval;
+        let start_ts = self.deltas.start_ts;
+        if end_ts.is_some_and(|end_ts| end_ts < start_ts) {
+            anyhow::bail!("end_ts must be greater than or equal to start_ts");
+        }
+
+        if Self::count_before(raw, self.next_start()) != self.consumed {
+            *self = Self::new(interval, start_ts);
+        }
+
+        // If the merged slots were dropped from the segments, merge them
+        // again.
+        let merged_end = self.slot_ts(self.merged.saturating_sub(1));
+        let covered = segments
+            .range(..=merged_end)
+            .next_back()
+            .is_some_and(|(_, s)| s.end_ts().is_some_and(|end| end >= merged_end));
+        if self.merged > 0 && !covered {
+            self.merged = 0;
+        }
+
+        let youngest = ops::element::youngest;
+        let next_start = self.next_start();
+        let mut closed = AlignedSeries::new(interval, next_start);
+        if closed.extend_from_elements(merged_iter_from(raw, next_start), youngest)? > 0 {
+            for sample in closed.values {
+                let delta = Self::delta(&mut self.last, sample);
+                self.deltas.push_sample(delta);
+            }
+            self.consumed = Self::count_before(raw, self.next_start());
+        }
+
+        let from = self.merged;
+        let mut fresh = AlignedSeries::new(interval, self.slot_ts(from));
+        match end_ts {
+            // All windows up to `end_ts` are closed.
+            Some(end_ts) if end_ts <= self.next_start() => {
+                let span = (end_ts - start_ts).millis();
+                let len = ((span + interval.millis() - 1) / interval.millis()) as usize;
+                fresh
+                    .values
+                    .extend_from_slice(&self.deltas.values[from.min(len)..len]);
+                self.merged = self.merged.max(len);
+            }
+            _ => {
+                let next_start = self.next_start();
+                let tail = AlignedSeries::from_elements(
+                    merged_iter_from(raw, next_start),
+                    interval,
+                    next_start,
+                    end_ts,
+                    youngest,
+                )?;
+
+                fresh.values.extend_from_slice(&self.deltas.values[from..]);
+                let mut last = self.last;
+                for sample in tail.values {
+                    fresh.push_sample(Self::delta(&mut last, sample));
+                }
+                self.merged = self.deltas.len();
+            }
+        }
+
+        // An empty result still marks the segment start the first time.
+        if fresh.is_empty() && from > 0 {
+            return Ok(None);
+        }
+        Ok(Some(fresh))
+    }
+
+    /// The delta from `last` to `sample`, which becomes the new `last`.
+    fn delta(last: &mut Option<Sample<T>>, sample: Sample<T>) -> Sample<T> {
+        let delta = match last {
+            Some(prev) => ops::sample::delta(&[*prev, sample]),
+            None => Sample::Err,
+        };
+        *last = Some(sample);
+        delta
+    }
+
+    /// The number of raw elements before `ts`, over all segments.
+    fn count_before(raw: &[RawSeries<T>], ts: TimeStamp) -> usize {
+        raw.iter()
+            .map(|s| s.values.partition_point(|e| e.0 < ts))
+            .sum()
+    }
+
+    fn slot_ts(&self, index: usize) -> TimeStamp {
+        TimeStamp(self.deltas.start_ts.millis() + index as i64 * self.deltas.interval.millis())
+    }
+
+    /// The start of the first window not yet closed.
+    fn next_start(&self) -> TimeStamp {
+        self.slot_ts(self.deltas.len())
+    }
+}
+
+/// Summary of a `Stream::backfill` call.
+#[derive(Debug, Default)]
+pub struct BackfillReport {
+    /// Number of raw elements merged into the stream.
+    pub merged: usize,
+
+    /// Number of aligned slots recomputed, per interval.
+    pub recomputed: HashMap<Interval, usize>,
+}
+
 impl<T: SampleValueOp<T>> Default for Stream<T> {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SampleEquals;
+
+    #[test]
+    fn align_multiple_raw_series() {
+        let interval = Interval(10);
+
+        // Two scrape sessions with overlapping time ranges.
+        let mut stream = Stream::<i64>::new();
+        let mut first = RawSeries::new();
+        let mut second = RawSeries::new();
+        for i in 0..10 {
+            first.push(TimeStamp(i * 10), i);
+        }
+        for i in 5..20 {
+            second.push(TimeStamp(i * 10 + 5), i);
+        }
+        stream.add_raw_series(first);
+        stream.add_raw_series(second);
+        stream.add_raw_series(RawSeries::new());
+
+        stream.align(interval, TimeStamp(0), None);
+        let aligned = &stream.aligned[&interval][&TimeStamp(0)];
+        assert_eq!(aligned.len(), 20);
+
+        let deltas = aligned.values.iter().map(|s| s.val()).collect::<Vec<_>>();
+        assert_eq!(deltas[1..], [1; 19]);
+    }
+
+    #[test]
+    fn align_incremental() {
+        let interval = Interval(10);
+        let mut stream = Stream::<i64>::new();
+
+        // Aligning incrementally gives the same segments as from scratch.
+        let align = |stream: &mut Stream<i64>, end_ts: Option<TimeStamp>| {
+            let mut scratch = stream.clone();
+            scratch.cursors.clear();
+            scratch.align(interval, TimeStamp(0), end_ts);
+            stream.align(interval, TimeStamp(0), end_ts);
+
+            let segments = &stream.aligned[&interval];
+            let expected = &scratch.aligned[&interval];
+            assert_eq!(segments.len(), expected.len());
+            for ((start_ts, segment), (expected_ts, expected)) in segments.iter().zip(expected) {
+                assert_eq!(start_ts, expected_ts);
+                assert_eq!(segment.len(), expected.len());
+                for (sample, expected) in segment.values.iter().zip(expected.values.iter()) {
+                    assert!(sample.equals(expected));
+                }
+            }
+        };
+
+        // Realign after every push, with a gap in the middle.
+        for i in (0..50).chain(80..120) {
+            stream.push_raw(TimeStamp(i * 3), i);
+            align(&mut stream, None);
+        }
+        let cursor = &stream.cursors[&(interval, TimeStamp(0))];
+        assert_eq!(cursor.deltas.len(), 35);
+        assert_eq!(cursor.merged, 35);
+        assert_eq!(cursor.consumed, 87);
+
+        // Bounded realigns, before and past the closed windows.
+        align(&mut stream, Some(TimeStamp(200)));
+        align(&mut stream, Some(TimeStamp(500)));
+        align(&mut stream, None);
+
+        // Without new data, only the open window is merged again.
+        let cursor = stream.cursors.get_mut(&(interval, TimeStamp(0))).unwrap();
+        let segments = &stream.aligned[&interval];
+        let fresh = cursor.align(&stream.raw, segments, None).unwrap().unwrap();
+        assert_eq!((fresh.start_ts, fresh.len()), (TimeStamp(350), 1));
+        assert!(cursor
+            .align(&stream.raw, segments, Some(TimeStamp(350)))
+            .unwrap()
+            .is_none());
+
+        // A second raw segment, read merged with the first.
+        stream.add_raw_series(RawSeries::new());
+        for i in 120..140 {
+            stream.push_raw(TimeStamp(i * 3 - 10), i);
+            align(&mut stream, None);
+        }
+
+        // Raw data removed behind the cursor resets it. Values edited in
+        // place aren't noticed.
+        stream.raw[0].values.truncate(80);
+        align(&mut stream, None);
+
+        // So do dropped segments.
+        stream.aligned.clear();
+        align(&mut stream, None);
+        assert_eq!(stream.aligned[&interval].len(), 1);
+
+        stream.truncate_before(TimeStamp(60));
+        assert!(stream.cursors.is_empty());
+        align(&mut stream, None);
+    }
+
+    #[test]
+    fn push_raw_batch() {
+        let mut metric = Metric::<i64>::new("requests".to_string());
+        assert_eq!(metric.push_raw_batch(&[]), Ok(0));
+        assert_eq!(
+            metric.push_raw_batch(&[(TimeStamp(10), 1), (TimeStamp(20), 2)]),
+            Ok(2)
+        );
+        assert!(metric
+            .push_raw_batch(&[(TimeStamp(30), 3), (TimeStamp(15), 4)])
+            .is_err());
+        assert_eq!(metric.stream.raw[0].len(), 2);
+
+        // Batches are checked before folding into pre-aggregated buckets.
+        let mut stream = Stream::<i64>::new();
+        stream
+            .enable_preaggregation(Interval(10), PreAggOp::Sum)
+            .unwrap();
+        stream.push_raw(TimeStamp(0), 1);
+        stream.push_raw(TimeStamp(10), 1);
+        assert_eq!(
+            stream.push_raw_batch(&[(TimeStamp(11), 2), (TimeStamp(25), 3)]),
+            Ok(2)
+        );
+        assert!(stream.push_raw_batch(&[(TimeStamp(5), 4)]).is_err());
+
+        // A batch older than the in-flight bucket but newer than the raw
+        // tail is rejected too.
+        assert_eq!(stream.raw[0].end_ts(), Some(TimeStamp(10)));
+        assert_eq!(
+            stream.push_raw_batch(&[(TimeStamp(21), 4)]),
+            Err(PushError::OutOfOrder {
+                last: TimeStamp(25),
+                ts: TimeStamp(21)
+            })
+        );
+        stream.flush_preaggregation();
+
+        let sums = stream.raw[0]
+            .iter()
+            .map(|e| (e.0 .0, e.1.val()))
+            .collect::<Vec<_>>();
+        assert_eq!(sums, vec![(0, 1), (10, 3), (20, 3)]);
+    }
+
+    #[test]
+    fn preaggregation() {
+        let ops = [
+            PreAggOp::Count,
+            PreAggOp::Sum,
+            PreAggOp::Min,
+            PreAggOp::Max,
+            PreAggOp::Mean,
+            PreAggOp::Last,
+        ];
+
+        // 100k samples with pseudo-random spacing and values, spread over
+        // about 200 seconds.
+        let mut seed = 42u64;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            seed >> 33
+        };
+        let mut ts = 0;
+        let samples = (0..100_000)
+            .map(|_| {
+                ts += (next() % 5) as i64;
+                (TimeStamp(ts), (next() % 1000) as f64)
+            })
+            .collect::<Vec<_>>();
+
+        // Offline fold.
+        let mut offline: BTreeMap<i64, Vec<f64>> = BTreeMap::new();
+        for (ts, v) in samples.iter() {
+            offline
+                .entry(ts.millis() / 1000 * 1000)
+                .or_default()
+                .push(*v);
+        }
+
+        for op in ops {
+            let mut stream = Stream::<f64>::new();
+            stream
+                .enable_preaggregation(Interval::from_secs(1), op)
+                .unwrap();
+            for (ts, v) in samples.iter() {
+                stream.push_raw(*ts, *v);
+            }
+            assert_eq!(stream.raw[0].len(), offline.len() - 1);
+            stream.flush_preaggregation();
+
+            let raw = &stream.raw[0];
+            assert_eq!(raw.len(), offline.len());
+            for (element, (start, values)) in raw.iter().zip(offline.iter()) {
+                let expected = match op {
+                    PreAggOp::Count => values.len() as f64,
+                    PreAggOp::Sum => values.iter().sum(),
+                    PreAggOp::Min => values.iter().copied().fold(f64::MAX, f64::min),
+                    PreAggOp::Max => values.iter().copied().fold(f64::MIN, f64::max),
+                    PreAggOp::Mean => values.iter().sum::<f64>() / values.len() as f64,
+                    PreAggOp::Last => *values.last().unwrap(),
+                };
+                assert_eq!(element.0, TimeStamp(*start));
+                assert!(element.1.approx_eq(&Sample::Point(expected), 1e-6));
+            }
+        }
+
+        // Without pre-aggregation, values go straight to the raw series.
+        let mut stream = Stream::<i64>::new();
+        stream
+            .enable_preaggregation(Interval::from_secs(1), PreAggOp::Sum)
+            .unwrap();
+        stream.push_raw(TimeStamp(100), 1);
+        stream.push_raw(TimeStamp(200), 2);
+        stream.disable_preaggregation();
+        stream.push_raw(TimeStamp(300), 3);
+        assert_eq!(stream.raw[0].len(), 2);
+        assert!(stream.raw[0].values[0].1.equals(&Sample::Point(3)));
+        assert!(stream
+            .enable_preaggregation(Interval(0), PreAggOp::Sum)
+            .is_err());
+    }
+
+    #[test]
+    fn to_prometheus() {
+        let mut metric = Metric::<i64>::new("http_requests_total".to_string());
+        assert_eq!(metric.to_prometheus(), "");
+
+        metric.add_tag(TagName("method".into()), TagValue::String("GET".into()));
+        metric.add_tag(
+            TagName("path".into()),
+            TagValue::String("/a \"b\"\\c\nd".into()),
+        );
+        metric.add_tag(TagName("code".into()), TagValue::Int(200));
+        metric.push_raw(TimeStamp(1_700_000_000_000), 41);
+        metric.push_raw(TimeStamp(1_700_000_001_000), 42);
+        metric
+            .stream
+            .raw
+            .last_mut()
+            .unwrap()
+            .push_sample(TimeStamp(1_700_000_002_000), Sample::Err);
+
+        assert_eq!(
+            metric.to_prometheus(),
+            "http_requests_total{method=\"GET\",path=\"/a \\\"b\\\"\\\\c\\nd\",code=\"200\"} 42 1700000001000\n"
+        );
+
+        let mut metric = Metric::<i64>::new("resets".to_string());
+        metric.stream.push_raw(TimeStamp(5), 3);
+        metric
+            .stream
+            .raw
+            .last_mut()
+            .unwrap()
+            .push_sample(TimeStamp(6), Sample::Zero);
+        assert_eq!(metric.to_prometheus(), "resets 0 6\n");
+    }
+
+    #[test]
+    fn downsampler_ops() {
+        let ds = DownSampler::new(Interval::from_minutes(1), vec!["min".into(), "p99".into()]);
+        assert_eq!(ds.element_ops::<f64>().unwrap().len(), 2);
+
+        let ds = DownSampler::new(Interval::from_minutes(1), vec!["min".into(), "rate".into()]);
+        assert!(ds.element_ops::<f64>().is_err());
+    }
+
+    #[test]
+    fn query_unit() {
+        let bytes = Unit::parse("bytes").unwrap();
+        let mut metric = Metric::<i64>::new("rx".to_string()).with_unit(bytes.clone());
+        for i in 0..10 {
+            metric.push_raw(TimeStamp(i * 1000), i);
+        }
+
+        let range = TimeStamp(0)..TimeStamp(10_000);
+        let series = metric
+            .query(range.clone(), Interval::from_secs(5), "max")
+            .unwrap();
+        assert_eq!(series.unit, Some(bytes));
+        assert!(metric
+            .stream
+            .query(range, Interval::from_secs(5), "max")
+            .unwrap()
+            .unit
+            .is_none());
+    }
+
+    #[test]
+    fn query_segments() {
+        let mut stream = Stream::<i64>::new();
+        for i in 0..6 {
+            stream.push_raw(TimeStamp(i * 10), i);
+        }
+        let mut later = RawSeries::new();
+        for i in 6..12 {
+            later.push(TimeStamp(i * 10), i);
+        }
+        stream.add_raw_series(later);
+
+        // Both segments are aggregated, not just the latest one.
+        let series = stream
+            .query(TimeStamp(0)..TimeStamp(120), Interval(30), "sum")
+            .unwrap();
+        let sums = series.values.iter().map(|s| s.val()).collect::<Vec<_>>();
+        assert_eq!(sums, vec![3, 12, 21, 30]);
+
+        let series = stream
+            .query(TimeStamp(40)..TimeStamp(80), Interval(20), "max")
+            .unwrap();
+        let maxes = series.values.iter().map(|s| s.val()).collect::<Vec<_>>();
+        assert_eq!(maxes, vec![5, 7]);
+    }
+
+    #[test]
+    fn query_cache() {
+        let minute = Interval::from_minutes(1);
+        let mut stream = Stream::<i64>::new();
+        for i in 0..120 {
+            stream.push_raw(TimeStamp(i * 1000), i);
+        }
+
+        // Without the cache every query is recomputed.
+        let first = stream
+            .query(TimeStamp(0)..TimeStamp(60_000), minute, "mean")
+            .unwrap();
+        let second = stream
+            .query(TimeStamp(0)..TimeStamp(60_000), minute, "mean")
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert!(stream.query_cache_stats().is_none());
+        assert!(stream
+            .query(TimeStamp(0)..TimeStamp(60_000), minute, "nope")
+            .is_err());
+
+        stream.enable_query_cache(2);
+        let early = TimeStamp(0)..TimeStamp(60_000);
+        let late = TimeStamp(60_000)..TimeStamp(120_000);
+
+        let a = stream.query(early.clone(), minute, "mean").unwrap();
+        let b = stream.query(early.clone(), minute, "mean").unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(a.values[0].val(), 29);
+
+        // A different op is a different entry.
+        let sum = stream.query(early.clone(), minute, "sum").unwrap();
+        assert!(!Arc::ptr_eq(&a, &sum));
+        assert_eq!(
+            stream.query_cache_stats(),
+            Some(QueryCacheStats { hits: 1, misses: 2 })
+        );
+
+        // Backfilling into the early range only drops the early entries.
+        let c = stream.query(late.clone(), minute, "mean").unwrap();
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(30_500), 1000);
+        stream.backfill(series).unwrap();
+        assert!(Arc::ptr_eq(
+            &c,
+            &stream.query(late.clone(), minute, "mean").unwrap()
+        ));
+        let d = stream.query(early.clone(), minute, "mean").unwrap();
+        assert!(!Arc::ptr_eq(&a, &d));
+
+        // A push at the end of the late range only drops the late entry.
+        stream.push_raw(TimeStamp(119_500), 0);
+        assert!(Arc::ptr_eq(
+            &d,
+            &stream.query(early.clone(), minute, "mean").unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &c,
+            &stream.query(late.clone(), minute, "mean").unwrap()
+        ));
+
+        // With capacity 2, querying a third range evicts the least recently
+        // used one (the early range).
+        let e = stream.query(early.clone(), minute, "mean").unwrap();
+        let f = stream.query(late.clone(), minute, "mean").unwrap();
+        stream
+            .query(TimeStamp(0)..TimeStamp(120_000), minute, "max")
+            .unwrap();
+        assert!(Arc::ptr_eq(
+            &f,
+            &stream.query(late.clone(), minute, "mean").unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &e,
+            &stream.query(early.clone(), minute, "mean").unwrap()
+        ));
+
+        // Retention drops entries starting before the cutoff.
+        let g = stream.query(late.clone(), minute, "mean").unwrap();
+        assert_eq!(stream.truncate_before(TimeStamp(10_000)), 10);
+        assert!(Arc::ptr_eq(
+            &g,
+            &stream.query(late.clone(), minute, "mean").unwrap()
+        ));
+        assert_eq!(stream.truncate_before(TimeStamp(i64::MIN)), 0);
+        assert!(Arc::ptr_eq(
+            &g,
+            &stream.query(late.clone(), minute, "mean").unwrap()
+        ));
+
+        // A change without a time range moves past every entry.
+        stream.add_raw_series(RawSeries::new());
+        stream.push_raw(TimeStamp(200_000), 0);
+        assert!(!Arc::ptr_eq(
+            &g,
+            &stream.query(late, minute, "mean").unwrap()
+        ));
+    }
+
+    #[test]
+    fn backfill() {
+        let minute = Interval::from_minutes(1).millis();
+
+        // A counter sampled every 10 seconds for an hour, with a hole between
+        // minutes 20 and 30.
+        let mut full = Stream::<i64>::new();
+        let mut holey = Stream::<i64>::new();
+        let mut late = RawSeries::new();
+        for i in 0..360 {
+            let ts = TimeStamp(i * 10_000);
+            full.push_raw(ts, i * 2);
+            if (120..180).contains(&i) {
+                late.push(ts, i * 2);
+            } else {
+                holey.push_raw(ts, i * 2);
+            }
+        }
+
+        for interval in [Interval::from_minutes(1), Interval::from_minutes(5)] {
+            full.align(interval, TimeStamp(0), None);
+            holey.align(interval, TimeStamp(0), None);
+        }
+
+        let before = holey.aligned.clone();
+        let report = holey.backfill(late).unwrap();
+        assert_eq!(report.merged, 60);
+
+        // Minutes 20..30 plus the following slot.
+        assert_eq!(report.recomputed[&Interval::from_minutes(1)], 11);
+        // Slots 4 and 5 (minutes 20..30) plus slot 6.
+        assert_eq!(report.recomputed[&Interval::from_minutes(5)], 3);
+
+        for interval in [Interval::from_minutes(1), Interval::from_minutes(5)] {
+            let old = &before[&interval][&TimeStamp(0)];
+            let new = &holey.aligned[&interval][&TimeStamp(0)];
+            let expected = &full.aligned[&interval][&TimeStamp(0)];
+            assert_eq!(new.len(), expected.len());
+
+            let first = 20 * minute / interval.millis();
+            let last = 30 * minute / interval.millis();
+            for i in 0..new.len() {
+                assert!(new.values[i].equals(&expected.values[i]), "slot {}", i);
+                if (i as i64) < first || (i as i64) > last {
+                    assert!(new.values[i].equals(&old.values[i]), "slot {}", i);
+                }
+            }
+        }
+
+        // Data older than every segment starts a new one.
+        let mut old = RawSeries::new();
+        old.push(TimeStamp(-10_000), 0);
+        let report = holey.backfill(old).unwrap();
+        assert_eq!(report.merged, 1);
+        assert_eq!(holey.raw.len(), 2);
+        assert_eq!(holey.raw[0].len(), 1);
+    }
+
+    #[test]
+    fn normalize_aligned() {
+        let interval = Interval(10);
+        let segment = |start: i64, values: &[i64]| {
+            let mut series = AlignedSeries::new(interval, TimeStamp(start));
+            for v in values {
+                series.push(*v);
+            }
+            series
+        };
+
+        let mut stream = Stream::<i64>::new();
+        let segments = stream.aligned.entry(interval).or_default();
+        segments.insert(TimeStamp(0), segment(0, &[1, 1, 1, 1, 1]));
+        // Overlaps slots 30 and 40, and extends to 60.
+        segments.insert(TimeStamp(30), segment(30, &[2, 2, 2, 2]));
+        // Off-grid segment overlapping slots 60 and 70.
+        segments.insert(TimeStamp(65), segment(65, &[3, 3]));
+        // Disjoint segment.
+        segments.insert(TimeStamp(200), segment(200, &[4]));
+
+        let total = |stream: &Stream<i64>| {
+            stream.aligned[&interval]
+                .values()
+                .flat_map(|s| s.values.iter())
+                .map(|s| s.val())
+                .sum::<i64>()
+        };
+        assert_eq!(total(&stream), 5 + 8 + 6 + 4);
+
+        let mut later = stream.clone();
+        later.normalize_aligned(interval, OverlapPolicy::PreferLater);
+        let segments = &later.aligned[&interval];
+        assert_eq!(
+            segments.keys().copied().collect::<Vec<_>>(),
+            vec![TimeStamp(0), TimeStamp(65), TimeStamp(200)]
+        );
+        assert!(segments[&TimeStamp(0)].approx_eq(&segment(0, &[1, 1, 1, 2, 2, 2]), 0.0));
+        assert!(segments[&TimeStamp(65)].approx_eq(&segment(65, &[3, 3]), 0.0));
+        assert_eq!(total(&later), 3 + 6 + 6 + 4);
+
+        let mut earlier = stream.clone();
+        earlier.normalize_aligned(interval, OverlapPolicy::PreferEarlier);
+        let segments = &earlier.aligned[&interval];
+        assert_eq!(
+            segments.keys().copied().collect::<Vec<_>>(),
+            vec![TimeStamp(0), TimeStamp(75), TimeStamp(200)]
+        );
+        assert!(segments[&TimeStamp(0)].approx_eq(&segment(0, &[1, 1, 1, 1, 1, 2, 2]), 0.0));
+        assert!(segments[&TimeStamp(75)].approx_eq(&segment(75, &[3]), 0.0));
+
+        // Normalizing is idempotent.
+        let mut again = earlier.clone();
+        again.normalize_aligned(interval, OverlapPolicy::PreferLater);
+        assert_eq!(again.aligned[&interval].len(), 3);
+        assert_eq!(total(&again), total(&earlier));
+
+        // Repeated align calls with different starts don't pile up.
+        let mut stream = Stream::<i64>::new();
+        for i in 0..100 {
+            stream.push_raw(TimeStamp(i), i);
+        }
+        stream.align(interval, TimeStamp(0), None);
+        stream.align(interval, TimeStamp(50), None);
+        stream.align(interval, TimeStamp(50), None);
+        assert_eq!(stream.aligned[&interval].len(), 1);
+        assert_eq!(stream.aligned[&interval][&TimeStamp(0)].len(), 10);
+    }
+
+    #[test]
+    fn counter_view() {
+        let mut metric = Metric::<i64>::new("requests".to_string());
+
+        // 10s samples. The counter climbs by 10 per sample, wraps to 5 at
+        // 60s, and gets an explicit reset marker at 120s.
+        let values = [
+            100, 110, 120, 130, 140, 150, 5, 15, 25, 35, 45, 55, -1, 10, 20, 30,
+        ];
+        for (i, v) in values.iter().enumerate() {
+            let ts = TimeStamp(i as i64 * 10_000);
+            if *v < 0 {
+                metric
+                    .stream
+                    .raw
+                    .last_mut()
+                    .unwrap()
+                    .push_sample(ts, Sample::Zero);
+            } else {
+                metric.push_raw(ts, *v);
+            }
+        }
+
+        let view = metric
+            .counter_view(TimeStamp(0)..TimeStamp(180_000), Interval::from_secs(60))
+            .unwrap();
+
+        assert_eq!(view.resets, vec![TimeStamp(60_000), TimeStamp(120_000)]);
+
+        // Slot 0: 100..150, slot 1: reset to 5 then up to 55, slot 2: reset
+        // marker then up to 30.
+        let mut rate = AlignedSeries::new(Interval::from_secs(60), TimeStamp(0));
+        rate.push(50.0 / 60.0);
+        rate.push(55.0 / 60.0);
+        rate.push(30.0 / 60.0);
+        assert!(view.rate.approx_eq(&rate, 1e-9));
+
+        let mut total = AlignedSeries::new(Interval::from_secs(60), TimeStamp(0));
+        total.push(150);
+        total.push(205);
+        total.push(235);
+        assert!(view.total.approx_eq(&total, 0.0));
+
+        // Slots past the data are Err.
+        let view = metric
+            .counter_view(TimeStamp(0)..TimeStamp(240_000), Interval::from_secs(60))
+            .unwrap();
+        assert_eq!(view.rate.len(), 4);
+        assert!(view.rate.values[3].is_err());
+        assert!(view.total.values[3].is_err());
+    }
+}
@@ -13,14 +13,30 @@ where
         "max" => Some(max),
         "min" => Some(min),
         "sum" => Some(sum),
+        "product" => Some(product),
         "mean" => Some(mean),
         "oldest" => Some(oldest),
         "youngest" => Some(youngest),
         "delta" => Some(delta),
+        "count" => Some(count),
+        "range" => Some(range),
+        "median" => Some(median),
+        "mode" => Some(mode),
+        "count_distinct" => Some(count_distinct),
+        "variance" => Some(variance),
+        "stddev" => Some(stddev),
+        "geometric_mean" => Some(geometric_mean),
+        "harmonic_mean" => Some(harmonic_mean),
+        "p50" => Some(p50),
+        "p90" => Some(p90),
+        "p95" => Some(p95),
+        "p99" => Some(p99),
         _ => None,
     }
 }
 
+/// Returns the largest non-`Err` value in the window, as a `Fake` if any
+/// value was `Fake`. Windows without a valid sample yield `Err`.
 pub fn max<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
     let mut max = Sample::Err;
     let mut has_fake = false;
@@ -28,18 +44,18 @@ pub fn max<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
     for elem in values.iter() {
         match elem.1 {
             Sample::Point(v) => {
-                if v > max.val() {
+                if max.is_err() || v > max.val() {
                     max = Sample::Point(v);
                 }
             }
             Sample::Fake(v) => {
                 has_fake = true;
-                if v > max.val() {
+                if max.is_err() || v > max.val() {
                     max = Sample::Fake(v);
                 }
             }
             Sample::Zero => {
-                if T::zero() > max.val() {
+                if max.is_err() || T::zero() > max.val() {
                     max = Sample::Point(T::zero());
                 }
             }
@@ -47,13 +63,17 @@ pub fn max<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
         }
     }
 
-    if has_fake {
+    if max.is_err() {
+        Sample::Err
+    } else if has_fake {
         Sample::Fake(max.val())
     } else {
         Sample::Point(max.val())
     }
 }
 
+/// Returns the smallest non-`Err` value in the window, as a `Fake` if any
+/// value was `Fake`. Windows without a valid sample yield `Err`.
 pub fn min<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
     let mut min = Sample::Err;
     let mut has_fake = false;
@@ -61,18 +81,18 @@ pub fn min<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
     for elem in values.iter() {
         match elem.1 {
             Sample::Point(v) => {
-                if v < min.val() {
+                if min.is_err() || v < min.val() {
                     min = Sample::Point(v);
                 }
             }
             Sample::Fake(v) => {
                 has_fake = true;
-                if v < min.val() {
+                if min.is_err() || v < min.val() {
                     min = Sample::Fake(v);
                 }
             }
             Sample::Zero => {
-                if T::zero() < min.val() {
+                if min.is_err() || T::zero() < min.val() {
                     min = Sample::Point(T::zero());
                 }
             }
@@ -80,14 +100,45 @@ pub fn min<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
         }
     }
 
-    if has_fake {
+    if min.is_err() {
+        Sample::Err
+    } else if has_fake {
         Sample::Fake(min.val())
     } else {
         Sample::Point(min.val())
     }
 }
 
+/// Returns the spread (max minus min) of the non-`Err` values in the window,
+/// as a `Fake` if any value was `Fake`. Empty windows yield `Err`.
+pub fn range<T: SampleValueOp<T>>(values: &[Element<T>]) -> Sample<T> {
+    let mut bounds: Option<(T, T)> = None;
+    let mut has_fake = false;
+
+    for elem in values.iter().filter(|elem| !elem.1.is_err()) {
+        has_fake |= matches!(elem.1, Sample::Fake(_));
+        let v = elem.1.val();
+        bounds = match bounds {
+            None => Some((v, v)),
+            Some((lo, hi)) if v < lo => Some((v, hi)),
+            Some((lo, hi)) if v > hi => Some((lo, v)),
+            bounds => bounds,
+        };
+    }
+
+    match bounds {
+        None => Sample::Err,
+        Some((lo, hi)) if has_fake => Sample::Fake(hi - lo),
+        Some((lo, hi)) => Sample::Point(hi - lo),
+    }
+}
+
+/// Returns the sum of the values in the window. Empty windows yield `Err`.
 pub fn sum<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    if values.is_empty() {
+        return Sample::Err;
+    }
+
     let mut sum = T::zero();
 
     for elem in values.iter() {
@@ -97,7 +148,23 @@ pub fn sum<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
     Sample::Point(sum)
 }
 
+/// Returns the product of the non-`Err` values in the window. `Zero` is
+/// treated as the numeric zero, and empty windows yield `Point(one)`.
+pub fn product<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    let mut product = T::one();
+
+    for elem in values.iter().filter(|elem| !elem.1.is_err()) {
+        product = product * elem.1.val();
+    }
+
+    Sample::Point(product)
+}
+
 pub fn mean<T: SampleValueOp<T>>(values: &[Element<T>]) -> Sample<T> {
+    if values.is_empty() {
+        return Sample::Err;
+    }
+
     let mut sum = T::zero();
 
     for elem in values.iter() {
@@ -123,19 +190,432 @@ pub fn youngest<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
     }
 }
 
+/// Counter delta between the two elements in the window. See
+/// `ops::sample::delta` for the reset semantics.
 pub fn delta<T: SampleValueOp<T>>(values: &[Element<T>]) -> Sample<T> {
-    // TODO: check for Zero point
-    if values.len() != 2 {
-        Sample::Err
+    match values {
+        [prev, last] => super::sample::counter_delta(&prev.1, &last.1),
+        _ => Sample::Err,
+    }
+}
+
+/// Returns the number of non-`Err` samples in the window. Empty windows yield
+/// `Point(0)`.
+pub fn count<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    let count = values.iter().filter(|elem| !elem.1.is_err()).count();
+
+    match T::from(count) {
+        Some(count) => Sample::Point(count),
+        None => Sample::Err,
+    }
+}
+
+/// Returns the non-`Err` values in the window sorted in ascending order, and
+/// whether any of them were `Fake`. `Zero` is treated as the numeric zero.
+fn sorted_values<T: SampleValue>(values: &[Element<T>]) -> (Vec<T>, bool) {
+    let mut has_fake = false;
+    let mut sorted = values
+        .iter()
+        .filter(|elem| !elem.1.is_err())
+        .map(|elem| {
+            has_fake |= matches!(elem.1, Sample::Fake(_));
+            elem.1.val()
+        })
+        .collect::<Vec<T>>();
+
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    (sorted, has_fake)
+}
+
+/// Returns the median of the non-`Err` values in the window, or the mean of
+/// the two middle values for even counts. Empty windows yield `Err`.
+pub fn median<T: SampleValueOp<T>>(values: &[Element<T>]) -> Sample<T> {
+    let (sorted, has_fake) = sorted_values(values);
+    if sorted.is_empty() {
+        return Sample::Err;
+    }
+
+    let mid = sorted.len() / 2;
+    let median = if sorted.len() % 2 == 1 {
+        sorted[mid]
     } else {
-        let last = values.last().unwrap().1.val();
-        let prev = values.first().unwrap().1.val();
-
-        if last > prev {
-            Sample::Point(last - prev)
-        } else {
-            // TODO: this should be last from Zero
-            Sample::Point(last)
+        (sorted[mid - 1] + sorted[mid]) / T::from(2).unwrap()
+    };
+
+    if has_fake {
+        Sample::Fake(median)
+    } else {
+        Sample::Point(median)
+    }
+}
+
+/// Returns the most frequent value in the window. Ties are broken in favor of
+/// the smaller value. Windows without any non-`Err` samples yield `Err`.
+pub fn mode<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    let (sorted, has_fake) = sorted_values(values);
+
+    let mut best: Option<(T, usize)> = None;
+    for run in sorted.chunk_by(|a, b| a == b) {
+        // Runs are visited in ascending order, so only a strictly longer run
+        // replaces the current best.
+        if best.is_none_or(|(_, len)| run.len() > len) {
+            best = Some((run[0], run.len()));
         }
     }
+
+    match best {
+        None => Sample::Err,
+        Some((v, _)) if has_fake => Sample::Fake(v),
+        Some((v, _)) => Sample::Point(v),
+    }
+}
+
+/// Returns the number of distinct non-`Err` values in the window.
+pub fn count_distinct<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    let (sorted, _) = sorted_values(values);
+    let count = sorted.chunk_by(|a, b| a == b).count();
+
+    match T::from(count) {
+        Some(count) => Sample::Point(count),
+        None => Sample::Err,
+    }
+}
+
+/// Returns the population variance of the non-`Err` values in the window,
+/// computed in f64. Windows with fewer than two values yield `Err`.
+pub fn variance<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    match variance_f64(values).and_then(T::from) {
+        Some(v) => Sample::Point(v),
+        None => Sample::Err,
+    }
+}
+
+/// Returns the population standard deviation of the non-`Err` values in the
+/// window, computed in f64. Windows with fewer than two values yield `Err`.
+pub fn stddev<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    match variance_f64(values).and_then(|v| T::from(v.sqrt())) {
+        Some(v) => Sample::Point(v),
+        None => Sample::Err,
+    }
+}
+
+fn variance_f64<T: SampleValue>(values: &[Element<T>]) -> Option<f64> {
+    let values = values_f64(values)?;
+
+    if values.len() < 2 {
+        return None;
+    }
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    Some(values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / n)
+}
+
+/// Returns the geometric mean of the non-`Err` values in the window, computed
+/// in f64. Empty windows, or windows with a non-positive value, yield `Err`.
+pub fn geometric_mean<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    let mean = values_f64(values)
+        .filter(|values| !values.is_empty() && values.iter().all(|v| *v > 0.0))
+        .map(|values| (values.iter().map(|v| v.ln()).sum::<f64>() / values.len() as f64).exp());
+
+    match mean.and_then(T::from) {
+        Some(v) => Sample::Point(v),
+        None => Sample::Err,
+    }
+}
+
+/// Returns the harmonic mean of the non-`Err` values in the window, computed
+/// in f64. Empty windows, or windows with a zero value, yield `Err`.
+pub fn harmonic_mean<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    let mean = values_f64(values)
+        .filter(|values| !values.is_empty() && values.iter().all(|v| *v != 0.0))
+        .map(|values| values.len() as f64 / values.iter().map(|v| 1.0 / v).sum::<f64>());
+
+    match mean.and_then(T::from) {
+        Some(v) => Sample::Point(v),
+        None => Sample::Err,
+    }
+}
+
+/// Returns the non-`Err` values in the window as f64, or `None` if any of
+/// them can't be represented.
+fn values_f64<T: SampleValue>(values: &[Element<T>]) -> Option<Vec<f64>> {
+    values
+        .iter()
+        .filter(|elem| !elem.1.is_err())
+        .map(|elem| elem.1.val().to_f64())
+        .collect()
+}
+
+/// Returns the `q`-th quantile (`0.0..=1.0`) of the non-`Err` values in the
+/// window, linearly interpolating between the two nearest ranks. Integer
+/// results are truncated. `Zero` is treated as the numeric zero, and empty
+/// windows (or an out of range `q`) yield `Err`.
+pub fn quantile<T: SampleValue>(values: &[Element<T>], q: f64) -> Sample<T> {
+    let (sorted, has_fake) = sorted_values(values);
+    if sorted.is_empty() || !(0.0..=1.0).contains(&q) {
+        return Sample::Err;
+    }
+
+    let rank = q * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    let (Some(lo), Some(hi)) = (sorted[lower].to_f64(), sorted[upper].to_f64()) else {
+        return Sample::Err;
+    };
+
+    match T::from(lo + (hi - lo) * (rank - lower as f64)) {
+        Some(v) if has_fake => Sample::Fake(v),
+        Some(v) => Sample::Point(v),
+        None => Sample::Err,
+    }
+}
+
+/// Returns an aggregation computing the `q`-th percentile (`0.0..=100.0`) of
+/// a window. See `p50`..`p99` for the common ones as ops.
+pub fn percentile<T: SampleValue>(q: f64) -> impl Fn(&[Element<T>]) -> Sample<T> {
+    move |values| quantile(values, q / 100.0)
+}
+
+pub fn p50<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    quantile(values, 0.50)
+}
+
+pub fn p90<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    quantile(values, 0.90)
+}
+
+pub fn p95<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    quantile(values, 0.95)
+}
+
+pub fn p99<T: SampleValue>(values: &[Element<T>]) -> Sample<T> {
+    quantile(values, 0.99)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sample::SampleEquals, AlignedSeries, Interval, RawSeries, TimeStamp};
+
+    fn window<T: SampleValue>(samples: &[Sample<T>]) -> Vec<Element<T>> {
+        samples
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (i as i64, *s).into())
+            .collect()
+    }
+
+    #[test]
+    fn range_matches_max_minus_min() {
+        use Sample::*;
+
+        let windows = [
+            window(&[Point(4), Point(9), Err, Point(7)]),
+            window(&[Point(-4), Zero, Point(-9)]),
+            window(&[Point(3)]),
+        ];
+        for w in windows.iter() {
+            let expected = max(w).val() - min(w).val();
+            assert!(range(w).equals(&Point(expected)));
+        }
+
+        let w = window(&[Point(4), Fake(12), Point(7)]);
+        assert!(range(&w).approx_eq(&Fake(8), 0.0));
+
+        assert!(range(&window::<i64>(&[Err])).is_err());
+        assert!(max(&window::<i64>(&[Err])).is_err());
+        assert!(min(&window::<i64>(&[Err])).is_err());
+        assert!(max(&window(&[Point(-4), Err, Point(-9)])).equals(&Point(-4)));
+        assert!(min(&window(&[Point(4), Err, Point(9)])).equals(&Point(4)));
+        assert!(range::<i64>(&[]).is_err());
+        assert!(from_str::<i64>("range").is_some());
+    }
+
+    #[test]
+    fn empty_windows() {
+        use Sample::*;
+
+        // Ops that have no value for an empty window yield `Err` rather than
+        // dividing by zero.
+        assert!(mean::<i64>(&[]).is_err());
+        assert!(mean::<f64>(&[]).is_err());
+        assert!(sum::<i64>(&[]).is_err());
+        assert!(max::<i64>(&[]).is_err());
+        assert!(min::<i64>(&[]).is_err());
+        assert!(mean(&window(&[Point(2), Point(4)])).equals(&Point(3)));
+    }
+
+    #[test]
+    fn product_of_window() {
+        use Sample::*;
+
+        assert!(product(&window(&[Point(2), Err, Point(3), Fake(4)])).equals(&Point(24)));
+        assert!(product(&window(&[Point(2), Zero, Point(3)])).equals(&Point(0)));
+        assert!(product(&window(&[Point(2), Point(0), Point(3)])).equals(&Point(0)));
+        assert!(product::<i64>(&[]).equals(&Point(1)));
+        assert!(product(&window(&[Point(1.5), Point(-2.0)])).approx_eq(&Point(-3.0), 0.0));
+    }
+
+    #[test]
+    fn count_samples() {
+        use Sample::*;
+
+        let w = window(&[Point(9), Err, Zero, Fake(5), Point(9)]);
+        assert!(count(&w).equals(&Point(4)));
+        assert!(count(&window(&[Err])).equals(&Point(0)));
+        assert!(count::<i64>(&[]).equals(&Point(0)));
+        assert!(from_str::<i64>("count").is_some());
+
+        // A density series for a sparse raw series reports empty windows as
+        // zero.
+        let mut raw = RawSeries::new();
+        for (ts, v) in [(0, 5), (3, 6), (35, 7)] {
+            raw.push(TimeStamp(ts), v);
+        }
+        let density =
+            AlignedSeries::from_raw_series(&raw, Interval(10), TimeStamp(0), None, count).unwrap();
+        let expected = [Point(2), Point(0), Point(0), Point(1)];
+        assert_eq!(density.len(), expected.len());
+        for (got, want) in density.values.iter().zip(expected.iter()) {
+            assert!(got.equals(want), "{} != {}", got, want);
+        }
+    }
+
+    #[test]
+    fn median_odd_and_even() {
+        use Sample::*;
+
+        let w = window(&[Point(9), Point(1), Err, Point(5), Point(100), Point(3)]);
+        assert!(median(&w).equals(&Point(5)));
+
+        let w = window(&[Point(9), Point(1), Point(5), Point(100), Point(3), Point(7)]);
+        assert!(median(&w).equals(&Point(6)));
+
+        let w = [
+            (0, Point(1.0)).into(),
+            (1, Point(2.0)).into(),
+            (2, Fake(4.0)).into(),
+            (3, Point(10.0)).into(),
+        ];
+        assert!(median(&w).approx_eq(&Fake(3.0), 1e-12));
+
+        assert!(median(&window::<i64>(&[Err, Err])).is_err());
+        assert!(median::<i64>(&[]).is_err());
+        assert!(from_str::<i64>("median").is_some());
+    }
+
+    #[test]
+    fn mode_and_count_distinct() {
+        use Sample::*;
+
+        // Clear majority.
+        let w = window(&[Point(0), Point(2), Point(1), Point(2), Point(2), Err]);
+        assert!(mode(&w).equals(&Point(2)));
+        assert!(count_distinct(&w).equals(&Point(3)));
+
+        // Ties go to the smaller value.
+        let w = window(&[Point(2), Point(1), Point(2), Point(1), Point(0)]);
+        assert!(mode(&w).equals(&Point(1)));
+        assert!(count_distinct(&w).equals(&Point(3)));
+
+        // Zero counts as the value zero.
+        let w = window(&[Zero, Point(0), Point(1)]);
+        assert!(mode(&w).equals(&Point(0)));
+        assert!(count_distinct(&w).equals(&Point(2)));
+
+        // Windows without valid samples.
+        let w = window(&[Err, Err]);
+        assert!(mode(&w).is_err());
+        assert!(count_distinct(&w).equals(&Point(0)));
+        assert!(mode::<i64>(&[]).is_err());
+
+        assert!(from_str::<i64>("mode").is_some());
+        assert!(from_str::<i64>("count_distinct").is_some());
+    }
+
+    #[test]
+    fn variance_and_stddev() {
+        let constant = (0..10)
+            .map(|i| (i, Sample::Point(4.2)).into())
+            .collect::<Vec<Element<f64>>>();
+        assert!(variance(&constant).approx_eq(&Sample::Point(0.0), 1e-12));
+        assert!(stddev(&constant).approx_eq(&Sample::Point(0.0), 1e-12));
+
+        let w = window(&[
+            Sample::Point(2),
+            Sample::Point(4),
+            Sample::Err,
+            Sample::Point(4),
+            Sample::Point(4),
+            Sample::Point(5),
+            Sample::Point(5),
+            Sample::Point(7),
+            Sample::Point(9),
+        ]);
+        assert!(variance(&w).equals(&Sample::Point(4)));
+        assert!(stddev(&w).equals(&Sample::Point(2)));
+
+        assert!(stddev(&window(&[Sample::Point(1), Sample::Err])).is_err());
+        assert!(variance::<i64>(&[]).is_err());
+
+        assert!(from_str::<f64>("variance").is_some());
+        assert!(from_str::<f64>("stddev").is_some());
+    }
+
+    #[test]
+    fn geometric_and_harmonic_mean() {
+        let w = window(&[
+            Sample::Point(1.0),
+            Sample::Point(2.0),
+            Sample::Err,
+            Sample::Point(4.0),
+        ]);
+
+        // (1 * 2 * 4)^(1/3) = 2 and 3 / (1 + 1/2 + 1/4) = 12/7.
+        assert!(geometric_mean(&w).approx_eq(&Sample::Point(2.0), 1e-9));
+        assert!(harmonic_mean(&w).approx_eq(&Sample::Point(12.0 / 7.0), 1e-9));
+        assert_eq!(
+            from_str::<f64>("geometric_mean").unwrap()(&w).val(),
+            geometric_mean(&w).val()
+        );
+
+        let w = window(&[Sample::Point(2.0), Sample::Point(-8.0)]);
+        assert!(geometric_mean(&w).is_err());
+        assert!(harmonic_mean(&w).approx_eq(&Sample::Point(16.0 / 3.0), 1e-9));
+
+        let w = window(&[Sample::Point(2.0), Sample::Zero]);
+        assert!(geometric_mean(&w).is_err());
+        assert!(harmonic_mean(&w).is_err());
+
+        assert!(geometric_mean::<f64>(&[]).is_err());
+        assert!(harmonic_mean::<f64>(&[]).is_err());
+    }
+
+    #[test]
+    fn percentiles() {
+        // 60 samples, shuffled, with an Err and a Zero thrown in.
+        let mut w = (1..=59)
+            .map(|i| ((i * 37) % 60, Sample::Point(((i * 37) % 60) as f64)).into())
+            .collect::<Vec<Element<f64>>>();
+        w.push((60, Sample::Zero).into());
+        w.push((61, Sample::Err).into());
+
+        assert!(p50(&w).approx_eq(&Sample::Point(29.5), 1e-9));
+        assert!(p99(&w).approx_eq(&Sample::Point(58.41), 1e-9));
+        assert!(quantile(&w, 0.0).approx_eq(&Sample::Point(0.0), 1e-9));
+        assert!(quantile(&w, 1.0).approx_eq(&Sample::Point(59.0), 1e-9));
+        assert!(percentile(90.0)(&w).approx_eq(&p90(&w), 0.0));
+
+        assert!(quantile(&w, 1.5).is_err());
+        assert!(p50::<f64>(&[]).is_err());
+        assert!(p50(&[(0, Sample::<f64>::Err).into()]).is_err());
+
+        // Fake inputs taint the result.
+        w[0].1 = Sample::Fake(w[0].1.val());
+        assert!(p50(&w).approx_eq(&Sample::Fake(29.5), 1e-9));
+
+        assert!(from_str::<f64>("p99").is_some());
+    }
 }
@@ -1,20 +1,255 @@
-use crate::sample::{Sample, SampleValueOp};
+use crate::{
+    base::Interval,
+    sample::{Sample, SampleValue, SampleValueOp},
+};
 
 pub type Op<T> = fn(&[Sample<T>]) -> Sample<T>;
 
+/// Look up a sample op by name. `rate` takes an interval and isn't an
+/// `Op<T>`, so it can't be looked up here.
+pub fn from_str<T: SampleValueOp<T>>(op: &str) -> Option<Op<T>> {
+    match op {
+        "delta" => Some(delta),
+        "increase" => Some(increase),
+        "sum" => Some(sum),
+        "mean" => Some(mean),
+        "min" => Some(min),
+        "max" => Some(max),
+        _ => None,
+    }
+}
+
+/// Returns the increase between the two samples in the window, treating the
+/// values as a monotonic counter.
+///
+/// - `prev <= last`: the counter grew, so the delta is `last - prev`.
+/// - `last < prev`: the counter was reset in between, so the delta is
+///   measured from zero, i.e. `last`.
+/// - `prev` is `Zero`: the counter was just reset, so the baseline is zero and
+///   the delta is `last`.
+/// - `last` is `Zero`: the reset itself, which contributes nothing.
+///
+/// Windows that don't hold exactly two samples, or that contain an `Err`,
+/// yield `Err`.
 pub fn delta<T: SampleValueOp<T>>(values: &[Sample<T>]) -> Sample<T> {
-    // TODO: check for Zero point
-    if values.len() != 2 {
-        Sample::Err
-    } else {
-        let last = values.last().unwrap().val();
-        let prev = values.first().unwrap().val();
-
-        if last > prev {
-            Sample::Point(last - prev)
-        } else {
-            // TODO: this should be last from Zero
-            Sample::Point(last)
+    match values {
+        [prev, last] => counter_delta(prev, last),
+        _ => Sample::Err,
+    }
+}
+
+/// Counter delta between two consecutive samples. See `delta`.
+pub(crate) fn counter_delta<T: SampleValueOp<T>>(prev: &Sample<T>, last: &Sample<T>) -> Sample<T> {
+    match (prev, last) {
+        (Sample::Err, _) | (_, Sample::Err) => Sample::Err,
+        (_, Sample::Zero) => Sample::Point(T::zero()),
+        (Sample::Zero, last) => Sample::Point(last.val()),
+        (prev, last) if last.val() >= prev.val() => Sample::Point(last.val() - prev.val()),
+        (_, last) => Sample::Point(last.val()),
+    }
+}
+
+/// Returns the total increase of a counter over a window of any length, i.e.
+/// the sum of the `delta`s between consecutive samples. A drop in value or a
+/// `Zero` marker resets the baseline, as in `delta`. `Err` samples are
+/// skipped; a window without valid samples yields `Err`.
+pub fn increase<T: SampleValueOp<T>>(values: &[Sample<T>]) -> Sample<T> {
+    let mut valid = values.iter().filter(|s| !s.is_err());
+    let Some(mut prev) = valid.next() else {
+        return Sample::Err;
+    };
+
+    let mut total = T::zero();
+    for last in valid {
+        total = total + counter_delta(prev, last).val();
+        prev = last;
+    }
+
+    Sample::Point(total)
+}
+
+/// Returns an aggregation computing the per-second rate of a counter between
+/// two samples `interval` apart, i.e. `delta / interval_secs`. Counter resets
+/// are handled as in `delta`. The division is done in `f64`, so sub-second
+/// and fractional intervals work for integer types too, with the rate
+/// truncated on conversion back to `T`. Since `Op<T>` is a plain function
+/// pointer it can't carry the interval, so this is used with
+/// `sliding_aggregate` directly.
+pub fn rate<T: SampleValueOp<T>>(interval: Interval) -> impl Fn(&[Sample<T>]) -> Sample<T> {
+    let secs = interval.millis() as f64 / 1000.0;
+
+    move |values| {
+        let rate = match delta(values) {
+            Sample::Point(delta) if secs > 0.0 => delta.to_f64().and_then(|d| T::from(d / secs)),
+            _ => None,
+        };
+        rate.map_or(Sample::Err, Sample::Point)
+    }
+}
+
+pub fn sum<T: SampleValue>(values: &[Sample<T>]) -> Sample<T> {
+    let mut sum = T::zero();
+
+    for sample in values.iter() {
+        sum = sum + sample.val();
+    }
+
+    Sample::Point(sum)
+}
+
+pub fn mean<T: SampleValueOp<T>>(values: &[Sample<T>]) -> Sample<T> {
+    if values.is_empty() {
+        return Sample::Err;
+    }
+
+    match (sum(values), T::from(values.len())) {
+        (Sample::Point(sum), Some(len)) => Sample::Point(sum / len),
+        _ => Sample::Err,
+    }
+}
+
+pub fn min<T: SampleValue>(values: &[Sample<T>]) -> Sample<T> {
+    extreme(values, |a, b| a < b)
+}
+
+pub fn max<T: SampleValue>(values: &[Sample<T>]) -> Sample<T> {
+    extreme(values, |a, b| a > b)
+}
+
+/// Returns the non-`Err` value for which `better` holds against all others,
+/// as a `Fake` if any of the values were `Fake`.
+fn extreme<T: SampleValue>(values: &[Sample<T>], better: fn(T, T) -> bool) -> Sample<T> {
+    let mut best: Option<T> = None;
+    let mut has_fake = false;
+
+    for sample in values.iter().filter(|s| !s.is_err()) {
+        has_fake |= matches!(sample, Sample::Fake(_));
+        let v = sample.val();
+        if best.is_none_or(|b| better(v, b)) {
+            best = Some(v);
         }
     }
+
+    match best {
+        None => Sample::Err,
+        Some(v) if has_fake => Sample::Fake(v),
+        Some(v) => Sample::Point(v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SampleEquals;
+
+    fn deltas(values: &[Sample<i64>]) -> Vec<Sample<i64>> {
+        values.windows(2).map(delta).collect()
+    }
+
+    #[test]
+    fn ops_by_name() {
+        let w = [Sample::Point(3), Sample::Point(1), Sample::Point(6)];
+        let ops: [(&str, Op<i64>); 6] = [
+            ("delta", delta),
+            ("increase", increase),
+            ("sum", sum),
+            ("mean", mean),
+            ("min", min),
+            ("max", max),
+        ];
+
+        for (name, op) in ops {
+            let found = from_str::<i64>(name).unwrap();
+            assert!(found(&w).equals(&op(&w)), "{}", name);
+            assert!(found(&w[..2]).equals(&op(&w[..2])), "{}", name);
+        }
+        assert!(from_str::<i64>("rate").is_none());
+    }
+
+    #[test]
+    fn delta_monotonic() {
+        use Sample::*;
+
+        let d = deltas(&[Point(1), Point(4), Point(4), Point(10)]);
+        assert!(d[0].equals(&Point(3)));
+        assert!(d[1].equals(&Point(0)));
+        assert!(d[2].equals(&Point(6)));
+
+        assert!(delta::<i64>(&[Point(1)]).is_err());
+        assert!(delta::<i64>(&[Point(1), Err]).is_err());
+    }
+
+    #[test]
+    fn delta_reset() {
+        use Sample::*;
+
+        // The counter wraps from 10 to 3: the increase since the reset is 3.
+        let d = deltas(&[Point(8), Point(10), Point(3), Point(5)]);
+        assert!(d[0].equals(&Point(2)));
+        assert!(d[1].equals(&Point(3)));
+        assert!(d[2].equals(&Point(2)));
+    }
+
+    #[test]
+    fn increase_over_window() {
+        use Sample::*;
+
+        // Grows by 4, resets and grows to 3, then by 2.
+        let w = [Point(10), Point(12), Point(14), Point(3), Point(5)];
+        assert!(increase(&w).equals(&Point(9)));
+
+        // An explicit reset in the middle anchors the baseline at zero.
+        let w = [Point(10), Point(12), Zero, Point(15), Point(16)];
+        assert!(increase(&w).equals(&Point(18)));
+
+        // Errs are skipped.
+        let w = [Point(1), Err, Point(4), Err, Point(6)];
+        assert!(increase(&w).equals(&Point(5)));
+        assert!(increase(&[Point(7)]).equals(&Point(0)));
+        assert!(increase::<i64>(&[Err, Err]).is_err());
+        assert!(increase::<i64>(&[]).is_err());
+    }
+
+    #[test]
+    fn rate_per_second() {
+        use crate::{base::TimeStamp, AlignedSeries};
+
+        let interval = Interval::from_secs(60);
+        let mut series = AlignedSeries::new(interval, TimeStamp(0));
+        for v in [0.0, 120.0, 300.0, 60.0, 90.0] {
+            series.push(v);
+        }
+
+        let rates = series.sliding_aggregate(2, rate(interval)).unwrap();
+        assert_eq!(rates.len(), series.len());
+        assert!(rates.values[0].is_err());
+        let expected = [2.0, 3.0, 1.0, 0.5];
+        for (got, want) in rates.values[1..].iter().zip(expected) {
+            assert!(
+                got.approx_eq(&Sample::Point(want), 1e-9),
+                "{} != {}",
+                got,
+                want
+            );
+        }
+
+        // Integer counters divide by the exact interval, not a truncated one.
+        let pair = [Sample::Point(10), Sample::Point(40)];
+        assert!(rate::<i64>(Interval(500))(&pair).equals(&Sample::Point(60)));
+        assert!(rate::<i64>(Interval(1500))(&pair).equals(&Sample::Point(20)));
+        assert!(rate::<i64>(Interval(0))(&pair).is_err());
+        assert!(rate::<f64>(interval)(&[Sample::Point(1.0)]).is_err());
+    }
+
+    #[test]
+    fn delta_zero_marker() {
+        use Sample::*;
+
+        // An explicit reset anchors the next delta at zero, even if the new
+        // value is larger than the one before the reset.
+        let d = deltas(&[Point(5), Zero, Point(7), Point(9)]);
+        assert!(d[0].equals(&Point(0)));
+        assert!(d[1].equals(&Point(7)));
+        assert!(d[2].equals(&Point(2)));
+    }
 }
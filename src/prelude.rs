@@ -0,0 +1,19 @@
+//! The commonly used types and traits, plus the `ops` module, for glob
+//! import:
+//!
+//! ```
+//! use sup::prelude::*;
+//! ```
+//!
+//! Ops are named through `ops`, e.g. `ops::element::mean`, so they don't
+//! collide with the crate's own modules.
+
+pub use crate::{
+    aligned_series::{AlignedSeries, FillPolicy, MissingPolicy},
+    base::{Duration, Interval, TimeStamp},
+    element::Element,
+    metric::{Metric, Stream, TagName, TagValue},
+    ops,
+    raw_series::RawSeries,
+    sample::{Sample, SampleEquals, SampleValue, SampleValueOp},
+};
@@ -1,14 +1,196 @@
-use std::fmt;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    fmt,
+    ops::Range,
+};
 
 use crate::{
+    aligned_series::AlignedSeries,
     base::*,
-    element::Element,
-    sample::{Sample, SampleValue},
-    window::WindowIter,
+    element::{ChangeEvent, Element},
+    ops::element,
+    ops::sample::counter_delta,
+    sample::{Sample, SampleValue, SampleValueOp},
+    window::{SlidingWindowIter, Window, WindowIter},
 };
 
+/// How to resolve elements that share a timestamp.
+#[derive(Debug, Clone, Copy)]
+pub enum DedupPolicy<T: SampleValue> {
+    /// Keep the first element.
+    KeepFirst,
+
+    /// Keep the last element.
+    KeepLast,
+
+    /// Replace the elements with the result of the op over all of them.
+    Combine(element::Op<T>),
+}
+
+impl<T: SampleValue> DedupPolicy<T> {
+    /// Collapse runs of equal timestamps in `elements`, which must be sorted.
+    fn dedup(&self, elements: Vec<Element<T>>) -> Vec<Element<T>> {
+        let mut deduped: Vec<Element<T>> = Vec::with_capacity(elements.len());
+
+        for group in elements.chunk_by(|a, b| a.0 == b.0) {
+            let sample = match self {
+                Self::KeepFirst => group[0].1,
+                Self::KeepLast => group[group.len() - 1].1,
+                Self::Combine(op) if group.len() > 1 => op(group),
+                Self::Combine(_) => group[0].1,
+            };
+            deduped.push((group[0].0, sample).into());
+        }
+
+        deduped
+    }
+}
+
+/// Resolves two samples sharing a timestamp in `RawSeries::merge_with`.
+pub type MergeConflict<T> = fn(Sample<T>, Sample<T>) -> Sample<T>;
+
+/// Errors returned by checked pushes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PushError {
+    /// The timestamp is older than the last sample in the series.
+    OutOfOrder { last: TimeStamp, ts: TimeStamp },
+}
+
+impl fmt::Display for PushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfOrder { last, ts } => {
+                write!(f, "timestamp {} is older than last sample {}", ts.0, last.0)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PushError {}
+
+/// Errors returned by `RawSeries::try_push`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeriesError {
+    /// The timestamp is older than the last sample in the series.
+    OutOfOrder { last: TimeStamp, ts: TimeStamp },
+
+    /// The timestamp is the same as the last sample's.
+    Duplicate { ts: TimeStamp },
+}
+
+impl fmt::Display for SeriesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfOrder { last, ts } => {
+                write!(f, "timestamp {} is older than last sample {}", ts.0, last.0)
+            }
+            Self::Duplicate { ts } => write!(f, "duplicate timestamp {}", ts.0),
+        }
+    }
+}
+
+impl std::error::Error for SeriesError {}
+
+/// Errors returned by `RawSeries::append`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppendError {
+    /// The appended series starts at or before the end of the series.
+    Overlap { last: TimeStamp, first: TimeStamp },
+}
+
+impl fmt::Display for AppendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overlap { last, first } => write!(
+                f,
+                "appended series starts at {}, not after last sample {}",
+                first.0, last.0
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AppendError {}
+
+/// Errors returned when building a series from bulk data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// The element at `index` is older than the one before it.
+    OutOfOrder {
+        index: usize,
+        last: TimeStamp,
+        ts: TimeStamp,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfOrder { index, last, ts } => {
+                let err = PushError::OutOfOrder {
+                    last: *last,
+                    ts: *ts,
+                };
+                write!(f, "element {}: {}", index, err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Check that `batch` is ordered and starts no earlier than `last`.
+pub(crate) fn check_batch<T>(
+    last: Option<TimeStamp>,
+    batch: &[(TimeStamp, T)],
+) -> Result<(), PushError> {
+    let mut last = last;
+    for (ts, _) in batch {
+        if let Some(last) = last.filter(|last| *ts < *last) {
+            return Err(PushError::OutOfOrder { last, ts: *ts });
+        }
+        last = Some(*ts);
+    }
+    Ok(())
+}
+
+/// Returns an iterator over the elements of all of `series` in timestamp
+/// order, without copying them. Each series must be sorted; they may
+/// overlap. Elements sharing a timestamp are ordered by the position of
+/// their series in the slice, as in `RawSeries::merge_all`.
+pub fn merged_iter<T: SampleValue>(series: &[RawSeries<T>]) -> impl Iterator<Item = &Element<T>> {
+    merged_iter_from(series, TimeStamp(i64::MIN))
+}
+
+/// Like `merged_iter`, but starting at the first elements at or after
+/// `start_ts`, found by binary search in each series.
+pub fn merged_iter_from<T: SampleValue>(
+    series: &[RawSeries<T>],
+    start_ts: TimeStamp,
+) -> impl Iterator<Item = &Element<T>> {
+    // Min-heap of the next element's (timestamp, series, index) per series.
+    let mut heap = series
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| {
+            let j = s.values.partition_point(|e| e.0 < start_ts);
+            Some(Reverse((s.get(j)?.0, i, j)))
+        })
+        .collect::<BinaryHeap<_>>();
+
+    std::iter::from_fn(move || {
+        let Reverse((_, i, j)) = heap.pop()?;
+        if let Some(next) = series[i].get(j + 1) {
+            heap.push(Reverse((next.0, i, j + 1)));
+        }
+        series[i].get(j)
+    })
+}
+
 /// `RawSeries` represents a series of raw timestamped
 /// data samples.
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(transparent))]
 #[derive(Debug, Clone)]
 pub struct RawSeries<T: SampleValue> {
     pub values: Vec<Element<T>>,
@@ -20,27 +202,165 @@ impl<T: SampleValue> RawSeries<T> {
         Self { values: vec![] }
     }
 
-    /// Returns the last value in the series.
-    pub fn last_val(&self) -> T {
+    /// Create a new, empty series with room for `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Build a series from `(timestamp, value)` pairs in bulk. If `sort` is
+    /// set, the pairs are stably sorted by timestamp first; otherwise they
+    /// must already be in order, and the first violation is returned.
+    ///
+    /// Duplicate timestamps are kept in input order; `duplicate_indices`
+    /// reports them, and `dedup_by_ts` collapses them.
+    pub fn from_vec(data: Vec<(TimeStamp, T)>, sort: bool) -> Result<Self, BuildError> {
+        let elements = data
+            .into_iter()
+            .map(|(ts, v)| (ts, Sample::point(v)).into())
+            .collect();
+        Self::from_elements(elements, sort)
+    }
+
+    /// Element variant of `from_vec`.
+    pub fn from_elements(mut elements: Vec<Element<T>>, sort: bool) -> Result<Self, BuildError> {
+        if sort {
+            elements.sort_by_key(|e| e.0);
+        } else if let Some(i) = elements.windows(2).position(|pair| pair[1].0 < pair[0].0) {
+            return Err(BuildError::OutOfOrder {
+                index: i + 1,
+                last: elements[i].0,
+                ts: elements[i + 1].0,
+            });
+        }
+
+        Ok(Self { values: elements })
+    }
+
+    /// Returns the indices of elements with the same timestamp as the element
+    /// before them. The series must be sorted.
+    pub fn duplicate_indices(&self) -> Vec<usize> {
         self.values
-            .last()
-            .unwrap_or(&(0, Sample::zero()).into())
-            .1
-            .val()
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0].0 == pair[1].0)
+            .map(|(i, _)| i + 1)
+            .collect()
+    }
+
+    /// Reserve room for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        self.values.reserve(additional);
     }
 
-    /// Add a new sample to the series. The timestamp must be greater than the
-    /// last sample's timestamp.
+    /// Returns the last value in the series, or zero if it's empty. Use
+    /// `try_last_val` to tell an empty series from a zero value.
+    pub fn last_val(&self) -> T {
+        self.try_last_val().unwrap_or_else(T::zero)
+    }
+
+    /// Returns the last value in the series, or `None` if it's empty.
+    pub fn try_last_val(&self) -> Option<T> {
+        self.last().map(|e| e.1.val())
+    }
+
+    /// Returns the oldest element in the series.
+    pub fn first(&self) -> Option<&Element<T>> {
+        self.values.first()
+    }
+
+    /// Returns the newest element in the series.
+    pub fn last(&self) -> Option<&Element<T>> {
+        self.values.last()
+    }
+
+    /// Returns the timestamp of the oldest element.
+    pub fn start_ts(&self) -> Option<TimeStamp> {
+        self.first().map(|e| e.0)
+    }
+
+    /// Returns the timestamp of the newest element.
+    pub fn end_ts(&self) -> Option<TimeStamp> {
+        self.last().map(|e| e.0)
+    }
+
+    /// Returns the time between the oldest and newest elements.
+    pub fn time_span(&self) -> Option<Duration> {
+        Some(Interval((self.end_ts()? - self.start_ts()?).millis()))
+    }
+
+    /// Add a new sample to the series. The timestamp must not be less than the
+    /// last sample's timestamp; this is only checked in debug builds (see
+    /// `try_push` for a checked variant).
     pub fn push(&mut self, ts: TimeStamp, value: T) {
         self.push_sample(ts, Sample::point(value))
     }
 
-    /// Add a new sample to the series. The timestamp must be greater than the
-    /// last sample's timestamp.
+    /// Add a new sample to the series. The timestamp must not be less than the
+    /// last sample's timestamp; this is only checked in debug builds.
     pub fn push_sample(&mut self, ts: TimeStamp, sample: Sample<T>) {
+        debug_assert!(
+            self.last().is_none_or(|last| last.0 <= ts),
+            "out of order push at {}",
+            ts
+        );
         self.values.push((ts, sample).into());
     }
 
+    /// Add a new sample stamped with the current time.
+    pub fn push_now(&mut self, value: T) {
+        self.push(TimeStamp::now(), value)
+    }
+
+    /// Append a batch of samples in one go, reserving capacity once. The
+    /// batch must be ordered and not older than the last sample; equal
+    /// timestamps are accepted, unlike `try_push`. On failure the whole batch
+    /// is rejected and the series is left untouched. Returns the number of
+    /// samples appended.
+    pub fn push_batch(&mut self, batch: &[(TimeStamp, T)]) -> Result<usize, PushError> {
+        check_batch(self.end_ts(), batch)?;
+
+        self.values.reserve(batch.len());
+        self.values
+            .extend(batch.iter().map(|(ts, v)| Element(*ts, Sample::point(*v))));
+        Ok(batch.len())
+    }
+
+    /// Add a new sample to the series, failing unless the timestamp is
+    /// greater than the last sample's timestamp.
+    pub fn try_push(&mut self, ts: TimeStamp, value: T) -> Result<(), SeriesError> {
+        self.try_push_sample(ts, Sample::point(value))
+    }
+
+    /// Sample variant of `try_push`.
+    pub fn try_push_sample(&mut self, ts: TimeStamp, sample: Sample<T>) -> Result<(), SeriesError> {
+        if let Some(last) = self.last() {
+            if ts < last.0 {
+                return Err(SeriesError::OutOfOrder { last: last.0, ts });
+            }
+            if ts == last.0 {
+                return Err(SeriesError::Duplicate { ts });
+            }
+        }
+
+        self.values.push((ts, sample).into());
+        Ok(())
+    }
+
+    /// Insert a sample at its ordered position, for sources that deliver
+    /// slightly out of order data. Samples with equal timestamps keep their
+    /// arrival order. Cheap when the sample belongs at or near the end.
+    pub fn push_unordered(&mut self, ts: TimeStamp, value: T) {
+        self.push_sample_unordered(ts, Sample::point(value))
+    }
+
+    /// Sample variant of `push_unordered`.
+    pub fn push_sample_unordered(&mut self, ts: TimeStamp, sample: Sample<T>) {
+        let index = self.values.partition_point(|e| e.0 <= ts);
+        self.values.insert(index, (ts, sample).into());
+    }
+
     /// Returns the number of samples in the series.
     pub fn len(&self) -> usize {
         self.values.len()
@@ -51,16 +371,66 @@ impl<T: SampleValue> RawSeries<T> {
         self.values.is_empty()
     }
 
+    /// Returns an iterator over the elements of the series.
+    pub fn iter(&self) -> std::slice::Iter<'_, Element<T>> {
+        self.values.iter()
+    }
+
+    /// Returns an iterator that allows modifying each element. Timestamps
+    /// must stay in order.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Element<T>> {
+        self.values.iter_mut()
+    }
+
     /// Get the sample at the given index.
     pub fn get(&self, index: usize) -> Option<&Element<T>> {
         self.values.get(index)
     }
 
     /// Return an iterator over windows of the series.
-    pub fn windows(&self, window_size: Interval, start_ts: TimeStamp) -> WindowIter<T> {
+    pub fn windows(&self, window_size: Interval, start_ts: TimeStamp) -> WindowIter<'_, T> {
         WindowIter::new(self, window_size, start_ts)
     }
 
+    /// Return an iterator over windows of `size` starting every `step` from
+    /// `start_ts`. Windows overlap if `step` is smaller than `size`, and with
+    /// `step == size` they are the same as those of `windows`. `size` and
+    /// `step` must be positive.
+    pub fn sliding_windows(
+        &self,
+        size: Interval,
+        step: Interval,
+        start_ts: TimeStamp,
+    ) -> anyhow::Result<SlidingWindowIter<'_, T>> {
+        SlidingWindowIter::new(self, size, step, start_ts)
+    }
+
+    /// Return an iterator over consecutive, non-overlapping runs of `n`
+    /// elements; the last may be shorter. Each run can be passed to an
+    /// `ops::element::Op`. Panics if `n` is zero.
+    pub fn chunk_windows(&self, n: usize) -> impl Iterator<Item = &[Element<T>]> {
+        self.values.chunks(n)
+    }
+
+    /// Split the series into sessions: runs of samples where each is at most
+    /// `gap` after the previous one. Returns a `Window::Range` per session.
+    pub fn session_windows(&self, gap: Duration) -> Vec<Window> {
+        let mut windows = vec![];
+        let mut start = 0;
+
+        for (i, pair) in self.values.windows(2).enumerate() {
+            if pair[1].0.millis() - pair[0].0.millis() > gap.millis() {
+                windows.push(Window::Range(start, i));
+                start = i + 1;
+            }
+        }
+        if !self.values.is_empty() {
+            windows.push(Window::Range(start, self.values.len() - 1));
+        }
+
+        windows
+    }
+
     /// Returns the nearest sample after or equal to the given timestamp.
     pub fn at_or_after(&self, ts: TimeStamp) -> Option<&Element<T>> {
         // Binary search for the first sample with a timestamp greater than or
@@ -83,6 +453,388 @@ impl<T: SampleValue> RawSeries<T> {
             None
         }
     }
+
+    /// Returns the nearest sample before or equal to the given timestamp. If
+    /// several samples share that timestamp, the last of them is returned.
+    pub fn at_or_before(&self, ts: TimeStamp) -> Option<&Element<T>> {
+        // Binary search for the first sample with a timestamp strictly greater
+        // than the given timestamp.
+        let mut left = 0;
+        let mut right = self.values.len();
+
+        while left < right {
+            let mid = left + (right - left) / 2;
+            if self.values[mid].0 <= ts {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+
+        if left > 0 {
+            self.get(left - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Binary search for `ts`. Returns `Ok` with the index of a matching
+    /// element, or `Err` with the index where one could be inserted to keep
+    /// the series sorted, like `slice::binary_search`.
+    pub fn index_of(&self, ts: TimeStamp) -> Result<usize, usize> {
+        self.values.binary_search_by_key(&ts, |e| e.0)
+    }
+
+    /// Returns the sample closest to `ts` in either direction, if it's at most
+    /// `tolerance` away. On a tie the earlier sample wins.
+    pub fn nearest(&self, ts: TimeStamp, tolerance: Duration) -> Option<&Element<T>> {
+        let distance = |e: &Element<T>| (e.0.millis() - ts.millis()).abs();
+
+        let nearest = match (self.at_or_before(ts), self.at_or_after(ts)) {
+            (Some(before), Some(after)) if distance(after) < distance(before) => after,
+            (Some(before), _) => before,
+            (None, after) => after?,
+        };
+
+        (distance(nearest) <= tolerance.millis()).then_some(nearest)
+    }
+
+    /// Returns the elements with timestamps in `start..end`: `start` is
+    /// inclusive and `end` exclusive. The slice is empty if no elements fall
+    /// in the range.
+    pub fn range(&self, start: TimeStamp, end: TimeStamp) -> &[Element<T>] {
+        &self.values[self.range_indices(start, end)]
+    }
+
+    /// Iterator variant of `range`.
+    pub fn range_iter(&self, start: TimeStamp, end: TimeStamp) -> std::slice::Iter<'_, Element<T>> {
+        self.range(start, end).iter()
+    }
+
+    /// Index range of the elements with timestamps in `start..end`.
+    fn range_indices(&self, start: TimeStamp, end: TimeStamp) -> Range<usize> {
+        let from = self.values.partition_point(|e| e.0 < start);
+        let to = self.values.partition_point(|e| e.0 < end).max(from);
+        from..to
+    }
+
+    /// Drop all elements with a timestamp before `ts`. Returns the number of
+    /// elements removed. The capacity is kept; call `shrink_to_fit` to
+    /// release it.
+    pub fn truncate_before(&mut self, ts: TimeStamp) -> usize {
+        let index = self.values.partition_point(|e| e.0 < ts);
+        self.values.drain(..index);
+        index
+    }
+
+    /// Keep only the newest `max_len` elements. Returns the number of
+    /// elements removed.
+    pub fn retain_last(&mut self, max_len: usize) -> usize {
+        let index = self.values.len().saturating_sub(max_len);
+        self.values.drain(..index);
+        index
+    }
+
+    /// Keep only the elements for which `pred` returns true, in place, like
+    /// `Vec::retain`. Order is preserved. Returns the number of elements
+    /// removed.
+    pub fn retain(&mut self, pred: impl FnMut(&Element<T>) -> bool) -> usize {
+        let len = self.values.len();
+        self.values.retain(pred);
+        len - self.values.len()
+    }
+
+    /// Returns a new series with only the elements for which `pred` returns
+    /// true, in order. See `retain` to filter in place.
+    pub fn filter(&self, pred: impl Fn(&Element<T>) -> bool) -> RawSeries<T> {
+        RawSeries {
+            values: self.values.iter().filter(|e| pred(e)).cloned().collect(),
+        }
+    }
+
+    /// Remove all `Err` elements. Returns the number removed.
+    pub fn drop_errors(&mut self) -> usize {
+        self.retain(|e| !e.1.is_err())
+    }
+
+    /// Returns a new series with `f` applied to every `Point` and `Fake`
+    /// value, e.g. to convert units. Timestamps, `Err` and `Zero` are kept.
+    pub fn map_values<U: SampleValue>(&self, f: impl Fn(T) -> U) -> RawSeries<U> {
+        RawSeries {
+            values: self
+                .values
+                .iter()
+                .map(|e| Element(e.0, e.1.map(&f)))
+                .collect(),
+        }
+    }
+
+    /// Release unused capacity, e.g. after `truncate_before`.
+    pub fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+    }
+
+    /// Remove the elements with timestamps in `start..end` and return them.
+    pub fn remove_range(&mut self, start: TimeStamp, end: TimeStamp) -> RawSeries<T> {
+        let range = self.range_indices(start, end);
+        RawSeries {
+            values: self.values.drain(range).collect(),
+        }
+    }
+
+    /// Like `remove_range`, but leaves a single `Err` element at `start` to
+    /// mark the span as missing.
+    pub fn remove_range_marked(&mut self, start: TimeStamp, end: TimeStamp) -> RawSeries<T> {
+        let removed = self.remove_range(start, end);
+        let index = self.values.partition_point(|e| e.0 < start);
+        self.values.insert(index, (start, Sample::Err).into());
+        removed
+    }
+
+    /// Split the series into the elements before `ts` and those at or after
+    /// it.
+    pub fn split_at_ts(mut self, ts: TimeStamp) -> (RawSeries<T>, RawSeries<T>) {
+        let index = self.values.partition_point(|e| e.0 < ts);
+        let after = self.values.split_off(index);
+        (self, RawSeries { values: after })
+    }
+
+    /// Returns the indices at which a new `interval`-aligned period starts,
+    /// i.e. the split points for chunking the series into consecutive
+    /// intervals. The first element's index is not included. The interval
+    /// must be positive.
+    pub fn split_points(&self, interval: Interval) -> Vec<usize> {
+        let period = |e: &Element<T>| e.0.millis().div_euclid(interval.millis());
+
+        self.values
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| period(&pair[0]) != period(&pair[1]))
+            .map(|(i, _)| i + 1)
+            .collect()
+    }
+}
+
+impl<T: SampleValue> RawSeries<T> {
+    /// Returns a new series with the elements of `self` and `other`
+    /// interleaved by timestamp. Elements sharing a timestamp are resolved
+    /// with `policy`, with those from `self` ordered before those from
+    /// `other`. Both series must be sorted.
+    pub fn merge(&self, other: &RawSeries<T>, policy: DedupPolicy<T>) -> RawSeries<T> {
+        let mut merged = Vec::with_capacity(self.len() + other.len());
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.len() && j < other.len() {
+            if other.values[j].0 < self.values[i].0 {
+                merged.push(other.values[j].clone());
+                j += 1;
+            } else {
+                merged.push(self.values[i].clone());
+                i += 1;
+            }
+        }
+        merged.extend_from_slice(&self.values[i..]);
+        merged.extend_from_slice(&other.values[j..]);
+
+        RawSeries {
+            values: policy.dedup(merged),
+        }
+    }
+
+    /// Merge `other` into `self` in place, interleaving elements by timestamp.
+    /// Where an element of `self` and one of `other` share a timestamp, they
+    /// are replaced by `conflict(ours, theirs)`, or both kept (ours first) if
+    /// `conflict` is `None`. Unlike `merge`, duplicates within either series
+    /// are left alone. Both series must be sorted.
+    pub fn merge_with(&mut self, other: &RawSeries<T>, conflict: Option<MergeConflict<T>>) {
+        let ours = std::mem::take(&mut self.values);
+        let mut merged = Vec::with_capacity(ours.len() + other.len());
+        let (mut i, mut j) = (0, 0);
+
+        while i < ours.len() && j < other.len() {
+            let (a, b) = (&ours[i], &other.values[j]);
+            match conflict {
+                Some(resolve) if a.0 == b.0 => {
+                    merged.push((a.0, resolve(a.1, b.1)).into());
+                    i += 1;
+                    j += 1;
+                }
+                _ if b.0 < a.0 => {
+                    merged.push(b.clone());
+                    j += 1;
+                }
+                _ => {
+                    merged.push(a.clone());
+                    i += 1;
+                }
+            }
+        }
+        merged.extend_from_slice(&ours[i..]);
+        merged.extend_from_slice(&other.values[j..]);
+
+        self.values = merged;
+    }
+
+    /// Append `other` to the end of the series. Fails, leaving the series
+    /// untouched, unless `other` starts strictly after the last element;
+    /// see `append_overlapping` to merge instead.
+    pub fn append(&mut self, other: RawSeries<T>) -> Result<(), AppendError> {
+        if let (Some(last), Some(first)) = (self.end_ts(), other.start_ts()) {
+            if first <= last {
+                return Err(AppendError::Overlap { last, first });
+            }
+        }
+
+        self.values.extend(other.values);
+        Ok(())
+    }
+
+    /// Append `other`, merging it with the elements of the series from its
+    /// first timestamp on as `merge` does, with `policy` resolving shared
+    /// timestamps. Elements before the overlap are left alone.
+    pub fn append_overlapping(&mut self, other: RawSeries<T>, policy: DedupPolicy<T>) {
+        let Some(start) = other.start_ts() else {
+            return;
+        };
+
+        let split = self.values.partition_point(|e| e.0 < start);
+        let tail = RawSeries {
+            values: self.values.split_off(split),
+        };
+        self.values.extend(tail.merge(&other, policy).values);
+    }
+
+    /// Collapse elements sharing a timestamp according to `policy`, in one
+    /// pass. The series must be sorted, e.g. built with `push_unordered`.
+    /// Returns the number of elements removed.
+    pub fn dedup_by_ts(&mut self, policy: DedupPolicy<T>) -> usize {
+        let len = self.values.len();
+        self.values = policy.dedup(std::mem::take(&mut self.values));
+        len - self.values.len()
+    }
+
+    /// Aggregate the series into windows of `interval` with `op`. The first
+    /// window starts at the first element's timestamp aligned down to the
+    /// interval, and the last window holds the last element.
+    pub fn downsample(
+        &self,
+        interval: Interval,
+        op: element::Op<T>,
+    ) -> anyhow::Result<AlignedSeries<T>> {
+        let Some(start_ts) = self.start_ts() else {
+            anyhow::bail!("can't downsample an empty series");
+        };
+        AlignedSeries::from_raw_series(
+            self,
+            interval,
+            start_ts.align_millis(interval.millis()),
+            None,
+            op,
+        )
+    }
+
+    /// Aggregate the series into windows of `interval` with `op`, from
+    /// `start_ts` up to `end_ts`.
+    pub fn downsample_between(
+        &self,
+        interval: Interval,
+        start_ts: TimeStamp,
+        end_ts: TimeStamp,
+        op: element::Op<T>,
+    ) -> anyhow::Result<AlignedSeries<T>> {
+        AlignedSeries::from_raw_series(self, interval, start_ts, Some(end_ts), op)
+    }
+
+    /// Returns the value transitions in the series, as
+    /// `AlignedSeries::changes` does.
+    pub fn changes(&self, eps: f64, skip_fake: bool) -> Vec<ChangeEvent<T>> {
+        crate::element::changes(self.values.iter().map(|e| (e.0, e.1)), eps, skip_fake)
+    }
+
+    /// Returns a new series merging all of `series`, as `merge` does for two.
+    /// Elements sharing a timestamp are ordered by the position of their
+    /// series in the slice.
+    pub fn merge_all(series: &[RawSeries<T>], policy: DedupPolicy<T>) -> RawSeries<T> {
+        let mut merged = series
+            .iter()
+            .flat_map(|s| s.values.iter().cloned())
+            .collect::<Vec<_>>();
+        merged.sort_by_key(|e| e.0);
+
+        RawSeries {
+            values: policy.dedup(merged),
+        }
+    }
+}
+
+impl<T: SampleValue> RawSeries<T> {
+    /// Group elements by a calendar field in the timezone `tz`, e.g. by hour
+    /// of day. Unlike windows, buckets fold across the whole series, so
+    /// elements from different days can land in the same bucket.
+    pub fn group_by_calendar<Tz: chrono::TimeZone>(
+        &self,
+        unit: CalendarUnit,
+        tz: &Tz,
+    ) -> BTreeMap<u32, Vec<&Element<T>>> {
+        let mut groups: BTreeMap<u32, Vec<&Element<T>>> = BTreeMap::new();
+        for element in self.iter() {
+            groups
+                .entry(unit.key(element.0, tz))
+                .or_default()
+                .push(element);
+        }
+        groups
+    }
+
+    /// Like `group_by_calendar`, but reduces each bucket with `op`.
+    pub fn aggregate_by_calendar<Tz: chrono::TimeZone>(
+        &self,
+        unit: CalendarUnit,
+        tz: &Tz,
+        op: element::Op<T>,
+    ) -> BTreeMap<u32, Sample<T>> {
+        self.group_by_calendar(unit, tz)
+            .into_iter()
+            .map(|(key, elements)| {
+                let elements = elements.into_iter().cloned().collect::<Vec<_>>();
+                (key, op(&elements))
+            })
+            .collect()
+    }
+}
+
+impl<T: SampleValueOp<T>> RawSeries<T> {
+    /// Returns the per-second rate between each pair of consecutive elements,
+    /// stamped at the later one, so the result has one element fewer. Values
+    /// are treated as a counter: decreases and `Zero` markers are resets, as
+    /// in `ops::sample::delta`. Pairs with no elapsed time, or with an `Err`,
+    /// yield `Err`.
+    pub fn rate(&self) -> RawSeries<f64> {
+        let mut rates = RawSeries::with_capacity(self.len().saturating_sub(1));
+
+        for pair in self.values.windows(2) {
+            let (prev, last) = (&pair[0], &pair[1]);
+            let secs = (last.0 - prev.0).millis() as f64 / 1000.0;
+
+            let rate = match counter_delta(&prev.1, &last.1) {
+                Sample::Point(delta) if secs > 0.0 => delta.to_f64().map(|d| d / secs),
+                _ => None,
+            };
+            rates.push_sample(last.0, rate.map_or(Sample::Err, Sample::Point));
+        }
+
+        rates
+    }
+}
+
+/// A series deserializes from a sequence of elements, which must be in
+/// timestamp order.
+#[cfg(feature = "serde")]
+impl<'de, T: SampleValue + serde::Deserialize<'de>> serde::Deserialize<'de> for RawSeries<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<Element<T>>::deserialize(deserializer)?;
+        Self::from_elements(values, false).map_err(serde::de::Error::custom)
+    }
 }
 
 impl<T: SampleValue> Default for RawSeries<T> {
@@ -93,18 +845,110 @@ impl<T: SampleValue> Default for RawSeries<T> {
 
 impl<T: SampleValue> fmt::Display for RawSeries<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for sample in self.values.iter() {
+        for sample in self.iter() {
             write!(f, "\n {}", sample)?;
         }
         Ok(())
     }
 }
 
+impl<'a, T: SampleValue> IntoIterator for &'a RawSeries<T> {
+    type Item = &'a Element<T>;
+    type IntoIter = std::slice::Iter<'a, Element<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T: SampleValue> IntoIterator for &'a mut RawSeries<T> {
+    type Item = &'a mut Element<T>;
+    type IntoIter = std::slice::IterMut<'a, Element<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T: SampleValue> IntoIterator for RawSeries<T> {
+    type Item = Element<T>;
+    type IntoIter = std::vec::IntoIter<Element<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.into_iter()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::sample::SampleEquals;
 
+    /// A series of `(timestamp, value)` pairs, which must be in order.
+    fn build(elements: &[(i64, i64)]) -> RawSeries<i64> {
+        let mut series = RawSeries::new();
+        for (ts, v) in elements {
+            series.push(TimeStamp(*ts), *v);
+        }
+        series
+    }
+
+    /// The `(timestamp, value)` pairs of `series`.
+    fn contents(series: &RawSeries<i64>) -> Vec<(i64, i64)> {
+        series.iter().map(|e| (e.0 .0, e.1.val())).collect()
+    }
+
+    #[test]
+    fn chunk_windows() {
+        let mut series = RawSeries::new();
+        for i in 0..10 {
+            series.push(TimeStamp(i * 10), i);
+        }
+
+        let chunks = series.chunk_windows(3).collect::<Vec<_>>();
+        assert_eq!(
+            chunks.iter().map(|c| c.len()).collect::<Vec<_>>(),
+            [3, 3, 3, 1]
+        );
+        assert_eq!(chunks[1][0].0, TimeStamp(30));
+
+        let sums = series
+            .chunk_windows(3)
+            .map(element::sum)
+            .map(|s| s.val())
+            .collect::<Vec<_>>();
+        assert_eq!(sums, [3, 12, 21, 9]);
+
+        assert_eq!(RawSeries::<i64>::new().chunk_windows(3).count(), 0);
+    }
+
+    #[test]
+    fn session_windows() {
+        let mut series = RawSeries::new();
+        for ts in [100, 110, 130, 135, 10_000, 10_020, 10_045] {
+            series.push(TimeStamp(ts), ts);
+        }
+
+        let ranges = |gap| {
+            series
+                .session_windows(Interval(gap))
+                .into_iter()
+                .map(|w| match w {
+                    Window::Range(start, end) => (start, end),
+                    Window::Empty => panic!("empty session"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ranges(1_000), [(0, 3), (4, 6)]);
+        // A gap equal to the limit doesn't split a session.
+        assert_eq!(ranges(20), [(0, 3), (4, 5), (6, 6)]);
+        assert_eq!(ranges(100_000), [(0, 6)]);
+
+        assert!(RawSeries::<i64>::new()
+            .session_windows(Interval(10))
+            .is_empty());
+    }
+
     #[test]
     fn nearest_after() {
         let mut series = RawSeries::new();
@@ -206,4 +1050,954 @@ mod tests {
 
         assert!(series.at_or_after(TimeStamp(9001)).is_none());
     }
+
+    #[test]
+    fn iterators() {
+        let mut series = RawSeries::new();
+        for i in 0..10 {
+            series.push(TimeStamp(i * 10), i);
+        }
+
+        assert_eq!(series.iter().len(), 10);
+
+        // The last three samples, newest first.
+        let last = series
+            .iter()
+            .rev()
+            .take(3)
+            .map(|e| e.1.val())
+            .collect::<Vec<_>>();
+        assert_eq!(last, vec![9, 8, 7]);
+
+        for element in series.iter_mut() {
+            element.1 = Sample::point(element.1.val() * 2);
+        }
+        for element in &mut series {
+            element.1 = Sample::point(element.1.val() + 1);
+        }
+
+        let mut count = 0;
+        for (i, element) in (&series).into_iter().enumerate() {
+            assert_eq!(element.0, TimeStamp(i as i64 * 10));
+            assert!(element.1.equals(&Sample::point(i as i64 * 2 + 1)));
+            count += 1;
+        }
+        assert_eq!(count, 10);
+
+        let owned = series.into_iter().collect::<Vec<Element<i64>>>();
+        assert_eq!(owned.len(), 10);
+    }
+
+    #[test]
+    fn group_by_calendar() {
+        let hour = Interval::from_minutes(60).millis();
+
+        // Three days of hourly samples starting at midnight UTC, valued by
+        // the UTC hour.
+        let mut series = RawSeries::new();
+        for i in 0..72 {
+            series.push(TimeStamp(i * hour), i % 24);
+        }
+
+        let utc = chrono::Utc;
+        let groups = series.group_by_calendar(CalendarUnit::HourOfDay, &utc);
+        assert_eq!(groups.len(), 24);
+        assert!(groups.values().all(|g| g.len() == 3));
+        assert!(groups[&5].iter().all(|e| e.1.val() == 5));
+
+        // At UTC+5:30 the half-hour offset shifts each sample into the bucket
+        // five hours later.
+        let tz = chrono::FixedOffset::east_opt(5 * 3600 + 1800).unwrap();
+        let groups = series.group_by_calendar(CalendarUnit::HourOfDay, &tz);
+        assert_eq!(groups.len(), 24);
+        assert!(groups[&5].iter().all(|e| e.1.val() == 0));
+        assert!(groups[&2].iter().all(|e| e.1.val() == 21));
+
+        // Jan 1 1970 was a Thursday.
+        let days = series.group_by_calendar(CalendarUnit::DayOfWeek, &utc);
+        assert_eq!(days.keys().copied().collect::<Vec<_>>(), vec![3, 4, 5]);
+        let tz = chrono::FixedOffset::west_opt(3600).unwrap();
+        let days = series.group_by_calendar(CalendarUnit::DayOfMonth, &tz);
+        assert_eq!(days[&31].len(), 1);
+        assert_eq!(days[&1].len(), 24);
+        assert_eq!(days[&3].len(), 23);
+
+        let counts = series.aggregate_by_calendar(CalendarUnit::HourOfDay, &utc, element::count);
+        assert!(counts.values().all(|c| c.val() == 3));
+        let sums = series.aggregate_by_calendar(CalendarUnit::HourOfDay, &tz, element::sum);
+        assert_eq!(sums[&0].val(), 3);
+    }
+
+    #[test]
+    fn retention() {
+        let series = || {
+            let mut series = RawSeries::new();
+            for i in 1..=10 {
+                series.push(TimeStamp(i * 10), i);
+            }
+            series
+        };
+
+        // Before the first element.
+        let mut s = series();
+        assert_eq!(s.truncate_before(TimeStamp(5)), 0);
+        assert_eq!(s.len(), 10);
+
+        // After the last.
+        assert_eq!(s.truncate_before(TimeStamp(101)), 10);
+        assert!(s.is_empty());
+        s.shrink_to_fit();
+        assert_eq!(s.values.capacity(), 0);
+
+        // Exactly on an element, which is kept.
+        let mut s = series();
+        assert_eq!(s.truncate_before(TimeStamp(40)), 3);
+        assert_eq!(s.get(0).unwrap().0, TimeStamp(40));
+        assert_eq!(s.len(), 7);
+
+        // Lookups still work on the trimmed series.
+        assert_eq!(s.at_or_after(TimeStamp(0)).unwrap().0, TimeStamp(40));
+        assert_eq!(s.at_or_after(TimeStamp(41)).unwrap().0, TimeStamp(50));
+        assert!(s.at_or_before(TimeStamp(39)).is_none());
+
+        assert_eq!(s.retain_last(10), 0);
+        assert_eq!(s.retain_last(2), 5);
+        assert_eq!(s.iter().map(|e| e.1.val()).collect::<Vec<_>>(), vec![9, 10]);
+        assert_eq!(s.at_or_after(TimeStamp(40)).unwrap().0, TimeStamp(90));
+        assert!(s.at_or_after(TimeStamp(101)).is_none());
+        assert_eq!(s.retain_last(0), 2);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn retain() {
+        let mut series = RawSeries::new();
+        for i in 0..20 {
+            match i % 5 {
+                0 => series.push_sample(TimeStamp(i * 10), Sample::Err),
+                _ => series.push(TimeStamp(i * 10), i),
+            }
+        }
+        let capacity = series.values.capacity();
+
+        assert_eq!(series.drop_errors(), 4);
+        assert_eq!(series.drop_errors(), 0);
+        assert_eq!(series.len(), 16);
+        assert_eq!(series.values.capacity(), capacity);
+
+        // Drop everything in 100..150.
+        assert_eq!(series.retain(|e| !(10..15).contains(&e.1.val())), 4);
+        assert_eq!(series.len(), 12);
+
+        // The windows over the gap are empty, the others keep their counts.
+        let counts = series
+            .windows(Interval(50), TimeStamp(0))
+            .map(|w| match w {
+                Window::Range(start, end) => end - start + 1,
+                Window::Empty => 0,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![4, 4, 0, 4]);
+    }
+
+    #[test]
+    fn filter() {
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(10), 1);
+        series.push_sample(TimeStamp(20), Sample::Err);
+        series.push(TimeStamp(30), 3);
+        series.push_sample(TimeStamp(40), Sample::Err);
+        series.push(TimeStamp(50), 5);
+
+        let clean = series.filter(|e| !e.1.is_err());
+        assert_eq!(
+            clean
+                .iter()
+                .map(|e| (e.0 .0, e.1.val()))
+                .collect::<Vec<_>>(),
+            vec![(10, 1), (30, 3), (50, 5)]
+        );
+        assert!(clean.iter().all(|e| !e.1.is_err()));
+        assert_eq!(series.len(), 5);
+
+        assert_eq!(series.filter(|e| e.1.val() > 1).len(), 2);
+        assert!(series.filter(|_| false).is_empty());
+    }
+
+    #[test]
+    fn map_values() {
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(10), 2048i64);
+        series.push_sample(TimeStamp(20), Sample::Err);
+        series.push_sample(TimeStamp(30), Sample::Zero);
+        series.push_sample(TimeStamp(40), Sample::Fake(512));
+
+        let kib = series.map_values(|v| v as f64 / 1024.0);
+        let expected = [
+            Sample::Point(2.0),
+            Sample::Err,
+            Sample::Zero,
+            Sample::Fake(0.5),
+        ];
+        assert_eq!(kib.len(), 4);
+        for ((a, b), e) in kib.iter().zip(series.iter()).zip(expected.iter()) {
+            assert_eq!(a.0, b.0);
+            assert!(a.1.approx_eq(e, 0.0));
+        }
+    }
+
+    #[test]
+    fn accessors() {
+        let mut series = RawSeries::new();
+        assert!(series.first().is_none());
+        assert!(series.last().is_none());
+        assert_eq!(series.try_last_val(), None);
+        assert_eq!(series.time_span(), None);
+
+        series.push(TimeStamp(10), 0);
+        assert_eq!(series.try_last_val(), Some(0));
+        assert_eq!(series.time_span(), Some(Interval(0)));
+
+        series.push(TimeStamp(35), 7);
+        assert_eq!(series.start_ts(), Some(TimeStamp(10)));
+        assert_eq!(series.end_ts(), Some(TimeStamp(35)));
+        assert_eq!(series.first().unwrap().1.val(), 0);
+        assert_eq!(series.last().unwrap().1.val(), 7);
+        assert_eq!(series.last_val(), 7);
+        assert_eq!(series.time_span(), Some(Interval(25)));
+
+        // Windowing an empty series yields nothing.
+        let empty = RawSeries::<i64>::new();
+        assert_eq!(empty.windows(Interval(10), TimeStamp(0)).count(), 0);
+    }
+
+    #[test]
+    fn last_val_empty() {
+        assert_eq!(RawSeries::<i64>::new().last_val(), 0);
+        assert_eq!(RawSeries::<i64>::new().try_last_val(), None);
+    }
+
+    #[test]
+    fn remove_range() {
+        let series = || {
+            let mut series = RawSeries::new();
+            for ts in [10, 20, 30, 40, 50] {
+                series.push(TimeStamp(ts), ts);
+            }
+            series
+        };
+        let timestamps = |s: &RawSeries<i64>| s.iter().map(|e| e.0 .0).collect::<Vec<_>>();
+
+        // Covers nothing.
+        let mut s = series();
+        assert!(s.remove_range(TimeStamp(21), TimeStamp(30)).is_empty());
+        assert!(s.remove_range(TimeStamp(60), TimeStamp(90)).is_empty());
+        assert!(s.remove_range(TimeStamp(40), TimeStamp(10)).is_empty());
+        assert_eq!(s.len(), 5);
+
+        // Covers everything.
+        let removed = s.remove_range(TimeStamp(0), TimeStamp(100));
+        assert_eq!(timestamps(&removed), vec![10, 20, 30, 40, 50]);
+        assert!(s.is_empty());
+
+        // Partial overlap at the start and at the end; `end` is exclusive.
+        let mut s = series();
+        let removed = s.remove_range(TimeStamp(0), TimeStamp(20));
+        assert_eq!(timestamps(&removed), vec![10]);
+        let removed = s.remove_range(TimeStamp(40), TimeStamp(70));
+        assert_eq!(timestamps(&removed), vec![40, 50]);
+        assert_eq!(timestamps(&s), vec![20, 30]);
+
+        let mut s = series();
+        let removed = s.remove_range_marked(TimeStamp(15), TimeStamp(45));
+        assert_eq!(timestamps(&removed), vec![20, 30, 40]);
+        assert_eq!(timestamps(&s), vec![10, 15, 50]);
+        assert!(s.get(1).unwrap().1.is_err());
+    }
+
+    #[test]
+    fn split() {
+        let series = || {
+            let mut series = RawSeries::new();
+            for ts in [5, 10, 25, 30, 31, 75] {
+                series.push(TimeStamp(ts), ts);
+            }
+            series
+        };
+
+        let (before, after) = series().split_at_ts(TimeStamp(0));
+        assert!(before.is_empty());
+        assert_eq!(after.len(), 6);
+
+        let (before, after) = series().split_at_ts(TimeStamp(100));
+        assert_eq!(before.len(), 6);
+        assert!(after.is_empty());
+
+        let (before, after) = series().split_at_ts(TimeStamp(25));
+        assert_eq!(
+            before.iter().map(|e| e.0 .0).collect::<Vec<_>>(),
+            vec![5, 10]
+        );
+        assert_eq!(
+            after.iter().map(|e| e.0 .0).collect::<Vec<_>>(),
+            vec![25, 30, 31, 75]
+        );
+
+        // Periods [0, 20), [20, 40) and [60, 80).
+        assert_eq!(series().split_points(Interval(20)), vec![2, 5]);
+        assert!(series().split_points(Interval(100)).is_empty());
+        assert!(RawSeries::<i64>::new()
+            .split_points(Interval(20))
+            .is_empty());
+    }
+
+    #[test]
+    fn checked_push() {
+        let mut series = RawSeries::new();
+        assert!(series.try_push(TimeStamp(10), 1).is_ok());
+        assert!(series.try_push(TimeStamp(20), 2).is_ok());
+
+        // Equal timestamps and regressions are rejected and leave the series
+        // untouched.
+        assert_eq!(
+            series.try_push(TimeStamp(20), 3),
+            Err(SeriesError::Duplicate { ts: TimeStamp(20) })
+        );
+        assert_eq!(
+            series.try_push(TimeStamp(19), 4),
+            Err(SeriesError::OutOfOrder {
+                last: TimeStamp(20),
+                ts: TimeStamp(19)
+            })
+        );
+        assert_eq!(series.len(), 2);
+
+        // `push` still takes equal timestamps.
+        series.push(TimeStamp(20), 3);
+        assert_eq!(series.len(), 3);
+    }
+
+    #[test]
+    fn from_vec() {
+        let data = vec![
+            (TimeStamp(30), 3),
+            (TimeStamp(10), 1),
+            (TimeStamp(20), 2),
+            (TimeStamp(10), 4),
+        ];
+
+        // Sorting is stable, so duplicates keep their input order.
+        let series = RawSeries::from_vec(data.clone(), true).unwrap();
+        let elements = series
+            .iter()
+            .map(|e| (e.0 .0, e.1.val()))
+            .collect::<Vec<_>>();
+        assert_eq!(elements, vec![(10, 1), (10, 4), (20, 2), (30, 3)]);
+        assert_eq!(series.duplicate_indices(), vec![1]);
+
+        let err = RawSeries::from_vec(data, false).unwrap_err();
+        assert_eq!(
+            err,
+            BuildError::OutOfOrder {
+                index: 1,
+                last: TimeStamp(30),
+                ts: TimeStamp(10)
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "element 1: timestamp 10 is older than last sample 30"
+        );
+
+        // Duplicates are tolerated without sorting too.
+        let data = vec![(TimeStamp(10), 1), (TimeStamp(10), 2), (TimeStamp(20), 3)];
+        let series = RawSeries::from_vec(data, false).unwrap();
+        assert_eq!(series.len(), 3);
+        assert_eq!(series.duplicate_indices(), vec![1]);
+        assert!(RawSeries::<i64>::from_vec(vec![], false)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn push_batch() {
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(10), 1);
+
+        assert_eq!(
+            series.push_batch(&[(TimeStamp(10), 2), (TimeStamp(20), 3), (TimeStamp(30), 4)]),
+            Ok(3)
+        );
+        assert_eq!(series.len(), 4);
+        assert_eq!(series.push_batch(&[]), Ok(0));
+
+        // Older than the tail: nothing is inserted.
+        assert_eq!(
+            series.push_batch(&[(TimeStamp(25), 5), (TimeStamp(40), 6)]),
+            Err(PushError::OutOfOrder {
+                last: TimeStamp(30),
+                ts: TimeStamp(25)
+            })
+        );
+        assert_eq!(series.len(), 4);
+
+        // Out of order within the batch: the valid prefix isn't inserted
+        // either.
+        assert_eq!(
+            series.push_batch(&[(TimeStamp(40), 5), (TimeStamp(60), 6), (TimeStamp(50), 7)]),
+            Err(PushError::OutOfOrder {
+                last: TimeStamp(60),
+                ts: TimeStamp(50)
+            })
+        );
+        assert_eq!(series.len(), 4);
+        assert_eq!(series.last_val(), 4);
+
+        let before = TimeStamp::now();
+        series.push_now(8);
+        assert!(series.end_ts().unwrap() >= before);
+        assert_eq!(series.last_val(), 8);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn unchecked_push_regression() {
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(20), 1);
+        series.push(TimeStamp(10), 2);
+    }
+
+    #[test]
+    fn unordered_push() {
+        let mut series = RawSeries::new();
+        for (ts, v) in [(10, 0), (30, 1), (20, 2), (40, 3), (5, 4), (20, 5), (35, 6)] {
+            series.push_unordered(TimeStamp(ts), v);
+        }
+
+        let timestamps = series.values.iter().map(|e| e.0 .0).collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![5, 10, 20, 20, 30, 35, 40]);
+
+        // Equal timestamps keep their arrival order.
+        assert!(series.values[2].1.equals(&Sample::point(2)));
+        assert!(series.values[3].1.equals(&Sample::point(5)));
+
+        assert!(series
+            .at_or_after(TimeStamp(31))
+            .unwrap()
+            .1
+            .equals(&Sample::point(6)));
+    }
+
+    #[test]
+    fn rate() {
+        let mut series = RawSeries::new();
+        series.push(TimeStamp(0), 10);
+        series.push(TimeStamp(2_000), 30);
+        series.push(TimeStamp(2_500), 40);
+        series.push(TimeStamp(2_500), 45);
+        series.push(TimeStamp(6_500), 5);
+        series.push_sample(TimeStamp(7_000), Sample::Zero);
+        series.push(TimeStamp(9_000), 8);
+        series.push_sample(TimeStamp(10_000), Sample::Err);
+
+        let rates = series.rate();
+        assert_eq!(rates.len(), series.len() - 1);
+
+        // 10/s, 20/s, no elapsed time, a reset to 5 over 4s, the reset marker
+        // itself, 8 since the reset over 2s, and an Err.
+        let expected = [
+            (2_000, Some(10.0)),
+            (2_500, Some(20.0)),
+            (2_500, None),
+            (6_500, Some(1.25)),
+            (7_000, Some(0.0)),
+            (9_000, Some(4.0)),
+            (10_000, None),
+        ];
+        for (got, (ts, want)) in rates.iter().zip(expected) {
+            assert_eq!(got.0, TimeStamp(ts));
+            match want {
+                Some(want) => assert!(got.1.approx_eq(&Sample::Point(want), 1e-9)),
+                None => assert!(got.1.is_err()),
+            }
+        }
+
+        assert!(RawSeries::<i64>::new().rate().is_empty());
+    }
+
+    #[test]
+    fn downsample() {
+        let mut series = RawSeries::new();
+        for ts in [13, 15, 22, 28, 41, 47] {
+            series.push(TimeStamp(ts), ts);
+        }
+
+        let downsampled = series.downsample(Interval(10), element::sum).unwrap();
+        let expected = AlignedSeries::from_raw_series(
+            &series,
+            Interval(10),
+            TimeStamp(10),
+            None,
+            element::sum,
+        )
+        .unwrap();
+        assert_eq!(downsampled.start_ts, TimeStamp(10));
+        assert_eq!(downsampled.len(), 4);
+        assert!(downsampled.approx_eq(&expected, 0.0));
+
+        let between = series
+            .downsample_between(Interval(10), TimeStamp(20), TimeStamp(40), element::sum)
+            .unwrap();
+        let expected = AlignedSeries::from_raw_series(
+            &series,
+            Interval(10),
+            TimeStamp(20),
+            Some(TimeStamp(40)),
+            element::sum,
+        )
+        .unwrap();
+        assert_eq!(between.len(), 2);
+        assert!(between.approx_eq(&expected, 0.0));
+        assert_eq!(between.values[0].val(), 50);
+
+        assert!(RawSeries::<i64>::new()
+            .downsample(Interval(10), element::sum)
+            .is_err());
+        assert!(series
+            .downsample_between(Interval(10), TimeStamp(40), TimeStamp(20), element::sum)
+            .is_err());
+    }
+
+    #[test]
+    fn dedup_by_ts() {
+        let series = || {
+            let mut series = RawSeries::new();
+            for (ts, v) in [(30, 1), (10, 2), (20, 3), (10, 4), (30, 5), (30, 6)] {
+                series.push_unordered(TimeStamp(ts), v);
+            }
+            series
+        };
+        let values = |s: &RawSeries<i64>| s.iter().map(|e| (e.0 .0, e.1.val())).collect::<Vec<_>>();
+
+        let mut s = series();
+        assert_eq!(s.dedup_by_ts(DedupPolicy::KeepFirst), 3);
+        assert_eq!(values(&s), vec![(10, 2), (20, 3), (30, 1)]);
+
+        let mut s = series();
+        assert_eq!(s.dedup_by_ts(DedupPolicy::KeepLast), 3);
+        assert_eq!(values(&s), vec![(10, 4), (20, 3), (30, 6)]);
+
+        let mut s = series();
+        assert_eq!(s.dedup_by_ts(DedupPolicy::Combine(element::sum)), 3);
+        assert_eq!(values(&s), vec![(10, 6), (20, 3), (30, 12)]);
+        assert_eq!(s.dedup_by_ts(DedupPolicy::KeepFirst), 0);
+    }
+
+    #[test]
+    fn append() {
+        let mut series = build(&[(10, 1), (20, 2)]);
+        assert!(series.append(build(&[(30, 3)])).is_ok());
+        assert!(series.append(RawSeries::new()).is_ok());
+
+        // Touching the last timestamp counts as overlap.
+        let err = series.append(build(&[(30, 4), (40, 5)])).unwrap_err();
+        assert_eq!(
+            err,
+            AppendError::Overlap {
+                last: TimeStamp(30),
+                first: TimeStamp(30)
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "appended series starts at 30, not after last sample 30"
+        );
+        assert_eq!(contents(&series), vec![(10, 1), (20, 2), (30, 3)]);
+
+        let mut empty = RawSeries::new();
+        assert!(empty.append(build(&[(0, 0)])).is_ok());
+        assert_eq!(empty.len(), 1);
+
+        // Overlapping appends merge from the other series' start on.
+        let mut a = build(&[(10, 1), (10, 2), (20, 3), (30, 4)]);
+        a.append_overlapping(
+            build(&[(20, 30), (25, 35), (40, 50)]),
+            DedupPolicy::KeepLast,
+        );
+        assert_eq!(
+            contents(&a),
+            vec![(10, 1), (10, 2), (20, 30), (25, 35), (30, 4), (40, 50)]
+        );
+
+        let mut a = build(&[(10, 1), (20, 3)]);
+        a.append_overlapping(build(&[(20, 30)]), DedupPolicy::Combine(element::sum));
+        assert_eq!(contents(&a), vec![(10, 1), (20, 33)]);
+    }
+
+    #[test]
+    fn merged_iter() {
+        let series = [
+            build(&[(0, 1), (20, 2), (40, 3)]),
+            RawSeries::new(),
+            build(&[(10, 10), (20, 20), (30, 30), (50, 50)]),
+            build(&[(20, 100)]),
+        ];
+
+        let merged = super::merged_iter(&series)
+            .map(|e| (e.0 .0, e.1.val()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            merged,
+            vec![
+                (0, 1),
+                (10, 10),
+                (20, 2),
+                (20, 20),
+                (20, 100),
+                (30, 30),
+                (40, 3),
+                (50, 50)
+            ]
+        );
+
+        // Matches merge_all, which does materialize.
+        let all = RawSeries::merge_all(&series, DedupPolicy::Combine(element::sum));
+        let mut merged = RawSeries::new();
+        for e in super::merged_iter(&series) {
+            merged.push_sample(e.0, e.1);
+        }
+        merged.dedup_by_ts(DedupPolicy::Combine(element::sum));
+        assert_eq!(merged.len(), all.len());
+
+        // Starting part way skips earlier elements in every series.
+        let from = super::merged_iter_from(&series, TimeStamp(20))
+            .map(|e| (e.0 .0, e.1.val()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            from,
+            vec![(20, 2), (20, 20), (20, 100), (30, 30), (40, 3), (50, 50)]
+        );
+        assert_eq!(super::merged_iter_from(&series, TimeStamp(51)).count(), 0);
+
+        assert_eq!(super::merged_iter::<i64>(&[]).count(), 0);
+        assert_eq!(
+            super::merged_iter(&[RawSeries::<i64>::new(), RawSeries::new()]).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = RawSeries::new();
+        let mut b = RawSeries::new();
+        for (ts, v) in [(0, 1), (20, 2), (30, 3), (50, 4)] {
+            a.push(TimeStamp(ts), v);
+        }
+        for (ts, v) in [(10, 10), (20, 20), (40, 40), (50, 50), (60, 60)] {
+            b.push(TimeStamp(ts), v);
+        }
+
+        let values = |s: &RawSeries<i64>| {
+            s.values
+                .iter()
+                .map(|e| (e.0 .0, e.1.val()))
+                .collect::<Vec<_>>()
+        };
+
+        let merged = a.merge(&b, DedupPolicy::KeepFirst);
+        assert_eq!(
+            values(&merged),
+            vec![
+                (0, 1),
+                (10, 10),
+                (20, 2),
+                (30, 3),
+                (40, 40),
+                (50, 4),
+                (60, 60)
+            ]
+        );
+
+        let merged = a.merge(&b, DedupPolicy::KeepLast);
+        assert_eq!(
+            values(&merged),
+            vec![
+                (0, 1),
+                (10, 10),
+                (20, 20),
+                (30, 3),
+                (40, 40),
+                (50, 50),
+                (60, 60)
+            ]
+        );
+
+        let merged = a.merge(&b, DedupPolicy::Combine(element::sum));
+        assert_eq!(
+            values(&merged),
+            vec![
+                (0, 1),
+                (10, 10),
+                (20, 22),
+                (30, 3),
+                (40, 40),
+                (50, 54),
+                (60, 60)
+            ]
+        );
+        assert!(merged
+            .at_or_after(TimeStamp(45))
+            .unwrap()
+            .1
+            .equals(&Sample::point(54)));
+
+        assert_eq!(
+            values(&a.merge(&RawSeries::new(), DedupPolicy::KeepFirst)),
+            values(&a)
+        );
+        assert_eq!(
+            values(&RawSeries::new().merge(&b, DedupPolicy::KeepFirst)),
+            values(&b)
+        );
+
+        let mut c = RawSeries::new();
+        c.push(TimeStamp(5), 100);
+        c.push(TimeStamp(20), 200);
+        let merged = RawSeries::merge_all(&[a, b, c], DedupPolicy::KeepLast);
+        assert_eq!(
+            values(&merged),
+            vec![
+                (0, 1),
+                (5, 100),
+                (10, 10),
+                (20, 200),
+                (30, 3),
+                (40, 40),
+                (50, 50),
+                (60, 60)
+            ]
+        );
+    }
+
+    #[test]
+    fn changes() {
+        let mut series = RawSeries::new();
+        for (ts, v) in [(0, 3), (7, 3), (9, 4), (15, 4), (40, 2)] {
+            series.push(TimeStamp(ts), v);
+        }
+        series.push_sample(TimeStamp(41), Sample::Err);
+
+        let events = series.changes(0.0, false);
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent {
+                    ts: TimeStamp(9),
+                    from: 3,
+                    to: 4
+                },
+                ChangeEvent {
+                    ts: TimeStamp(40),
+                    from: 4,
+                    to: 2
+                },
+            ]
+        );
+        assert_eq!(series.changes(0.5, false).len(), 2);
+        assert!(series.changes(1.5, false).is_empty());
+
+        // Integers that differ by less than f64 can resolve are still told
+        // apart with a zero tolerance.
+        let big = 1i64 << 53;
+        let mut series = RawSeries::new();
+        for (ts, v) in [(0, big), (10, big + 1), (20, big + 1)] {
+            series.push(TimeStamp(ts), v);
+        }
+        assert_eq!(
+            series.changes(0.0, false),
+            vec![ChangeEvent {
+                ts: TimeStamp(10),
+                from: big,
+                to: big + 1
+            }]
+        );
+    }
+
+    #[test]
+    fn nearest_before_random_intervals() {
+        let mut series = RawSeries::new();
+        series.push(0.into(), 0);
+        series.push(200.into(), 1);
+        series.push(350.into(), 2);
+        series.push(500.into(), 3);
+        series.push(1023.into(), 4);
+        series.push(3044.into(), 5);
+        series.push(4033.into(), 6);
+        series.push(9000.into(), 7);
+
+        assert!(series.at_or_before(TimeStamp(-1)).is_none());
+
+        assert_eq!(series.at_or_before(TimeStamp(0)).unwrap().0, 0.into());
+        assert!(series
+            .at_or_before(TimeStamp(0))
+            .unwrap()
+            .1
+            .equals(&Sample::point(0)));
+
+        assert_eq!(series.at_or_before(TimeStamp(199)).unwrap().0, 0.into());
+        assert!(series
+            .at_or_before(TimeStamp(199))
+            .unwrap()
+            .1
+            .equals(&Sample::point(0)));
+
+        assert_eq!(series.at_or_before(TimeStamp(200)).unwrap().0, 200.into());
+        assert!(series
+            .at_or_before(TimeStamp(200))
+            .unwrap()
+            .1
+            .equals(&Sample::point(1)));
+
+        assert_eq!(series.at_or_before(TimeStamp(349)).unwrap().0, 200.into());
+        assert_eq!(series.at_or_before(TimeStamp(350)).unwrap().0, 350.into());
+        assert_eq!(series.at_or_before(TimeStamp(351)).unwrap().0, 350.into());
+        assert_eq!(series.at_or_before(TimeStamp(500)).unwrap().0, 500.into());
+
+        assert_eq!(series.at_or_before(TimeStamp(9000)).unwrap().0, 9000.into());
+        assert!(series
+            .at_or_before(TimeStamp(9001))
+            .unwrap()
+            .1
+            .equals(&Sample::point(7)));
+    }
+
+    #[test]
+    fn index_of_and_nearest() {
+        let mut series = RawSeries::new();
+        for (ts, v) in [(100, 1), (200, 2), (300, 3), (350, 4)] {
+            series.push(TimeStamp(ts), v);
+        }
+
+        assert_eq!(series.index_of(TimeStamp(200)), Ok(1));
+        assert_eq!(series.index_of(TimeStamp(250)), Err(2));
+        assert_eq!(series.index_of(TimeStamp(0)), Err(0));
+        assert_eq!(series.index_of(TimeStamp(400)), Err(4));
+
+        let nearest = |ts, tolerance| {
+            series
+                .nearest(TimeStamp(ts), Interval(tolerance))
+                .map(|e| e.1.val())
+        };
+
+        // A tolerance of zero only matches exactly.
+        assert_eq!(nearest(200, 0), Some(2));
+        assert_eq!(nearest(201, 0), None);
+
+        assert_eq!(nearest(240, 50), Some(2));
+        assert_eq!(nearest(330, 50), Some(4));
+        assert_eq!(nearest(240, 30), None);
+
+        // Ties prefer the earlier sample.
+        assert_eq!(nearest(150, 50), Some(1));
+        assert_eq!(nearest(325, 25), Some(3));
+
+        // Outside the bounds of the series.
+        assert_eq!(nearest(40, 100), Some(1));
+        assert_eq!(nearest(40, 50), None);
+        assert_eq!(nearest(500, 150), Some(4));
+        assert_eq!(nearest(500, 149), None);
+        assert!(RawSeries::<i64>::new()
+            .nearest(TimeStamp(0), Interval(1000))
+            .is_none());
+    }
+
+    #[test]
+    fn range() {
+        let mut series = RawSeries::new();
+        for (ts, v) in [(100, 1), (200, 2), (200, 3), (300, 4), (400, 5)] {
+            series.push(TimeStamp(ts), v);
+        }
+
+        let range = |start, end| {
+            series
+                .range(TimeStamp(start), TimeStamp(end))
+                .iter()
+                .map(|e| e.1.val())
+                .collect::<Vec<_>>()
+        };
+
+        // Bounds between samples.
+        assert_eq!(range(150, 350), vec![2, 3, 4]);
+
+        // Start is inclusive, end exclusive.
+        assert_eq!(range(200, 400), vec![2, 3, 4]);
+        assert_eq!(range(100, 101), vec![1]);
+
+        assert_eq!(range(0, 1000), vec![1, 2, 3, 4, 5]);
+        assert!(range(210, 290).is_empty());
+        assert!(range(500, 600).is_empty());
+        assert!(range(300, 300).is_empty());
+        assert!(range(300, 200).is_empty());
+
+        let sum: i64 = series
+            .range_iter(TimeStamp(150), TimeStamp(350))
+            .map(|e| e.1.val())
+            .sum();
+        assert_eq!(sum, 9);
+    }
+
+    #[test]
+    fn merge_with() {
+        // Interleaved.
+        let mut a = build(&[(10, 1), (30, 3), (50, 5)]);
+        a.merge_with(&build(&[(0, 0), (20, 2), (40, 4), (60, 6)]), None);
+        assert_eq!(
+            contents(&a),
+            vec![(0, 0), (10, 1), (20, 2), (30, 3), (40, 4), (50, 5), (60, 6)]
+        );
+
+        // Duplicates are kept, ours first, without a conflict resolver.
+        let ours = build(&[(10, 1), (20, 2)]);
+        let theirs = build(&[(20, 20), (30, 30)]);
+        let mut a = ours.clone();
+        a.merge_with(&theirs, None);
+        assert_eq!(contents(&a), vec![(10, 1), (20, 2), (20, 20), (30, 30)]);
+
+        // Or resolved pairwise.
+        let mut a = ours.clone();
+        a.merge_with(&theirs, Some(|a, b| Sample::Point(a.val() + b.val())));
+        assert_eq!(contents(&a), vec![(10, 1), (20, 22), (30, 30)]);
+
+        let mut a = ours.clone();
+        a.merge_with(&theirs, Some(|_, b| b));
+        assert_eq!(contents(&a), vec![(10, 1), (20, 20), (30, 30)]);
+
+        let mut empty = RawSeries::new();
+        empty.merge_with(&ours, None);
+        assert_eq!(contents(&empty), contents(&ours));
+    }
+
+    #[test]
+    fn nearest_before_duplicates_and_empty() {
+        assert!(RawSeries::<i64>::new().at_or_before(TimeStamp(0)).is_none());
+
+        let mut series = RawSeries::new();
+        series.push(100.into(), 0);
+        series.push(200.into(), 1);
+        series.push(200.into(), 2);
+        series.push(200.into(), 3);
+        series.push(300.into(), 4);
+
+        assert!(series
+            .at_or_before(TimeStamp(200))
+            .unwrap()
+            .1
+            .equals(&Sample::point(3)));
+        assert!(series
+            .at_or_before(TimeStamp(299))
+            .unwrap()
+            .1
+            .equals(&Sample::point(3)));
+    }
 }
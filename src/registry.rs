@@ -0,0 +1,194 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::RwLock,
+};
+
+use crate::{
+    base::{Interval, TimeStamp},
+    metric::Metric,
+    ops::element,
+    sample::SampleValueOp,
+    AlignedSeries,
+};
+
+/// `ShardedRegistry` holds metrics keyed by name, spread across a fixed number
+/// of shards that are each guarded by their own `RwLock`. Pushes to metrics in
+/// different shards never contend, and read paths (`select`, `aggregate`,
+/// `snapshot`) only take read locks.
+///
+/// Consistency: every operation on a single metric happens under its shard's
+/// lock, so a metric is always observed in a consistent state. Operations that
+/// span metrics (`snapshot`, `sample_count`) visit one shard at a time and
+/// never hold more than one lock, so they may observe some shards before and
+/// others after a concurrent push.
+pub struct ShardedRegistry<T: SampleValueOp<T>> {
+    shards: Vec<RwLock<HashMap<String, Metric<T>>>>,
+}
+
+impl<T: SampleValueOp<T>> ShardedRegistry<T> {
+    /// Create a new registry with `num_shards` shards (at least one).
+    pub fn new(num_shards: usize) -> Self {
+        Self {
+            shards: (0..num_shards.max(1))
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard(&self, name: &str) -> &RwLock<HashMap<String, Metric<T>>> {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        &self.shards[(hasher.finish() % self.shards.len() as u64) as usize]
+    }
+
+    /// Run `f` on the named metric under its shard's write lock, creating the
+    /// metric if it doesn't exist.
+    pub fn get_or_create<R>(&self, name: &str, f: impl FnOnce(&mut Metric<T>) -> R) -> R {
+        let mut shard = self.shard(name).write().unwrap();
+        let metric = shard
+            .entry(name.to_string())
+            .or_insert_with(|| Metric::new(name.to_string()));
+        f(metric)
+    }
+
+    /// Push a raw value to the named metric, creating it if necessary.
+    pub fn push(&self, name: &str, ts: TimeStamp, value: T) {
+        self.get_or_create(name, |metric| metric.push_raw(ts, value));
+    }
+
+    /// Run `f` on the named metric under its shard's read lock. Returns `None`
+    /// if the metric doesn't exist.
+    pub fn select<R>(&self, name: &str, f: impl FnOnce(&Metric<T>) -> R) -> Option<R> {
+        let shard = self.shard(name).read().unwrap();
+        shard.get(name).map(f)
+    }
+
+    /// Aggregate the latest raw series of the named metric into an aligned
+    /// series (see `AlignedSeries::from_raw_series`).
+    pub fn aggregate(
+        &self,
+        name: &str,
+        interval: Interval,
+        start_ts: TimeStamp,
+        end_ts: Option<TimeStamp>,
+        op: element::Op<T>,
+    ) -> anyhow::Result<AlignedSeries<T>> {
+        let shard = self.shard(name).read().unwrap();
+        let Some(raw) = shard.get(name).and_then(|m| m.stream.raw().last()) else {
+            anyhow::bail!("no raw data for metric {}", name);
+        };
+
+        AlignedSeries::from_raw_series(raw, interval, start_ts, end_ts, op)
+    }
+
+    /// Returns the names of all metrics, in no particular order.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = vec![];
+        for shard in self.shards.iter() {
+            names.extend(shard.read().unwrap().keys().cloned());
+        }
+        names
+    }
+
+    /// Returns a copy of every metric. Shards are copied one at a time, so the
+    /// result is only consistent per metric.
+    pub fn snapshot(&self) -> Vec<Metric<T>> {
+        let mut metrics = vec![];
+        for shard in self.shards.iter() {
+            metrics.extend(shard.read().unwrap().values().cloned());
+        }
+        metrics
+    }
+
+    /// Returns the total number of raw samples stored across all metrics.
+    pub fn sample_count(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .read()
+                    .unwrap()
+                    .values()
+                    .map(|m| m.stream.raw().iter().map(|s| s.len()).sum::<usize>())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+}
+
+impl<T: SampleValueOp<T>> Default for ShardedRegistry<T> {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+    use crate::ops::element::sum;
+
+    #[test]
+    fn concurrent_pushes() {
+        let registry = Arc::new(ShardedRegistry::<i64>::new(4));
+        let num_threads = 8;
+        let pushes_per_thread = 1000;
+
+        let handles = (0..num_threads)
+            .map(|t| {
+                let registry = registry.clone();
+                thread::spawn(move || {
+                    let own = format!("metric_{}", t);
+                    for i in 0..pushes_per_thread {
+                        // Disjoint metric, one per thread.
+                        registry.push(&own, TimeStamp(i), 1);
+
+                        // Overlapping metric, shared by all threads. The
+                        // timestamp is picked under the lock to stay ordered.
+                        registry.get_or_create("shared", |metric| {
+                            let ts = metric
+                                .stream
+                                .raw()
+                                .last()
+                                .map(|s| s.len() as i64)
+                                .unwrap_or(0);
+                            metric.push_raw(TimeStamp(ts), 1);
+                        });
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let total = (num_threads * pushes_per_thread * 2) as usize;
+        assert_eq!(registry.sample_count(), total);
+        assert_eq!(registry.names().len(), num_threads as usize + 1);
+        assert_eq!(registry.snapshot().len(), num_threads as usize + 1);
+
+        let shared_len = registry
+            .select("shared", |m| m.stream.raw().last().unwrap().len())
+            .unwrap();
+        assert_eq!(shared_len, (num_threads * pushes_per_thread) as usize);
+
+        let aligned = registry
+            .aggregate("metric_0", Interval(100), TimeStamp(0), None, sum)
+            .unwrap();
+        assert_eq!(aligned.len(), 10);
+        assert!(aligned.values.iter().all(|s| s.val() == 100));
+
+        assert!(registry.select("missing", |_| ()).is_none());
+        assert!(registry
+            .aggregate("missing", Interval(100), TimeStamp(0), None, sum)
+            .is_err());
+    }
+}
@@ -1,7 +1,7 @@
-use num_traits::{Zero, NumCast};
+use num_traits::{One, Zero, NumCast};
 use std::{fmt, ops::{Sub, Div}};
 
-pub trait SampleValue: Zero + Copy + PartialEq + PartialOrd + NumCast + fmt::Display {}
+pub trait SampleValue: Zero + One + Copy + PartialEq + PartialOrd + NumCast + fmt::Display {}
 pub trait SampleValueOp<T>: SampleValue + Div<Output=T> + Sub<Output = T> + Sized {}
 
 impl SampleValue for i32 {}
@@ -20,6 +20,7 @@ pub trait SampleEquals {
     fn equals(&self, other: &Self) -> bool;
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
 #[derive(Debug, Copy, Clone)]
 pub enum Sample<T: SampleValue> {
     Err,
@@ -58,6 +59,49 @@ impl<T: SampleValue> Sample<T> {
             Self::Fake(v) => *v,
         }
     }
+
+    /// Apply `f` to the value of a `Point` or `Fake` sample, keeping the
+    /// variant. `Err` and `Zero` are kept as they are.
+    pub fn map<U: SampleValue>(self, f: impl FnOnce(T) -> U) -> Sample<U> {
+        match self {
+            Self::Err => Sample::Err,
+            Self::Zero => Sample::Zero,
+            Self::Point(v) => Sample::Point(f(v)),
+            Self::Fake(v) => Sample::Fake(f(v)),
+        }
+    }
+
+    /// Returns true if both samples are the same variant and their values are
+    /// within `eps` of each other. `Err` and `Zero` compare exactly.
+    pub fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        let close = |a: &T, b: &T| match (a.to_f64(), b.to_f64()) {
+            (Some(a), Some(b)) => (a - b).abs() <= eps,
+            _ => false,
+        };
+
+        match (self, other) {
+            (Self::Err, Self::Err) => true,
+            (Self::Zero, Self::Zero) => true,
+            (Self::Point(v1), Self::Point(v2)) => close(v1, v2),
+            (Self::Fake(v1), Self::Fake(v2)) => close(v1, v2),
+            _ => false,
+        }
+    }
+}
+
+/// Samples serialize externally tagged with the value as payload, e.g.
+/// `{"Point": 3}`. `Err` and `Zero` carry a unit payload (`{"Err": null}` in
+/// JSON) rather than serializing as bare strings.
+#[cfg(feature = "serde")]
+impl<T: SampleValue + serde::Serialize> serde::Serialize for Sample<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Err => serializer.serialize_newtype_variant("Sample", 0, "Err", &()),
+            Self::Zero => serializer.serialize_newtype_variant("Sample", 1, "Zero", &()),
+            Self::Point(v) => serializer.serialize_newtype_variant("Sample", 2, "Point", v),
+            Self::Fake(v) => serializer.serialize_newtype_variant("Sample", 3, "Fake", v),
+        }
+    }
 }
 
 impl<T: SampleValue> fmt::Display for Sample<T> {
@@ -0,0 +1,437 @@
+//! Fixed-size digests of metrics for overview pages.
+//!
+//! `Metric::summarize` condenses a metric into a handful of numbers per
+//! trailing window, independent of how much history the metric holds. Each
+//! window is computed from the coarsest aligned series that exactly tiles it,
+//! falling back to the raw series.
+//!
+//! `RawSeries::summary` and `AlignedSeries::summary` give whole-series
+//! statistics for quick sanity checks.
+
+use std::fmt;
+
+use crate::{
+    base::{Duration, Interval, TimeStamp},
+    metric::{Metric, Stream},
+    sample::{Sample, SampleValue, SampleValueOp},
+    AlignedSeries, RawSeries,
+};
+
+/// Statistics over a whole series, see `RawSeries::summary`. `Err` samples
+/// are counted but excluded from the numeric fields, which are `None` if no
+/// other samples remain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeriesSummary<T: SampleValue> {
+    /// Number of samples, including `Err`s.
+    pub count: usize,
+    pub err_count: usize,
+    pub fake_count: usize,
+    pub min: Option<T>,
+    pub max: Option<T>,
+    pub mean: Option<f64>,
+    pub first_ts: Option<TimeStamp>,
+    pub last_ts: Option<TimeStamp>,
+}
+
+impl<T: SampleValue> SeriesSummary<T> {
+    fn new(samples: impl Iterator<Item = (TimeStamp, Sample<T>)>) -> Self {
+        let mut summary = Self {
+            count: 0,
+            err_count: 0,
+            fake_count: 0,
+            min: None,
+            max: None,
+            mean: None,
+            first_ts: None,
+            last_ts: None,
+        };
+        let mut sum = 0.0;
+        let mut valid = 0;
+
+        for (ts, sample) in samples {
+            summary.count += 1;
+            summary.first_ts = summary.first_ts.or(Some(ts));
+            summary.last_ts = Some(ts);
+
+            match sample {
+                Sample::Err => {
+                    summary.err_count += 1;
+                    continue;
+                }
+                Sample::Fake(_) => summary.fake_count += 1,
+                _ => {}
+            }
+
+            let v = sample.val();
+            if summary.min.is_none_or(|min| v < min) {
+                summary.min = Some(v);
+            }
+            if summary.max.is_none_or(|max| v > max) {
+                summary.max = Some(v);
+            }
+            if let Some(v) = v.to_f64() {
+                sum += v;
+                valid += 1;
+            }
+        }
+
+        summary.mean = (valid > 0).then(|| sum / valid as f64);
+        summary
+    }
+}
+
+impl<T: SampleValue> fmt::Display for SeriesSummary<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn opt<V: fmt::Display>(v: &Option<V>) -> String {
+            v.as_ref().map_or("-".to_string(), |v| v.to_string())
+        }
+
+        write!(
+            f,
+            "count={} err={} fake={} min={} max={} mean={} first={} last={}",
+            self.count,
+            self.err_count,
+            self.fake_count,
+            opt(&self.min),
+            opt(&self.max),
+            opt(&self.mean),
+            opt(&self.first_ts),
+            opt(&self.last_ts)
+        )
+    }
+}
+
+impl<T: SampleValue> RawSeries<T> {
+    /// Summarize the whole series in one pass.
+    pub fn summary(&self) -> SeriesSummary<T> {
+        SeriesSummary::new(self.iter().map(|e| (e.0, e.1)))
+    }
+}
+
+impl<T: SampleValue> AlignedSeries<T> {
+    /// Summarize the whole series in one pass. Timestamps are those of the
+    /// first and last slots.
+    pub fn summary(&self) -> SeriesSummary<T> {
+        SeriesSummary::new(self.iter())
+    }
+}
+
+/// Where a window's numbers were computed from.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummarySource {
+    /// The raw series.
+    Raw,
+
+    /// The aligned series of the given interval.
+    Aligned(Interval),
+}
+
+/// Summary of a metric over one trailing window. Values are `None` if the
+/// window holds no valid samples.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowSummary {
+    pub window: Duration,
+    pub source: SummarySource,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub last: Option<f64>,
+
+    /// Fraction of the samples (raw) or slots (aligned) in the window that
+    /// are valid.
+    pub completeness: f64,
+}
+
+/// Digest of a metric, see `Metric::summarize`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricSummary {
+    pub now: TimeStamp,
+
+    /// Time since the newest raw sample, or `None` if there are none.
+    pub staleness: Option<Duration>,
+
+    /// Number of raw samples held by the metric.
+    pub total_samples: usize,
+
+    pub windows: Vec<WindowSummary>,
+}
+
+impl<T: SampleValueOp<T>> Metric<T> {
+    /// Summarize the metric over each of the trailing `windows` ending at
+    /// `now` (exclusive).
+    pub fn summarize(&self, now: TimeStamp, windows: &[Duration]) -> MetricSummary {
+        let newest = self
+            .stream
+            .raw()
+            .iter()
+            .filter_map(|raw| raw.end_ts())
+            .max();
+
+        MetricSummary {
+            now,
+            staleness: newest.map(|ts| Interval((now - ts).millis())),
+            total_samples: self.stream.raw().iter().map(|raw| raw.len()).sum(),
+            windows: windows
+                .iter()
+                .map(|window| summarize_window(&self.stream, now, *window))
+                .collect(),
+        }
+    }
+}
+
+fn summarize_window<T: SampleValueOp<T>>(
+    stream: &Stream<T>,
+    now: TimeStamp,
+    window: Duration,
+) -> WindowSummary {
+    let start = TimeStamp(now.millis() - window.millis());
+
+    match best_interval(stream, start, now, window) {
+        Some(interval) => {
+            let samples = stream.aligned[&interval]
+                .values()
+                .flat_map(|segment| segment.iter().map(|(ts, sample)| (ts.millis(), sample)))
+                .filter(|(ts, _)| *ts >= start.millis() && ts + interval.millis() <= now.millis());
+            digest(window, SummarySource::Aligned(interval), samples)
+        }
+        None => {
+            let samples = stream
+                .raw()
+                .iter()
+                .flat_map(|raw| raw.iter())
+                .filter(|e| e.0 >= start && e.0 < now)
+                .map(|e| (e.0.millis(), e.1));
+            digest(window, SummarySource::Raw, samples)
+        }
+    }
+}
+
+/// Returns the largest aligned interval whose slots exactly tile
+/// `start..end`.
+fn best_interval<T: SampleValueOp<T>>(
+    stream: &Stream<T>,
+    start: TimeStamp,
+    end: TimeStamp,
+    window: Duration,
+) -> Option<Interval> {
+    stream
+        .aligned
+        .iter()
+        .filter(|(interval, _)| interval.millis() > 0 && window.millis() % interval.millis() == 0)
+        .filter(|(interval, segments)| {
+            let slots = segments
+                .values()
+                .flat_map(|segment| {
+                    (0..segment.len() as i64)
+                        .map(move |i| segment.start_ts.millis() + i * interval.millis())
+                })
+                .filter(|ts| *ts >= start.millis() && ts + interval.millis() <= end.millis())
+                .count() as i64;
+            slots == window.millis() / interval.millis()
+        })
+        .map(|(interval, _)| *interval)
+        .max()
+}
+
+/// Compute the window statistics over `(ts, sample)` pairs.
+fn digest<T: SampleValue>(
+    window: Duration,
+    source: SummarySource,
+    samples: impl Iterator<Item = (i64, Sample<T>)>,
+) -> WindowSummary {
+    let mut total = 0;
+    let mut valid = 0;
+    let mut sum = 0.0;
+    let mut min: Option<f64> = None;
+    let mut max: Option<f64> = None;
+    let mut last: Option<(i64, f64)> = None;
+
+    for (ts, sample) in samples {
+        total += 1;
+        if sample.is_err() {
+            continue;
+        }
+        let Some(v) = sample.val().to_f64() else {
+            continue;
+        };
+
+        valid += 1;
+        sum += v;
+        min = Some(min.map_or(v, |m| m.min(v)));
+        max = Some(max.map_or(v, |m| m.max(v)));
+        if last.is_none_or(|(last_ts, _)| ts >= last_ts) {
+            last = Some((ts, v));
+        }
+    }
+
+    WindowSummary {
+        window,
+        source,
+        min,
+        max,
+        mean: (valid > 0).then(|| sum / valid as f64),
+        last: last.map(|(_, v)| v),
+        completeness: if total > 0 {
+            valid as f64 / total as f64
+        } else {
+            0.0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_summary() {
+        let mut raw = RawSeries::new();
+        raw.push(TimeStamp(1000), 4);
+        raw.push_sample(TimeStamp(2000), Sample::Err);
+        raw.push(TimeStamp(3000), -2);
+        raw.push_sample(TimeStamp(4000), Sample::Zero);
+        raw.push(TimeStamp(5000), 10);
+
+        let summary = raw.summary();
+        assert_eq!(
+            summary,
+            SeriesSummary {
+                count: 5,
+                err_count: 1,
+                fake_count: 0,
+                min: Some(-2),
+                max: Some(10),
+                mean: Some(3.0),
+                first_ts: Some(TimeStamp(1000)),
+                last_ts: Some(TimeStamp(5000)),
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            format!(
+                "count=5 err=1 fake=0 min=-2 max=10 mean=3 first={} last={}",
+                TimeStamp(1000),
+                TimeStamp(5000)
+            )
+        );
+
+        let mut aligned = AlignedSeries::new(Interval(10), TimeStamp(100));
+        aligned.push(1.5);
+        aligned.push_sample(Sample::Fake(2.5));
+        aligned.push_sample(Sample::Err);
+        let summary = aligned.summary();
+        assert_eq!(summary.count, 3);
+        assert_eq!((summary.err_count, summary.fake_count), (1, 1));
+        assert_eq!((summary.min, summary.max), (Some(1.5), Some(2.5)));
+        assert_eq!(summary.mean, Some(2.0));
+        assert_eq!(summary.last_ts, Some(TimeStamp(120)));
+
+        let summary = RawSeries::<i64>::new().summary();
+        assert_eq!(summary.count, 0);
+        assert_eq!(summary.mean, None);
+        assert!(summary.to_string().contains("min=- max=- mean=-"));
+    }
+
+    #[test]
+    fn summarize() {
+        let minute = Interval::from_minutes(1);
+        let hour = Interval::from_minutes(60);
+        let now = TimeStamp(24 * hour.millis());
+
+        let mut metric = Metric::<f64>::new("load".to_string());
+
+        // Raw data for the last 10 minutes, one sample every 10 seconds.
+        let mut raw = RawSeries::new();
+        let raw_start = now.millis() - 10 * minute.millis();
+        for i in 0..60 {
+            raw.push(TimeStamp(raw_start + i * 10_000), i as f64);
+        }
+        metric.stream.add_raw_series(raw);
+
+        // One-minute rollups for the last 2 hours, with one missing slot.
+        let mut minutes = AlignedSeries::new(minute, TimeStamp(now.millis() - 2 * hour.millis()));
+        for i in 0..120 {
+            if i == 117 {
+                minutes.push_sample(Sample::Err);
+            } else {
+                minutes.push(i as f64);
+            }
+        }
+        metric
+            .stream
+            .aligned
+            .entry(minute)
+            .or_default()
+            .insert(minutes.start_ts, minutes);
+
+        // Hourly rollups for the whole day, split over two segments.
+        for (start, len) in [(0, 12), (12, 12)] {
+            let mut hours = AlignedSeries::new(hour, TimeStamp(start * hour.millis()));
+            for i in start..start + len {
+                hours.push(100.0 + i as f64);
+            }
+            metric
+                .stream
+                .aligned
+                .entry(hour)
+                .or_default()
+                .insert(hours.start_ts, hours);
+        }
+
+        let summary = metric.summarize(
+            now,
+            &[
+                Interval::from_secs(30),
+                Interval::from_minutes(5),
+                hour,
+                Interval::from_minutes(24 * 60),
+            ],
+        );
+        assert_eq!(summary.total_samples, 60);
+        assert_eq!(summary.staleness, Some(Interval::from_secs(10)));
+
+        // 30s doesn't tile into any rollup, so it comes from raw.
+        let w = &summary.windows[0];
+        assert_eq!(w.source, SummarySource::Raw);
+        assert_eq!((w.min, w.max, w.last), (Some(57.0), Some(59.0), Some(59.0)));
+        assert_eq!(w.mean, Some(58.0));
+        assert_eq!(w.completeness, 1.0);
+
+        // 5m comes from the minutes, including the missing slot.
+        let w = &summary.windows[1];
+        assert_eq!(w.source, SummarySource::Aligned(minute));
+        assert_eq!(
+            (w.min, w.max, w.last),
+            (Some(115.0), Some(119.0), Some(119.0))
+        );
+        assert_eq!(w.mean, Some((115.0 + 116.0 + 118.0 + 119.0) / 4.0));
+        assert_eq!(w.completeness, 0.8);
+
+        // 1h and 24h come from the hours.
+        let w = &summary.windows[2];
+        assert_eq!(w.source, SummarySource::Aligned(hour));
+        assert_eq!(
+            (w.min, w.max, w.last),
+            (Some(123.0), Some(123.0), Some(123.0))
+        );
+
+        let w = &summary.windows[3];
+        assert_eq!(w.source, SummarySource::Aligned(hour));
+        assert_eq!(
+            (w.min, w.max, w.last),
+            (Some(100.0), Some(123.0), Some(123.0))
+        );
+        assert_eq!(w.mean, Some(111.5));
+        assert_eq!(w.completeness, 1.0);
+
+        // An empty metric still summarizes.
+        let summary = Metric::<f64>::new("empty".to_string()).summarize(now, &[hour]);
+        assert_eq!(summary.staleness, None);
+        assert_eq!(summary.windows[0].source, SummarySource::Raw);
+        assert_eq!(summary.windows[0].mean, None);
+        assert_eq!(summary.windows[0].completeness, 0.0);
+    }
+}
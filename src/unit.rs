@@ -0,0 +1,201 @@
+//! Units of measurement for metrics, e.g. "bytes" or "bytes/second".
+//!
+//! A `Unit` is a product of base units over a product of base units, kept in
+//! canonical (sorted, cancelled) form so that equal units compare equal. The
+//! arithmetic helpers mirror what happens to the values: sums and
+//! differences need equal units, quotients and products combine them, and
+//! scaling by a plain number keeps them.
+
+use std::fmt;
+
+/// A unit of measurement. The dimensionless unit is written "1".
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Unit {
+    num: Vec<String>,
+    den: Vec<String>,
+}
+
+/// Errors returned by unit parsing and unit arithmetic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnitError {
+    /// The unit string could not be parsed.
+    Invalid(String),
+
+    /// The operands of a sum or difference have different units.
+    UnitMismatch { left: Unit, right: Unit },
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(unit) => write!(f, "invalid unit {:?}", unit),
+            Self::UnitMismatch { left, right } => {
+                write!(f, "unit mismatch: {} and {}", left, right)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UnitError {}
+
+impl Unit {
+    /// Returns the dimensionless unit.
+    pub fn dimensionless() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if the unit is dimensionless.
+    pub fn is_dimensionless(&self) -> bool {
+        self.num.is_empty() && self.den.is_empty()
+    }
+
+    /// Parse a unit such as "bytes", "bytes/second", "bytes*seconds" or "1".
+    /// At most one "/" is allowed; either side is a "*"-separated product.
+    pub fn parse(unit: &str) -> Result<Self, UnitError> {
+        let invalid = || UnitError::Invalid(unit.to_string());
+
+        let product = |side: &str| -> Result<Vec<String>, UnitError> {
+            let mut terms = vec![];
+            for term in side.split('*').map(str::trim) {
+                if term == "1" {
+                    continue;
+                }
+                if term.is_empty() || !term.chars().all(|c| c.is_ascii_alphabetic() || c == '_') {
+                    return Err(invalid());
+                }
+                terms.push(term.to_string());
+            }
+            Ok(terms)
+        };
+
+        let (num, den) = match unit.split_once('/') {
+            Some((num, den)) if !den.contains('/') => (product(num)?, product(den)?),
+            Some(_) => return Err(invalid()),
+            None => (product(unit)?, vec![]),
+        };
+
+        Ok(Self::canonical(num, den))
+    }
+
+    /// The unit of `self + other`. Both must be equal.
+    pub fn add(&self, other: &Unit) -> Result<Unit, UnitError> {
+        if self != other {
+            return Err(UnitError::UnitMismatch {
+                left: self.clone(),
+                right: other.clone(),
+            });
+        }
+        Ok(self.clone())
+    }
+
+    /// The unit of `self - other`. Both must be equal.
+    pub fn sub(&self, other: &Unit) -> Result<Unit, UnitError> {
+        self.add(other)
+    }
+
+    /// The unit of `self * other`.
+    pub fn mul(&self, other: &Unit) -> Unit {
+        Self::canonical(
+            self.num.iter().chain(other.num.iter()).cloned().collect(),
+            self.den.iter().chain(other.den.iter()).cloned().collect(),
+        )
+    }
+
+    /// The unit of `self / other`.
+    pub fn div(&self, other: &Unit) -> Unit {
+        Self::canonical(
+            self.num.iter().chain(other.den.iter()).cloned().collect(),
+            self.den.iter().chain(other.num.iter()).cloned().collect(),
+        )
+    }
+
+    /// The unit of `self` scaled by a dimensionless factor.
+    pub fn scale(&self) -> Unit {
+        self.clone()
+    }
+
+    /// Sort both sides and cancel terms that appear on both.
+    fn canonical(mut num: Vec<String>, mut den: Vec<String>) -> Self {
+        num.sort();
+        den.sort();
+
+        let mut i = 0;
+        while i < num.len() {
+            match den.iter().position(|d| *d == num[i]) {
+                Some(j) => {
+                    num.remove(i);
+                    den.remove(j);
+                }
+                None => i += 1,
+            }
+        }
+
+        Self { num, den }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.num.is_empty() {
+            write!(f, "1")?;
+        } else {
+            write!(f, "{}", self.num.join("*"))?;
+        }
+        if !self.den.is_empty() {
+            write!(f, "/{}", self.den.join("*"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Unit::parse("bytes").unwrap().to_string(), "bytes");
+        assert_eq!(
+            Unit::parse("bytes / second").unwrap().to_string(),
+            "bytes/second"
+        );
+        assert!(Unit::parse("1").unwrap().is_dimensionless());
+        assert_eq!(Unit::parse("1/second").unwrap().to_string(), "1/second");
+        assert_eq!(Unit::parse("bytes/bytes").unwrap(), Unit::dimensionless());
+        assert_eq!(
+            Unit::parse("seconds*bytes").unwrap(),
+            Unit::parse("bytes*seconds").unwrap()
+        );
+
+        assert!(Unit::parse("").is_err());
+        assert!(Unit::parse("a/b/c").is_err());
+        assert!(Unit::parse("bytes^2").is_err());
+    }
+
+    #[test]
+    fn propagation() {
+        let bytes = Unit::parse("bytes").unwrap();
+        let seconds = Unit::parse("seconds").unwrap();
+
+        assert_eq!(bytes.add(&bytes).unwrap(), bytes);
+        assert_eq!(bytes.scale(), bytes);
+
+        let rate = bytes.div(&seconds);
+        assert_eq!(rate, Unit::parse("bytes/seconds").unwrap());
+        assert_eq!(rate.mul(&seconds), bytes);
+        assert!(bytes.div(&bytes).is_dimensionless());
+
+        assert_eq!(
+            bytes.sub(&seconds),
+            Err(UnitError::UnitMismatch {
+                left: bytes.clone(),
+                right: seconds.clone()
+            })
+        );
+        assert_eq!(
+            rate.add(&bytes).unwrap_err().to_string(),
+            "unit mismatch: bytes/seconds and bytes"
+        );
+    }
+}
@@ -1,17 +1,15 @@
-use crate::base::TimeStamp;
+//! Utility functions
 
-/// Utility functions
+use crate::base::TimeStamp;
 
 /// Returns the current time in UTC as a timestamp in milliseconds.
+#[deprecated(note = "use TimeStamp::now")]
 pub fn utc_now() -> TimeStamp {
     chrono::DateTime::timestamp_millis(&chrono::Utc::now()).into()
 }
 
 /// Returns the given i64 timestamp as a UTC datetime.
+#[deprecated(note = "use TimeStamp::to_utc")]
 pub fn ts_to_utc(ts: impl Into<TimeStamp>) -> chrono::DateTime<chrono::Utc> {
-    chrono::DateTime::from_utc(
-        chrono::NaiveDateTime::from_timestamp_millis(ts.into().into())
-            .unwrap_or(chrono::NaiveDateTime::from_timestamp_millis(0).unwrap()),
-        chrono::Utc,
-    )
+    chrono::DateTime::from_timestamp_millis(ts.into().into()).unwrap_or_default()
 }
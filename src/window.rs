@@ -51,43 +51,65 @@ pub struct WindowIter<'a, T: SampleValue> {
 
     /// Next value
     next: Option<Window>,
+
+    /// The number of windows taken from the back.
+    back_windows: usize,
 }
 
 impl<'a, T: SampleValue> WindowIter<'a, T> {
     /// Create a new window iterator.
     pub fn new(series: &'a RawSeries<T>, window_size: Interval, start_ts: TimeStamp) -> Self {
-        let last_sample_ts = series.values.last().unwrap().0;
-        let mut num_windows =
-            ((last_sample_ts.millis() - start_ts.millis()) / window_size.millis()) + 1;
-
-        if last_sample_ts < start_ts {
-            num_windows = 0;
-        }
+        let num_windows = Self::data_windows(series, window_size, start_ts);
 
-        // TODO: Binary search, set last_index
+        // Samples before `start_ts` are in no window, skip them up front.
+        let last_index = series.values.partition_point(|e| e.0 < start_ts);
 
         Self {
             series,
             window_size,
             start_ts,
             end_ts: None,
-            num_windows: num_windows as usize,
+            num_windows,
             current_window: 0,
-            last_index: 0,
+            last_index,
             next: None,
+            back_windows: 0,
         }
     }
 
+    /// Builder form of `set_end_ts`. Like it, this yields only the windows
+    /// starting before `end_ts`; it used to also yield the window starting
+    /// at `end_ts`.
     pub fn with_end_ts(mut self, end_ts: TimeStamp) -> Self {
-        self.end_ts = Some(end_ts);
-        self.num_windows =
-            (((end_ts.millis() - self.start_ts.millis()) / self.window_size.millis()) + 1) as usize;
-
+        self.set_end_ts(end_ts);
         self
     }
 
+    /// Yield windows starting before `end_ts` only: iteration stops at
+    /// `end_ts` even if samples extend beyond it, and continues with `Empty`
+    /// windows up to it after the last sample.
     pub fn set_end_ts(&mut self, end_ts: TimeStamp) {
+        let span = (end_ts.millis() - self.start_ts.millis()).max(0);
         self.end_ts = Some(end_ts);
+        self.num_windows =
+            ((span + self.window_size.millis() - 1) / self.window_size.millis()) as usize;
+    }
+
+    /// Returns the number of windows the iterator yields in total.
+    pub fn num_windows(&self) -> usize {
+        self.num_windows
+    }
+
+    /// The number of windows up to and including the one holding the last
+    /// sample.
+    fn data_windows(series: &RawSeries<T>, window_size: Interval, start_ts: TimeStamp) -> usize {
+        match series.end_ts() {
+            Some(last_sample_ts) if last_sample_ts >= start_ts => {
+                (((last_sample_ts.millis() - start_ts.millis()) / window_size.millis()) + 1)
+                    as usize
+            }
+            _ => 0,
+        }
     }
 
     pub fn samples(&'a mut self) -> WindowSamples<'a, T> {
@@ -100,7 +122,7 @@ impl<'a, T: SampleValue> Iterator for WindowIter<'a, T> {
 
     /// Returns the next window.
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current_window >= self.num_windows {
+        if self.current_window + self.back_windows >= self.num_windows() {
             self.next = None;
             return None;
         }
@@ -168,6 +190,156 @@ impl<'a, T: SampleValue> Iterator for WindowIter<'a, T> {
     }
 }
 
+impl<T: SampleValue> DoubleEndedIterator for WindowIter<'_, T> {
+    /// Returns the last window not yet returned from either end. Windows from
+    /// the back are found by binary search rather than the forward scan.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let remaining = self
+            .num_windows()
+            .checked_sub(self.current_window + self.back_windows)?;
+        if remaining == 0 {
+            return None;
+        }
+
+        self.back_windows += 1;
+        let window_start_ts = self.start_ts.millis()
+            + ((self.current_window + remaining - 1) as i64 * self.window_size.millis());
+
+        Some(range_window(
+            &self.series.values,
+            window_start_ts,
+            window_start_ts + self.window_size.millis(),
+        ))
+    }
+}
+
+/// The window of `values` with timestamps in `[start_ts, end_ts)`.
+fn range_window<T: SampleValue>(values: &[Element<T>], start_ts: i64, end_ts: i64) -> Window {
+    let start_index = values.partition_point(|e| e.0.millis() < start_ts);
+    let end_index = values.partition_point(|e| e.0.millis() < end_ts);
+
+    if start_index < end_index {
+        Window::Range(start_index, end_index - 1)
+    } else {
+        Window::Empty
+    }
+}
+
+/// An iterator over possibly overlapping windows of a series, see
+/// `RawSeries::sliding_windows`.
+#[derive(Clone)]
+pub struct SlidingWindowIter<'a, T: SampleValue> {
+    series: &'a RawSeries<T>,
+    size: Interval,
+    step: Interval,
+    start_ts: TimeStamp,
+    num_windows: usize,
+    current_window: usize,
+}
+
+impl<'a, T: SampleValue> SlidingWindowIter<'a, T> {
+    /// Create a new sliding window iterator. Fails if `size` or `step` isn't
+    /// positive.
+    pub fn new(
+        series: &'a RawSeries<T>,
+        size: Interval,
+        step: Interval,
+        start_ts: TimeStamp,
+    ) -> anyhow::Result<Self> {
+        if size.millis() <= 0 {
+            anyhow::bail!("window size must be positive");
+        }
+        if step.millis() <= 0 {
+            anyhow::bail!("window step must be positive");
+        }
+
+        let num_windows = match series.end_ts() {
+            Some(last_sample_ts) if last_sample_ts >= start_ts => {
+                ((last_sample_ts.millis() - start_ts.millis()) / step.millis()) + 1
+            }
+            _ => 0,
+        };
+
+        Ok(Self {
+            series,
+            size,
+            step,
+            start_ts,
+            num_windows: num_windows as usize,
+            current_window: 0,
+        })
+    }
+
+    /// Returns the number of windows the iterator yields in total.
+    pub fn num_windows(&self) -> usize {
+        self.num_windows
+    }
+}
+
+impl<T: SampleValue> Iterator for SlidingWindowIter<'_, T> {
+    type Item = Window;
+
+    /// Returns the next window. Unlike `WindowIter`, which resumes its scan
+    /// after the previous window, each window is found by binary search, as
+    /// overlapping windows share samples.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current_window >= self.num_windows {
+            return None;
+        }
+
+        let window_start_ts =
+            self.start_ts.millis() + (self.current_window as i64 * self.step.millis());
+        let window_end_ts = window_start_ts + self.size.millis();
+        self.current_window += 1;
+
+        Some(range_window(
+            &self.series.values,
+            window_start_ts,
+            window_end_ts,
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.num_windows - self.current_window;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Windows over sorted `timestamps`, with the same index ranges `WindowIter`
+/// yields for a `RawSeries` holding them. For series types that don't store
+/// their elements in a single slice.
+pub(crate) fn windows_of(
+    timestamps: impl Iterator<Item = TimeStamp>,
+    end_ts: Option<TimeStamp>,
+    window_size: Interval,
+    start_ts: TimeStamp,
+) -> impl Iterator<Item = Window> {
+    let num_windows = match end_ts {
+        Some(end_ts) if end_ts >= start_ts => {
+            (end_ts.millis() - start_ts.millis()) / window_size.millis() + 1
+        }
+        _ => 0,
+    };
+    let mut timestamps = timestamps.map(|ts| ts.millis()).enumerate().peekable();
+
+    (0..num_windows).map(move |i| {
+        let window_start = start_ts.millis() + i * window_size.millis();
+        let window_end = window_start + window_size.millis();
+
+        while timestamps.next_if(|(_, ts)| *ts < window_start).is_some() {}
+
+        let mut range = None;
+        while let Some((j, _)) = timestamps.next_if(|(_, ts)| *ts < window_end) {
+            range = Some(range.map_or((j, j), |(start, _)| (start, j)));
+        }
+
+        match range {
+            Some((start, end)) => Window::Range(start, end),
+            None => Window::Empty,
+        }
+    })
+}
+
 pub struct WindowSamples<'a, T: SampleValue> {
     iter: &'a mut WindowIter<'a, T>,
 }
@@ -195,6 +367,8 @@ where
     }
 }
 
+/// Aggregates each window with an op. Empty windows are passed to the op as
+/// an empty slice, so each op decides what an empty window yields.
 pub struct WindowAggregates<'a, T: SampleValue> {
     iter: &'a mut WindowSamples<'a, T>,
     f: element::Op<T>,
@@ -207,7 +381,7 @@ where
     type Item = Sample<T>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|w| (self.f)(w))
+        self.iter.next().map(self.f)
     }
 }
 
@@ -217,7 +391,8 @@ mod tests {
 
     use crate::{
         ops::element::{max, mean, min},
-        sample::Sample,
+        sample::{Sample, SampleEquals},
+        AlignedSeries,
     };
 
     use super::*;
@@ -333,8 +508,9 @@ mod tests {
             )
             .collect::<Vec<Window>>();
 
-        // Expect 20 windows
-        assert_eq!(windows.len(), 20);
+        // Expect 19 windows: only windows starting before the end are
+        // yielded, so the window starting at 1:19 is not.
+        assert_eq!(windows.len(), 19);
 
         // Break it into 2 minute windows
         let windows = s
@@ -376,6 +552,277 @@ mod tests {
         assert_every_nth(&windows, 5, Some(1));
     }
 
+    #[test]
+    fn sliding_windows() {
+        let mut s = RawSeries::new();
+        for ts in [0, 3, 5, 9, 10, 11, 24, 30, 31, 47] {
+            s.push(TimeStamp(ts), ts);
+        }
+
+        // With step == size, the windows are the tumbling ones.
+        for (size, start) in [(5, 0), (10, 0), (7, 2), (10, -15), (100, 0)] {
+            let tumbling = s
+                .windows(Interval(size), TimeStamp(start))
+                .collect::<Vec<_>>();
+            let sliding = s
+                .sliding_windows(Interval(size), Interval(size), TimeStamp(start))
+                .unwrap()
+                .collect::<Vec<_>>();
+            assert_eq!(
+                format!("{:?}", sliding),
+                format!("{:?}", tumbling),
+                "size {} start {}",
+                size,
+                start
+            );
+        }
+
+        // Overlapping windows of 10 every 5 share samples.
+        let windows = s
+            .sliding_windows(Interval(10), Interval(5), TimeStamp(0))
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(windows.len(), 10);
+        let ranges = windows
+            .iter()
+            .map(|w| match w {
+                Window::Range(start, end) => Some((*start, *end)),
+                Window::Empty => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            [
+                Some((0, 3)),
+                Some((2, 5)),
+                Some((4, 5)),
+                Some((6, 6)),
+                Some((6, 6)),
+                Some((7, 8)),
+                Some((7, 8)),
+                None,
+                Some((9, 9)),
+                Some((9, 9)),
+            ]
+        );
+
+        // Gaps between windows skip samples.
+        let windows = s
+            .sliding_windows(Interval(2), Interval(10), TimeStamp(0))
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(windows.len(), 5);
+        assert!(matches!(windows[1], Window::Range(4, 5)));
+        assert!(windows[2].is_empty());
+
+        assert_eq!(
+            RawSeries::<i64>::new()
+                .sliding_windows(Interval(10), Interval(5), TimeStamp(0))
+                .unwrap()
+                .count(),
+            0
+        );
+
+        // Empty or backwards windows are rejected.
+        assert!(s
+            .sliding_windows(Interval(0), Interval(5), TimeStamp(0))
+            .is_err());
+        assert!(s
+            .sliding_windows(Interval(10), Interval(-5), TimeStamp(0))
+            .is_err());
+    }
+
+    #[test]
+    fn reverse_windows() {
+        let mut s = RawSeries::new();
+        for ts in [0, 3, 5, 9, 10, 11, 24, 30, 31, 47] {
+            s.push(TimeStamp(ts), ts);
+        }
+
+        for (size, start, end) in [
+            (5, 0, None),
+            (10, 0, None),
+            (7, 2, None),
+            (10, -15, None),
+            (100, 0, None),
+            (10, 0, Some(30)),
+            (10, 0, Some(35)),
+            (10, 0, Some(100)),
+        ] {
+            let windows = || {
+                let mut windows = s.windows(Interval(size), TimeStamp(start));
+                if let Some(end) = end {
+                    windows.set_end_ts(TimeStamp(end));
+                }
+                windows
+            };
+            let mut forward = windows().collect::<Vec<_>>();
+            forward.reverse();
+            let backward = windows().rev().collect::<Vec<_>>();
+            assert_eq!(
+                format!("{:?}", backward),
+                format!("{:?}", forward),
+                "size {} start {} end {:?}",
+                size,
+                start,
+                end
+            );
+        }
+
+        // Windows past the last sample up to an end given by `with_end_ts`.
+        for end in [25, 100] {
+            let windows = || {
+                s.windows(Interval(10), TimeStamp(0))
+                    .with_end_ts(TimeStamp(end))
+            };
+            let mut forward = windows().collect::<Vec<_>>();
+            forward.reverse();
+            let backward = windows().rev().collect::<Vec<_>>();
+            assert_eq!(
+                format!("{:?}", backward),
+                format!("{:?}", forward),
+                "end {}",
+                end
+            );
+        }
+
+        // Both ends meet in the middle without repeating windows.
+        let all = s.windows(Interval(10), TimeStamp(0)).collect::<Vec<_>>();
+        assert_eq!(all.len(), 5);
+        let mut windows = s.windows(Interval(10), TimeStamp(0));
+        let (mut front, mut back) = (vec![], vec![]);
+        while let Some(w) = windows.next() {
+            front.push(w);
+            match windows.next_back() {
+                Some(w) => back.push(w),
+                None => break,
+            }
+        }
+        front.extend(back.into_iter().rev());
+        assert_eq!(format!("{:?}", front), format!("{:?}", all));
+        assert!(windows.next().is_none());
+        assert!(windows.next_back().is_none());
+    }
+
+    #[test]
+    fn set_end_ts() {
+        let mut s = RawSeries::new();
+        for ts in [0, 3, 12, 25, 31, 47] {
+            s.push(TimeStamp(ts), ts);
+        }
+
+        // End before the last sample: iteration stops at the end.
+        let mut windows = s.windows(Interval(10), TimeStamp(0));
+        windows.set_end_ts(TimeStamp(30));
+        assert_eq!(windows.num_windows(), 3);
+        let got = windows.collect::<Vec<_>>();
+        assert_eq!(got.len(), 3);
+        assert!(matches!(got[2], Window::Range(3, 3)));
+
+        // Ends between slot starts round up to a whole window.
+        let mut windows = s.windows(Interval(10), TimeStamp(0));
+        windows.set_end_ts(TimeStamp(31));
+        assert_eq!(windows.num_windows(), 4);
+        assert_eq!(windows.count(), 4);
+
+        // End after the last sample: trailing windows are empty.
+        let mut windows = s.windows(Interval(10), TimeStamp(0));
+        windows.set_end_ts(TimeStamp(80));
+        assert_eq!(windows.num_windows(), 8);
+        let got = windows.collect::<Vec<_>>();
+        assert_eq!(got.len(), 8);
+        assert!(matches!(got[4], Window::Range(5, 5)));
+        assert!(got[5..].iter().all(|w| w.is_empty()));
+
+        let aligned = AlignedSeries::from_raw_series(
+            &s,
+            Interval(10),
+            TimeStamp(0),
+            Some(TimeStamp(80)),
+            max,
+        )
+        .unwrap();
+        assert_eq!(aligned.len(), 8);
+        assert!(aligned.values[5..].iter().all(|s| s.is_err()));
+
+        let empty = RawSeries::<i64>::new();
+        let mut windows = empty.windows(Interval(10), TimeStamp(0));
+        windows.set_end_ts(TimeStamp(20));
+        assert_eq!(windows.filter(|w| w.is_empty()).count(), 2);
+
+        // `with_end_ts` counts windows the same way.
+        for end in [0, 25, 30, 31, 47, 80] {
+            let mut set = s.windows(Interval(10), TimeStamp(0));
+            set.set_end_ts(TimeStamp(end));
+            let with = s
+                .windows(Interval(10), TimeStamp(0))
+                .with_end_ts(TimeStamp(end));
+            assert_eq!(with.num_windows(), set.num_windows(), "end {}", end);
+            assert_eq!(
+                format!("{:?}", with.collect::<Vec<_>>()),
+                format!("{:?}", set.collect::<Vec<_>>()),
+                "end {}",
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn empty_series() {
+        let empty = RawSeries::<i64>::new();
+        let mut windows = empty.windows(Interval(5), TimeStamp(0));
+        assert_eq!(windows.num_windows(), 0);
+        assert!(windows.next().is_none());
+        assert_eq!(empty.windows(Interval(5), TimeStamp(0)).count(), 0);
+        assert_eq!(
+            empty
+                .windows(Interval(5), TimeStamp(0))
+                .samples()
+                .aggregate(max)
+                .count(),
+            0
+        );
+
+        let aligned =
+            AlignedSeries::from_raw_series(&empty, Interval(5), TimeStamp(0), None, max).unwrap();
+        assert!(aligned.is_empty());
+    }
+
+    #[test]
+    fn empty_windows() {
+        let mut s = RawSeries::new();
+        for ts in [0, 5, 42, 44] {
+            s.push(TimeStamp(ts), ts);
+        }
+
+        // Integer mean would divide by zero on an empty window.
+        let means = s
+            .windows(Interval(10), TimeStamp(0))
+            .samples()
+            .aggregate(mean)
+            .collect::<Vec<_>>();
+        assert_eq!(means.len(), 5);
+        assert!(means[0].equals(&Sample::Point(2)));
+        assert!(means[1..4].iter().all(|s| s.is_err()));
+        assert!(means[4].equals(&Sample::Point(43)));
+
+        let aligned =
+            AlignedSeries::from_raw_series(&s, Interval(10), TimeStamp(0), None, mean).unwrap();
+        assert_eq!(aligned.len(), 5);
+        assert!(aligned.values[2].is_err());
+
+        // Ops with a value for empty windows still get to produce it.
+        let counts = AlignedSeries::from_raw_series(
+            &s,
+            Interval(10),
+            TimeStamp(0),
+            None,
+            crate::ops::element::count,
+        )
+        .unwrap();
+        assert!(counts.values[2].equals(&Sample::Point(0)));
+    }
+
     #[test]
     fn aggregation() {
         let mut s = RawSeries::new();
@@ -0,0 +1,35 @@
+// Downstream-style usage of the crate with nothing but the prelude.
+use sup::prelude::*;
+
+#[test]
+fn prelude_demo() {
+    let mut series = RawSeries::new();
+    for i in 0..20 {
+        series.push(TimeStamp(1_000 + i * 10), 10 + i);
+    }
+
+    let aligned = AlignedSeries::from_raw_series(
+        &series,
+        Interval(20),
+        series.get(0).unwrap().0.align_millis(100),
+        None,
+        ops::element::youngest,
+    )
+    .unwrap();
+    assert_eq!(aligned.len(), 10);
+
+    let deltas = aligned.sliding_aggregate(2, ops::sample::delta).unwrap();
+    assert_eq!(deltas.len(), aligned.len());
+    assert!(deltas.values[1].equals(&Sample::point(2)));
+
+    let mut metric = Metric::new("cpu_usage".to_string());
+    metric.add_tag(TagName("host".to_string()), TagValue::Int(1));
+    for i in 0..10 {
+        metric.push_raw(TimeStamp(i * 300), i as f32);
+    }
+    assert_eq!(metric.stream.raw().first().unwrap().len(), 10);
+
+    let _: Duration = Interval::from_secs(1);
+    let _: Element<i64> = (0, Sample::Point(1)).into();
+    let _ = ops::element::mean::<f64>;
+}
@@ -0,0 +1,140 @@
+// JSON round trips of the series types. Run with `--features serde`.
+#![cfg(feature = "serde")]
+
+use sup::prelude::*;
+
+#[test]
+fn sample_json_shape() {
+    let samples: [Sample<f64>; 4] = [
+        Sample::Point(3.0),
+        Sample::Zero,
+        Sample::Err,
+        Sample::Fake(1.2),
+    ];
+    let json = serde_json::to_string(&samples).unwrap();
+    assert_eq!(
+        json,
+        r#"[{"Point":3.0},{"Zero":null},{"Err":null},{"Fake":1.2}]"#
+    );
+
+    let decoded: Vec<Sample<f64>> = serde_json::from_str(&json).unwrap();
+    for (a, b) in decoded.iter().zip(samples.iter()) {
+        assert!(a.approx_eq(b, 0.0));
+    }
+}
+
+#[test]
+fn raw_series_round_trip() {
+    let mut series = RawSeries::new();
+    series.push(TimeStamp(1_000), 1.5);
+    series.push_sample(TimeStamp(2_000), Sample::Zero);
+    series.push_sample(TimeStamp(3_000), Sample::Err);
+    series.push_sample(TimeStamp(4_000), Sample::Fake(-0.25));
+
+    let json = serde_json::to_string(&series).unwrap();
+    let decoded: RawSeries<f64> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(decoded.len(), series.len());
+    for (a, b) in decoded.iter().zip(series.iter()) {
+        assert_eq!(a.0, b.0);
+        assert!(a.1.approx_eq(&b.1, 0.0));
+    }
+}
+
+#[test]
+fn aligned_series_round_trip() {
+    let mut series = AlignedSeries::new(Interval(500), TimeStamp(1_000));
+    series.push(3i64);
+    series.push_sample(Sample::Err);
+    series.push_sample(Sample::Fake(5));
+
+    let json = serde_json::to_string(&series).unwrap();
+    assert_eq!(
+        json,
+        r#"{"start_ts":1000,"interval":"500ms","values":[{"Point":3},{"Err":null},{"Fake":5}]}"#
+    );
+
+    let decoded: AlignedSeries<i64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded.start_ts, series.start_ts);
+    assert_eq!(decoded.interval, series.interval);
+    for (a, b) in decoded.values.iter().zip(series.values.iter()) {
+        assert!(a.approx_eq(b, 0.0));
+    }
+
+    // Intervals written as plain milliseconds still decode.
+    let decoded: AlignedSeries<i64> =
+        serde_json::from_str(r#"{"start_ts":1000,"interval":60000,"values":[]}"#).unwrap();
+    assert_eq!(decoded.interval, Interval::from_minutes(1));
+    assert_eq!(serde_json::to_string(&decoded.interval).unwrap(), r#""1m""#);
+}
+
+#[test]
+fn raw_series_compact_i64() {
+    let mut series = RawSeries::new();
+    series.push(TimeStamp(1_000), 3i64);
+    series.push_sample(TimeStamp(2_000), Sample::Zero);
+    series.push_sample(TimeStamp(2_000), Sample::Err);
+    series.push_sample(TimeStamp(3_000), Sample::Fake(-4));
+
+    let json = serde_json::to_string(&series).unwrap();
+    assert_eq!(
+        json,
+        r#"[[1000,3],[2000,{"Zero":null}],[2000,{"Err":null}],[3000,{"Fake":-4}]]"#
+    );
+
+    let decoded: RawSeries<i64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded.len(), series.len());
+    for (a, b) in decoded.iter().zip(series.iter()) {
+        assert_eq!(a.0, b.0);
+        assert!(a.1.approx_eq(&b.1, 0.0));
+    }
+
+    // Integers are accepted for float series.
+    let decoded: RawSeries<f64> = serde_json::from_str("[[1000,3],[2000,2.5]]").unwrap();
+    assert_eq!(decoded.last_val(), 2.5);
+}
+
+#[test]
+fn raw_series_rejects_out_of_order() {
+    let err = serde_json::from_str::<RawSeries<i64>>("[[2000,1],[1000,2]]").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("element 1: timestamp 1000 is older than last sample 2000"),
+        "{}",
+        err
+    );
+
+    assert!(serde_json::from_str::<RawSeries<i64>>(r#"[[1000,"x"]]"#).is_err());
+}
+
+#[test]
+fn metric_summary_json_shape() {
+    let mut metric = Metric::new("load".to_string());
+    for i in 0..6 {
+        metric.push_raw(TimeStamp(i * 10_000), i as f64);
+    }
+
+    let summary = metric.summarize(
+        TimeStamp(60_000),
+        &[Interval::from_secs(30), Interval::from_minutes(60)],
+    );
+    let json = serde_json::to_string(&summary).unwrap();
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"now":60000,"staleness":"10s","total_samples":6,"windows":["#,
+            r#"{"window":"30s","source":"Raw","min":3.0,"max":5.0,"mean":4.0,"last":5.0,"completeness":1.0},"#,
+            r#"{"window":"1h","source":"Raw","min":0.0,"max":5.0,"mean":2.5,"last":5.0,"completeness":1.0}]}"#
+        )
+    );
+
+    // The digest doesn't grow with the history it summarizes.
+    for i in 6..10_000 {
+        metric.push_raw(TimeStamp(i * 10_000), i as f64);
+    }
+    let summary = metric.summarize(
+        TimeStamp(100_000_000),
+        &[Interval::from_secs(30), Interval::from_minutes(60)],
+    );
+    assert!(serde_json::to_string(&summary).unwrap().len() < 2 * json.len());
+}